@@ -472,12 +472,19 @@ fn collect_assets_rec(dir: &Dir, assets: &mut Vec<Asset>) {
 /// Otherwise the content type is determined by the text after the last dot in the file name,
 /// and the encoding is `ContentEncoding::Identity`.
 fn content_type_and_encoding(asset_path: &Path) -> (ContentType, ContentEncoding) {
+    try_content_type_and_encoding(asset_path).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Same as [content_type_and_encoding], but returns an error for unsupported files.
+fn try_content_type_and_encoding(
+    asset_path: &Path,
+) -> Result<(ContentType, ContentEncoding), String> {
     if let Some((content_type, content_encoding)) = KNOWN_FILES.get(asset_path) {
-        return (*content_type, *content_encoding);
+        return Ok((*content_type, *content_encoding));
     }
     let extension = asset_path
         .extension()
-        .unwrap_or_else(|| panic!("Unsupported file without extension: {:?}", asset_path))
+        .ok_or_else(|| format!("Unsupported file without extension: {:?}", asset_path))?
         .to_str()
         .unwrap();
     let (extension, encoding) = if extension == "gz" {
@@ -503,13 +510,37 @@ fn content_type_and_encoding(asset_path: &Path) -> (ContentType, ContentEncoding
         "svg" => ContentType::SVG,
         "webp" => ContentType::WEBP,
         "woff2" => ContentType::WOFF2,
-        ext => panic!(
-            "Unknown asset type '{}' for asset '{}'",
-            ext,
-            asset_path.display()
-        ),
+        ext => {
+            return Err(format!(
+                "Unknown asset type '{}' for asset '{}'",
+                ext,
+                asset_path.display()
+            ))
+        }
     };
-    (content_type, encoding)
+    Ok((content_type, encoding))
+}
+
+/// Returns the assets of a single file, e.g. one uploaded to the canister at runtime, like
+/// [collect_assets] does for the files of a directory. The file path must be relative.
+/// Returns an error if the path is invalid or the type of the file is not supported.
+pub fn file_assets(file_path: &str, content: Vec<u8>) -> Result<Vec<Asset>, String> {
+    if file_path
+        .split('/')
+        .any(|element| element.is_empty() || element == "." || element == "..")
+    {
+        return Err(format!("Invalid asset path '{}'", file_path));
+    }
+    let (content_type, encoding) = try_content_type_and_encoding(Path::new(file_path))?;
+    Ok(filepath_to_urlpaths(file_path.to_string())
+        .into_iter()
+        .map(|url_path| Asset {
+            url_path,
+            content: content.clone(),
+            encoding,
+            content_type,
+        })
+        .collect())
 }
 
 /// Returns the URL paths for a given asset filepath. For instance:
@@ -736,4 +767,29 @@ fn should_return_correct_extension() {
             (expected_extension, expected_encoding)
         );
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn should_return_file_assets() {
+    let assets = file_assets("foo/index.html", b"<html></html>".to_vec()).unwrap();
+    let mut url_paths: Vec<&str> = assets.iter().map(|a| a.url_path.as_str()).collect();
+    url_paths.sort();
+    assert_eq!(url_paths, vec!["/foo", "/foo/", "/foo/index.html"]);
+    assert!(assets.iter().all(|a| a.content_type == ContentType::HTML));
+
+    let assets = file_assets("app.js.gz", vec![1, 2, 3]).unwrap();
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].url_path, "/app.js");
+    assert_eq!(assets[0].encoding, ContentEncoding::GZip);
+
+    for path in [
+        "",
+        "/index.html",
+        "foo//app.js",
+        "../app.js",
+        "app",
+        "app.exe",
+    ] {
+        assert!(file_assets(path, vec![]).is_err(), "{}", path);
+    }
+}
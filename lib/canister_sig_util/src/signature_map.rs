@@ -13,7 +13,7 @@ const MINUTE_NS: u64 = 60 * 1_000_000_000;
 
 // The expiration used for signatures.
 #[allow(clippy::identity_op)]
-const SIGNATURE_EXPIRATION_PERIOD_NS: u64 = 1 * MINUTE_NS;
+pub const SIGNATURE_EXPIRATION_PERIOD_NS: u64 = 1 * MINUTE_NS;
 const MAX_SIGS_TO_PRUNE: usize = 50;
 pub const LABEL_SIG: &[u8] = b"sig";
 #[derive(Default)]
@@ -168,6 +168,12 @@ impl SignatureMap {
         self.put(seed, message_hash, expires_at);
     }
 
+    /// Adds a signature that expires at the given time, e.g. to restore the signatures after an upgrade.
+    /// Unlike [add_signature] this does not prune expired signatures.
+    pub fn restore_signature(&mut self, seed: &[u8], message_hash: Hash, expires_at: u64) {
+        self.put(seed, message_hash, expires_at);
+    }
+
     pub fn len(&self) -> usize {
         self.expiration_queue.len()
     }
@@ -37,6 +37,8 @@ pub struct GetCredentialRequest {
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
 pub struct PreparedCredentialData {
     pub prepared_context: Option<ByteBuf>,
+    /// Expiration (`exp`) of the prepared VC in seconds since the epoch, if reported by the issuer
+    pub expiration_timestamp_s: Option<u32>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
@@ -104,13 +106,63 @@ pub enum ManifestResponse {
     Err(String),
 }
 
+/// The credential types an issuer issues, for relying parties to discover its capabilities
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
-pub struct ManifestData {}
+pub struct ManifestData {
+    pub credential_types: Vec<CredentialTypeManifest>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct CredentialTypeManifest {
+    /// The `credential_type` to use in the credential spec
+    pub credential_type: String,
+    pub name: String,
+    pub description: String,
+    /// The spec arguments accepted for the credential type
+    pub arguments: Vec<ManifestArgument>,
+    /// Names of the claims of the holder that can be disclosed in the VC
+    pub disclosable_claims: Vec<String>,
+    pub lifetime: ManifestLifetime,
+    /// Formats the VC can be issued in
+    pub formats: Vec<String>,
+    /// BCP-47 language tags of the consent messages
+    pub consent_languages: Vec<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct ManifestArgument {
+    pub name: String,
+    pub value_type: ArgumentType,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum ArgumentType {
+    String,
+    Int,
+}
+
+/// Bounds of the lifetime of the VCs in seconds
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct ManifestLifetime {
+    pub min_seconds: u64,
+    pub max_seconds: u64,
+    pub default_seconds: u64,
+}
+
+/// A standard supported by a canister as listed by ICRC-10
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc10SupportedStandard {
+    pub name: String,
+    pub url: String,
+}
 
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
 pub struct Icrc21VcConsentMessageRequest {
     pub credential_spec: CredentialSpec,
     pub preferences: Icrc21ConsentPreferences,
+    /// The ID alias of the holder, if the caller has it, so that the issuer can tell the holder
+    /// about its credential
+    pub signed_id_alias: Option<SignedIdAlias>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
@@ -127,6 +179,7 @@ pub struct Icrc21ErrorInfo {
 pub enum Icrc21Error {
     UnsupportedCanisterCall(Icrc21ErrorInfo),
     ConsentMessageUnavailable(Icrc21ErrorInfo),
+    InsufficientPayment(Icrc21ErrorInfo),
     GenericError {
         error_code: Nat,
         description: String,
@@ -139,6 +192,87 @@ pub struct Icrc21ConsentInfo {
     pub language: String,
 }
 
+/// Structured consent messages as specified by ICRC-21
+/// (cf. https://github.com/dfinity/wg-identity-authentication/blob/main/topics/ICRC-21/icrc_21_consent_msg.md)
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21ConsentMessageMetadata {
+    pub language: String,
+    pub utc_offset_minutes: Option<i16>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum Icrc21DeviceSpec {
+    GenericDisplay,
+    LineDisplay {
+        characters_per_line: u16,
+        lines_per_page: u16,
+    },
+    FieldsDisplay,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21ConsentMessageSpec {
+    pub metadata: Icrc21ConsentMessageMetadata,
+    pub device_spec: Option<Icrc21DeviceSpec>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21ConsentMessageRequest {
+    pub method: String,
+    pub arg: ByteBuf,
+    pub user_preferences: Icrc21ConsentMessageSpec,
+}
+
+/// Consent message request for a VC that lets the wallet choose the display mode.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21VcStructuredConsentMessageRequest {
+    pub credential_spec: CredentialSpec,
+    pub user_preferences: Icrc21ConsentMessageSpec,
+    /// The ID alias of the holder, as in `Icrc21VcConsentMessageRequest`
+    pub signed_id_alias: Option<SignedIdAlias>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21LineDisplayPage {
+    pub lines: Vec<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum Icrc21Value {
+    TokenAmount {
+        decimals: u8,
+        amount: u64,
+        symbol: String,
+    },
+    TimestampSeconds {
+        amount: u64,
+    },
+    DurationSeconds {
+        amount: u64,
+    },
+    Text {
+        content: String,
+    },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum Icrc21ConsentMessage {
+    GenericDisplayMessage(String),
+    LineDisplayMessage {
+        pages: Vec<Icrc21LineDisplayPage>,
+    },
+    FieldsDisplayMessage {
+        intent: String,
+        fields: Vec<(String, Icrc21Value)>,
+    },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct Icrc21ConsentMessageInfo {
+    pub consent_message: Icrc21ConsentMessage,
+    pub metadata: Icrc21ConsentMessageMetadata,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
 pub struct DerivationOriginRequest {
     pub frontend_hostname: String,
@@ -160,6 +294,9 @@ pub enum IssuerError {
     UnauthorizedSubject(String),
     SignatureNotFound(String),
     UnsupportedCredentialSpec(String),
+    UnsupportedOrigin(String),
+    InvalidAsset(String),
+    Internal(String),
 }
 
 #[cfg(test)]
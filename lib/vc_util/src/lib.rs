@@ -22,6 +22,10 @@ use sha2::{Digest, Sha256};
 use std::ops::{Add, Deref, DerefMut};
 
 pub mod issuer_api;
+pub mod sd_jwt;
+pub mod status_list;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 pub const II_CREDENTIAL_URL_PREFIX: &str = "data:text/plain;charset=UTF-8,";
 pub const II_ISSUER_URL: &str = "https://identity.ic0.app/";
@@ -67,7 +71,7 @@ pub fn vc_signing_input(
     credential_jwt: &str,
     canister_sig_pk: &CanisterSigPublicKey,
 ) -> Result<Vec<u8>, String> {
-    let encoder = jws_encoder(credential_jwt, canister_sig_pk)?;
+    let encoder = jws_encoder(credential_jwt, canister_sig_pk, None)?;
     Ok(encoder.signing_input().to_vec())
 }
 
@@ -94,7 +98,7 @@ pub fn vc_jwt_to_jws(
     canister_sig_pk: &CanisterSigPublicKey,
     sig: &[u8],
 ) -> Result<String, String> {
-    let encoder = jws_encoder(credential_jwt, canister_sig_pk)?;
+    let encoder = jws_encoder(credential_jwt, canister_sig_pk, None)?;
     Ok(encoder.into_jws(sig))
 }
 
@@ -166,17 +170,12 @@ pub fn get_verified_id_alias_from_jws(
     root_pk_raw: &[u8],
     current_time_ns: u128,
 ) -> Result<AliasTuple, CredentialVerificationError> {
-    let claims = verify_credential_jws_with_canister_id(
+    let alias_tuple = get_verified_id_alias_from_jws_for_any_subject(
         credential_jws,
         signing_canister_id,
         root_pk_raw,
         current_time_ns,
-    )
-    .map_err(CredentialVerificationError::InvalidJws)?;
-    validate_claim("iss", II_ISSUER_URL, claims.iss())
-        .map_err(CredentialVerificationError::InvalidClaims)?;
-    let alias_tuple =
-        extract_id_alias(&claims).map_err(CredentialVerificationError::InvalidClaims)?;
+    )?;
     if *expected_vc_subject != alias_tuple.id_dapp {
         return Err(CredentialVerificationError::InvalidClaims(
             inconsistent_jwt_claims("unexpected vc subject"),
@@ -185,6 +184,26 @@ pub fn get_verified_id_alias_from_jws(
     Ok(alias_tuple)
 }
 
+/// Verifies the specified JWS id_alias credential like `get_verified_id_alias_from_jws`, but
+/// returns the alias tuple of whichever subject the credential was issued for.
+pub fn get_verified_id_alias_from_jws_for_any_subject(
+    credential_jws: &str,
+    signing_canister_id: &Principal,
+    root_pk_raw: &[u8],
+    current_time_ns: u128,
+) -> Result<AliasTuple, CredentialVerificationError> {
+    let claims = verify_credential_jws_with_canister_id(
+        credential_jws,
+        signing_canister_id,
+        root_pk_raw,
+        current_time_ns,
+    )
+    .map_err(CredentialVerificationError::InvalidJws)?;
+    validate_claim("iss", II_ISSUER_URL, claims.iss())
+        .map_err(CredentialVerificationError::InvalidClaims)?;
+    extract_id_alias(&claims).map_err(CredentialVerificationError::InvalidClaims)
+}
+
 /// Verifies the specified JWS credential cryptographically and checks that the signature was
 /// created by the provided canister.
 /// DOES NOT perform semantic validation of the claims in the credential.
@@ -537,9 +556,11 @@ pub fn validate_claims_match_spec(
     Ok(())
 }
 
+/// Returns the JWK of the DER-encoded canister signature public key, as put into the JWS header
+/// of the VCs.
 // Per https://datatracker.ietf.org/doc/html/rfc7518#section-6.4,
 // JwkParamsOct are for symmetric keys or another key whose value is a single octet sequence.
-fn canister_sig_pk_jwk(canister_sig_pk_der: &[u8]) -> Result<Jwk, String> {
+pub fn canister_sig_pk_jwk(canister_sig_pk_der: &[u8]) -> Result<Jwk, String> {
     let mut cspk_jwk = Jwk::new(JwkType::Oct);
     cspk_jwk.set_alg("IcCs");
     cspk_jwk
@@ -553,9 +574,13 @@ fn canister_sig_pk_jwk(canister_sig_pk_der: &[u8]) -> Result<Jwk, String> {
 fn jws_encoder<'a>(
     credential_jwt: &'a str,
     canister_sig_pk: &CanisterSigPublicKey,
+    typ: Option<&str>,
 ) -> Result<CompactJwsEncoder<'a>, String> {
     let mut header: JwsHeader = JwsHeader::new();
     header.set_alg(JwsAlgorithm::IcCs);
+    if let Some(typ) = typ {
+        header.set_typ(typ);
+    }
     let kid = did_for_principal(canister_sig_pk.canister_id);
     let jwk = canister_sig_pk_jwk(&canister_sig_pk.to_der())?;
     header.set_kid(kid);
@@ -617,10 +642,10 @@ pub fn get_canister_sig_pk_raw(
 mod tests {
     use super::*;
     use crate::issuer_api::ArgumentValue;
+    use crate::test_utils::create_verifiable_presentation_jwt_for_test;
     use assert_matches::assert_matches;
     use canister_sig_util::{extract_raw_root_pk_from_der, IC_ROOT_PK_DER_PREFIX};
     use identity_core::common::Url;
-    use identity_credential::presentation::{JwtPresentationOptions, PresentationBuilder};
     use std::collections::HashMap;
 
     const TEST_IC_ROOT_PK_B64URL: &str = "MIGCMB0GDSsGAQQBgtx8BQMBAgEGDCsGAQQBgtx8BQMCAQNhAK32VjilMFayIiyRuyRXsCdLypUZilrL2t_n_XIXjwab3qjZnpR52Ah6Job8gb88SxH-J1Vw1IHxaY951Giv4OV6zB4pj4tpeY2nqJG77Blwk-xfR1kJkj1Iv-1oQ9vtHw";
@@ -689,37 +714,6 @@ mod tests {
         claims
     }
 
-    pub(crate) fn create_verifiable_presentation_jwt_for_test(
-        holder: Principal,
-        vcs_jws: Vec<String>,
-    ) -> Result<String, String> {
-        let holder_url = Url::parse(did_for_principal(holder)).map_err(|_| "Invalid holder")?;
-        let mut builder = PresentationBuilder::new(holder_url, Default::default());
-        for vc in vcs_jws {
-            builder = builder.credential(Jwt::from(vc));
-        }
-        let presentation: Presentation<Jwt> = builder
-            .build()
-            .map_err(|_| "failed building presentation")?;
-        presentation_to_compact_jwt(&presentation)
-    }
-
-    fn presentation_to_compact_jwt(presentation: &Presentation<Jwt>) -> Result<String, String> {
-        let mut header: JwsHeader = JwsHeader::new();
-        header.set_typ("JWT");
-        header.set_alg(JwsAlgorithm::NONE);
-        let vp_jwt = presentation
-            .serialize_jwt(&JwtPresentationOptions {
-                expiration_date: None,
-                issuance_date: None,
-                audience: None,
-            })
-            .map_err(|_| "failed serializing presentation")?;
-        let encoder: CompactJwsEncoder = CompactJwsEncoder::new(vp_jwt.as_ref(), &header)
-            .map_err(|_| "internal error: JWS encoder failed")?;
-        Ok(encoder.into_jws(&[]))
-    }
-
     #[test]
     fn should_compute_domain_separated_signing_input_hash() {
         let signing_input = b"some bytes to sign";
@@ -0,0 +1,301 @@
+//! Selective disclosure JWTs (SD-JWT, cf. https://datatracker.ietf.org/doc/draft-ietf-oauth-selective-disclosure-jwt/)
+//! for credentials signed with canister signatures.
+//!
+//! The issuer JWT carries the digests of the salted disclosures instead of the selectively
+//! disclosable claims and is signed like any other VC, but with the media type `vc+sd-jwt` as
+//! `typ` of the JWS header (cf. `sd_jwt_signing_input()` and `sd_jwt_issuer_jws()`). The
+//! disclosures are appended to the resulting JWS: `<issuer JWS>~<disclosure>~...~<disclosure>~`.
+//! Key binding JWTs are not supported.
+use crate::{jws_encoder, verify_credential_jws_with_canister_id};
+use candid::Principal;
+use canister_sig_util::CanisterSigPublicKey;
+use identity_jose::jws::SignatureVerificationError;
+use identity_jose::jwu::{decode_b64, encode_b64};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Claim with the digests of the disclosures
+pub const SD_CLAIM: &str = "_sd";
+/// Claim with the hash algorithm of the digests
+pub const SD_ALG_CLAIM: &str = "_sd_alg";
+/// The only supported hash algorithm
+pub const SD_ALG: &str = "sha-256";
+/// The `typ` of the header of the issuer JWS
+pub const SD_JWT_TYP: &str = "vc+sd-jwt";
+const SEPARATOR: char = '~';
+
+#[derive(Debug)]
+pub enum SdJwtError {
+    Malformed(String),
+    InvalidJws(SignatureVerificationError),
+    InvalidDisclosure(String),
+}
+
+/// Returns the effective bytes that will be signed when computing a canister signature for the
+/// issuer JWT of an SD-JWT, like `vc_signing_input()` but with `typ` set to `vc+sd-jwt`.
+pub fn sd_jwt_signing_input(
+    issuer_jwt: &str,
+    canister_sig_pk: &CanisterSigPublicKey,
+) -> Result<Vec<u8>, String> {
+    let encoder = jws_encoder(issuer_jwt, canister_sig_pk, Some(SD_JWT_TYP))?;
+    Ok(encoder.signing_input().to_vec())
+}
+
+/// Constructs the issuer JWS of an SD-JWT like `vc_jwt_to_jws()`, but with `typ` set to
+/// `vc+sd-jwt`. The given signature should be created over the bytes returned by
+/// `sd_jwt_signing_input()`.
+/// Note: the validity of the signature is not checked.
+pub fn sd_jwt_issuer_jws(
+    issuer_jwt: &str,
+    canister_sig_pk: &CanisterSigPublicKey,
+    sig: &[u8],
+) -> Result<String, String> {
+    let encoder = jws_encoder(issuer_jwt, canister_sig_pk, Some(SD_JWT_TYP))?;
+    Ok(encoder.into_jws(sig))
+}
+
+/// Returns the disclosure of the claim `name` with the given value, salted with `salt`.
+/// The salt should consist of at least 16 random bytes.
+pub fn create_disclosure(salt: &[u8], name: &str, value: &Value) -> String {
+    encode_b64(json!([encode_b64(salt), name, value]).to_string())
+}
+
+/// Returns the digest of the disclosure that goes into the issuer JWT.
+pub fn disclosure_digest(disclosure: &str) -> String {
+    encode_b64(Sha256::digest(disclosure.as_bytes()))
+}
+
+/// Adds the digests of the given disclosures to the claims of the issuer JWT.
+/// The digests are sorted, so that their order does not reveal the order of the claims.
+pub fn add_disclosure_digests(claims: &mut Map<String, Value>, disclosures: &[String]) {
+    let mut digests: Vec<String> = disclosures
+        .iter()
+        .map(|disclosure| disclosure_digest(disclosure))
+        .collect();
+    digests.sort();
+    claims.insert(SD_CLAIM.to_string(), json!(digests));
+    claims.insert(SD_ALG_CLAIM.to_string(), json!(SD_ALG));
+}
+
+/// Combines the issuer JWS and the disclosures into an SD-JWT.
+pub fn sd_jwt_from_jws(issuer_jws: &str, disclosures: &[String]) -> String {
+    let mut sd_jwt = issuer_jws.to_string();
+    for disclosure in disclosures {
+        sd_jwt.push(SEPARATOR);
+        sd_jwt.push_str(disclosure);
+    }
+    sd_jwt.push(SEPARATOR);
+    sd_jwt
+}
+
+/// Splits an SD-JWT into the issuer JWS and the disclosures.
+pub fn split_sd_jwt(sd_jwt: &str) -> Result<(&str, Vec<&str>), SdJwtError> {
+    let Some(without_key_binding) = sd_jwt.strip_suffix(SEPARATOR) else {
+        return Err(SdJwtError::Malformed(
+            "SD-JWT must end with a separator, key binding JWTs are not supported".to_string(),
+        ));
+    };
+    let mut parts = without_key_binding.split(SEPARATOR);
+    let issuer_jws = parts
+        .next()
+        .filter(|jws| !jws.is_empty())
+        .ok_or(SdJwtError::Malformed("missing issuer JWS".to_string()))?;
+    let disclosures: Vec<&str> = parts.collect();
+    if disclosures.iter().any(|disclosure| disclosure.is_empty()) {
+        return Err(SdJwtError::Malformed("empty disclosure".to_string()));
+    }
+    Ok((issuer_jws, disclosures))
+}
+
+/// Keeps only the disclosures of the claims with the given names, e.g. for the holder to
+/// present an SD-JWT to a relying party.
+pub fn select_disclosures(sd_jwt: &str, names: &[&str]) -> Result<String, SdJwtError> {
+    let (issuer_jws, disclosures) = split_sd_jwt(sd_jwt)?;
+    let mut selected = Vec::new();
+    for disclosure in disclosures {
+        let (name, _) = decode_disclosure(disclosure)?;
+        if names.contains(&name.as_str()) {
+            selected.push(disclosure.to_string());
+        }
+    }
+    Ok(sd_jwt_from_jws(issuer_jws, &selected))
+}
+
+/// Checks the disclosures against the digests in the claims of the issuer JWT and returns the
+/// claims with the disclosed claims added and the digests removed.
+pub fn process_disclosures(
+    mut claims: Map<String, Value>,
+    disclosures: &[&str],
+) -> Result<Map<String, Value>, SdJwtError> {
+    let digests = match claims.remove(SD_CLAIM) {
+        Some(Value::Array(digests)) => digests,
+        Some(_) => return Err(SdJwtError::Malformed("malformed _sd claim".to_string())),
+        None => vec![],
+    };
+    match claims.remove(SD_ALG_CLAIM) {
+        Some(Value::String(alg)) if alg == SD_ALG => {}
+        None if disclosures.is_empty() => {}
+        _ => return Err(SdJwtError::Malformed("expected _sd_alg sha-256".to_string())),
+    }
+    let mut disclosed_digests = Vec::new();
+    for disclosure in disclosures {
+        let digest = disclosure_digest(disclosure);
+        if !digests.contains(&Value::String(digest.clone())) {
+            return Err(SdJwtError::InvalidDisclosure(
+                "disclosure does not match any digest".to_string(),
+            ));
+        }
+        if disclosed_digests.contains(&digest) {
+            return Err(SdJwtError::InvalidDisclosure(
+                "disclosure is repeated".to_string(),
+            ));
+        }
+        disclosed_digests.push(digest);
+        let (name, value) = decode_disclosure(disclosure)?;
+        if name == SD_CLAIM || name == "..." || claims.contains_key(&name) {
+            return Err(SdJwtError::InvalidDisclosure(format!(
+                "disclosure of claim {} is not allowed",
+                name
+            )));
+        }
+        claims.insert(name, value);
+    }
+    Ok(claims)
+}
+
+/// Verifies the issuer JWS of the SD-JWT cryptographically, checks that it was signed by the
+/// provided canister and returns its claims with the disclosed claims added.
+/// DOES NOT perform semantic validation of the claims.
+pub fn verify_sd_jwt_with_canister_id(
+    sd_jwt: &str,
+    signing_canister_id: &Principal,
+    root_pk_raw: &[u8],
+    current_time_ns: u128,
+) -> Result<Map<String, Value>, SdJwtError> {
+    let (issuer_jws, disclosures) = split_sd_jwt(sd_jwt)?;
+    verify_credential_jws_with_canister_id(
+        issuer_jws,
+        signing_canister_id,
+        root_pk_raw,
+        current_time_ns,
+    )
+    .map_err(SdJwtError::InvalidJws)?;
+    // The JWS is valid, so its header and payload can be taken as is
+    let mut parts = issuer_jws.split('.');
+    let header = parts
+        .next()
+        .and_then(decode_json_part)
+        .ok_or(SdJwtError::Malformed("malformed JWS header".to_string()))?;
+    if header.get("typ") != Some(&json!(SD_JWT_TYP)) {
+        return Err(SdJwtError::Malformed(format!(
+            "expected typ {} in the JWS header",
+            SD_JWT_TYP
+        )));
+    }
+    let claims = parts
+        .next()
+        .and_then(decode_json_part)
+        .ok_or(SdJwtError::Malformed("malformed JWS payload".to_string()))?;
+    process_disclosures(claims, &disclosures)
+}
+
+fn decode_json_part(part: &str) -> Option<Map<String, Value>> {
+    decode_b64(part)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+}
+
+fn decode_disclosure(disclosure: &str) -> Result<(String, Value), SdJwtError> {
+    let decoded: Value = decode_b64(disclosure)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(SdJwtError::InvalidDisclosure(
+            "malformed disclosure".to_string(),
+        ))?;
+    match decoded {
+        Value::Array(mut parts) if parts.len() == 3 => {
+            let value = parts.remove(2);
+            match &parts[1] {
+                Value::String(name) => Ok((name.clone(), value)),
+                _ => Err(SdJwtError::InvalidDisclosure(
+                    "malformed claim name in disclosure".to_string(),
+                )),
+            }
+        }
+        _ => Err(SdJwtError::InvalidDisclosure(
+            "disclosure must be an array of salt, claim name and value".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer_claims(disclosures: &[String]) -> Map<String, Value> {
+        let mut claims = Map::new();
+        claims.insert("vct".to_string(), json!("VerifiedResident"));
+        add_disclosure_digests(&mut claims, disclosures);
+        claims
+    }
+
+    /// Test the digest against the example of the SD-JWT specification
+    #[test]
+    fn should_compute_disclosure_digest() {
+        assert_eq!(
+            disclosure_digest(
+                "WyJfMjZiYzRMVC1hYzZxMktJNmNCVzVlcyIsICJmYW1pbHlfbmFtZSIsICJNw7ZiaXVzIl0"
+            ),
+            "X9yH0Ajrdm1Oij4tWso9UzzKJvPoDxwmuEcO3XAdRC0"
+        );
+    }
+
+    #[test]
+    fn should_process_selected_disclosures() {
+        let disclosures = vec![
+            create_disclosure(&[1; 16], "country", &json!("CH")),
+            create_disclosure(&[2; 16], "city", &json!("Zurich")),
+        ];
+        let claims = issuer_claims(&disclosures);
+        let sd_jwt = sd_jwt_from_jws("header.payload.signature", &disclosures);
+
+        let presented = select_disclosures(&sd_jwt, &["city"]).expect("failed selecting");
+        let (issuer_jws, presented_disclosures) = split_sd_jwt(&presented).expect("failed split");
+        assert_eq!(issuer_jws, "header.payload.signature");
+        assert_eq!(presented_disclosures, vec![disclosures[1].as_str()]);
+
+        let processed =
+            process_disclosures(claims, &presented_disclosures).expect("failed processing");
+        assert_eq!(processed.get("city"), Some(&json!("Zurich")));
+        assert!(!processed.contains_key("country"));
+        assert!(!processed.contains_key(SD_CLAIM));
+        assert!(!processed.contains_key(SD_ALG_CLAIM));
+    }
+
+    #[test]
+    fn should_reject_invalid_disclosures() {
+        let disclosures = vec![create_disclosure(&[1; 16], "country", &json!("CH"))];
+        let claims = issuer_claims(&disclosures);
+
+        let forged = create_disclosure(&[1; 16], "country", &json!("FR"));
+        assert!(matches!(
+            process_disclosures(claims.clone(), &[forged.as_str()]),
+            Err(SdJwtError::InvalidDisclosure(_))
+        ));
+        assert!(matches!(
+            process_disclosures(claims.clone(), &[disclosures[0].as_str(), disclosures[0].as_str()]),
+            Err(SdJwtError::InvalidDisclosure(_))
+        ));
+
+        let overriding = vec![create_disclosure(&[3; 16], "vct", &json!("VerifiedAdult"))];
+        assert!(matches!(
+            process_disclosures(issuer_claims(&overriding), &[overriding[0].as_str()]),
+            Err(SdJwtError::InvalidDisclosure(_))
+        ));
+
+        assert!(matches!(
+            split_sd_jwt("header.payload.signature~disclosure~kb-jwt"),
+            Err(SdJwtError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+//! Bitstring Status Lists (cf. https://www.w3.org/TR/vc-bitstring-status-list/) to check whether
+//! an issued VC has been revoked or suspended.
+//!
+//! Each VC carries `credentialStatus` entries that point to a bit of a status list. The issuer
+//! publishes the status lists as status list credentials with the bits compressed into their
+//! `encodedList`. The status list credentials are not signed themselves: the issuer canister
+//! serves them as certified assets, so `check_credential_status` verifies the certification of
+//! the responses (with `ic-response-verification`) before it reads the status bits.
+use candid::Principal;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ic_http_certification::{HttpRequest, HttpResponse};
+use ic_response_verification::verify_request_response_pair;
+use identity_jose::jwu::{decode_b64, encode_b64};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub const STATUS_LIST_ENTRY_TYPE: &str = "BitstringStatusListEntry";
+pub const STATUS_LIST_CREDENTIAL_TYPE: &str = "BitstringStatusListCredential";
+pub const STATUS_LIST_TYPE: &str = "BitstringStatusList";
+pub const REVOCATION_PURPOSE: &str = "revocation";
+pub const SUSPENSION_PURPOSE: &str = "suspension";
+/// Minimum number of bits of a status list, so that a VC is hidden among many others
+pub const MIN_STATUS_LIST_LENGTH: usize = 131_072;
+const CREDENTIALS_V2_CONTEXT: &str = "https://www.w3.org/ns/credentials/v2";
+// Multibase prefix of base64url without padding
+const MULTIBASE_BASE64URL: char = 'u';
+// Decompressed status lists are cut off at 16 MiB
+const MAX_STATUS_LIST_BYTES: u64 = 16 * 1024 * 1024;
+// The certificate of a status list response may be at most 5 minutes old
+const MAX_CERT_TIME_OFFSET_NS: u128 = 5 * 60 * 1_000_000_000;
+// Only the response verification version 2 certifies the path of the response
+const MIN_VERIFICATION_VERSION: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum StatusListError {
+    MalformedEntry(String),
+    FetchFailed(String),
+    InvalidStatusList(String),
+}
+
+/// A `credentialStatus` entry of a VC
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusListEntry {
+    pub status_purpose: String,
+    pub status_list_index: usize,
+    /// URL of the status list credential
+    pub status_list_credential: String,
+}
+
+impl StatusListEntry {
+    /// Returns the entry as it is put into the `credentialStatus` of a VC.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": format!("{}#{}", self.status_list_credential, self.status_list_index),
+            "type": STATUS_LIST_ENTRY_TYPE,
+            "statusPurpose": self.status_purpose,
+            "statusListIndex": self.status_list_index.to_string(),
+            "statusListCredential": self.status_list_credential,
+        })
+    }
+}
+
+/// HTTP response of the issuer canister to the GET request of a status list credential
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusListResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Compresses the bitstring into the `encodedList` of a status list credential.
+pub fn encode_status_list(bits: &[u8]) -> String {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bits)
+        .and_then(|_| encoder.finish())
+        .map(|compressed| format!("{}{}", MULTIBASE_BASE64URL, encode_b64(compressed)))
+        .expect("internal: failed compressing status list")
+}
+
+/// Decompresses the `encodedList` of a status list credential into the bitstring.
+pub fn decode_status_list(encoded_list: &str) -> Result<Vec<u8>, StatusListError> {
+    let compressed = encoded_list
+        .strip_prefix(MULTIBASE_BASE64URL)
+        .and_then(|encoded| decode_b64(encoded).ok())
+        .ok_or(invalid_status_list("encodedList is not multibase base64url"))?;
+    let mut bits = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .take(MAX_STATUS_LIST_BYTES)
+        .read_to_end(&mut bits)
+        .map_err(|_| invalid_status_list("encodedList is not GZIP compressed"))?;
+    Ok(bits)
+}
+
+/// Returns the bit at the index, where index 0 is the left-most bit of the first byte.
+pub fn status_bit(bits: &[u8], index: usize) -> Option<bool> {
+    bits.get(index / 8).map(|byte| byte & (0x80 >> (index % 8)) != 0)
+}
+
+/// Sets the bit at the index, where index 0 is the left-most bit of the first byte.
+pub fn set_status_bit(bits: &mut [u8], index: usize, value: bool) {
+    let mask = 0x80 >> (index % 8);
+    if value {
+        bits[index / 8] |= mask;
+    } else {
+        bits[index / 8] &= !mask;
+    }
+}
+
+/// Builds the status list credential with the given id (its URL) for one purpose.
+/// `valid_from` is an RFC 3339 timestamp.
+pub fn build_status_list_credential(
+    id: &str,
+    issuer: &str,
+    status_purpose: &str,
+    valid_from: &str,
+    bits: &[u8],
+) -> Value {
+    json!({
+        "@context": [CREDENTIALS_V2_CONTEXT],
+        "id": id,
+        "type": ["VerifiableCredential", STATUS_LIST_CREDENTIAL_TYPE],
+        "issuer": issuer,
+        "validFrom": valid_from,
+        "credentialSubject": {
+            "id": format!("{}#list", id),
+            "type": STATUS_LIST_TYPE,
+            "statusPurpose": status_purpose,
+            "encodedList": encode_status_list(bits),
+        },
+    })
+}
+
+/// Parses the `credentialStatus` entries of the VC claims.
+/// Returns no entries if the VC has no `credentialStatus`.
+pub fn status_list_entries(
+    vc_claims: &Map<String, Value>,
+) -> Result<Vec<StatusListEntry>, StatusListError> {
+    let entries = match vc_claims.get("credentialStatus") {
+        None => return Ok(vec![]),
+        Some(Value::Array(entries)) => entries.iter().collect(),
+        Some(entry) => vec![entry],
+    };
+    entries.into_iter().map(parse_status_list_entry).collect()
+}
+
+fn parse_status_list_entry(entry: &Value) -> Result<StatusListEntry, StatusListError> {
+    let field = |name: &str| {
+        entry
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                StatusListError::MalformedEntry(format!("missing {} of credentialStatus", name))
+            })
+    };
+    if field("type")? != STATUS_LIST_ENTRY_TYPE {
+        return Err(StatusListError::MalformedEntry(format!(
+            "unsupported credentialStatus type {}",
+            field("type")?
+        )));
+    }
+    // Only purposes with one bit per VC are supported
+    if entry.get("statusSize").is_some_and(|size| size != &json!(1)) {
+        return Err(StatusListError::MalformedEntry(
+            "unsupported statusSize of credentialStatus".to_string(),
+        ));
+    }
+    Ok(StatusListEntry {
+        status_purpose: field("statusPurpose")?.to_string(),
+        status_list_index: field("statusListIndex")?.parse().map_err(|_| {
+            StatusListError::MalformedEntry("malformed statusListIndex".to_string())
+        })?,
+        status_list_credential: field("statusListCredential")?.to_string(),
+    })
+}
+
+/// Checks the status list credential that the entry points to and returns the status bit of
+/// the entry. The status list credential must have been issued by `issuer`.
+pub fn verify_status(
+    entry: &StatusListEntry,
+    status_list_credential: &Value,
+    issuer: &str,
+) -> Result<bool, StatusListError> {
+    let types = status_list_credential
+        .get("type")
+        .and_then(Value::as_array)
+        .ok_or(invalid_status_list("missing type"))?;
+    if !types.contains(&json!(STATUS_LIST_CREDENTIAL_TYPE)) {
+        return Err(invalid_status_list("not a BitstringStatusListCredential"));
+    }
+    if status_list_credential.get("id") != Some(&json!(entry.status_list_credential)) {
+        return Err(invalid_status_list("id does not match statusListCredential"));
+    }
+    if status_list_credential.get("issuer") != Some(&json!(issuer)) {
+        return Err(invalid_status_list("issuer does not match the VC issuer"));
+    }
+    let subject = status_list_credential
+        .get("credentialSubject")
+        .ok_or(invalid_status_list("missing credentialSubject"))?;
+    if subject.get("statusPurpose") != Some(&json!(entry.status_purpose)) {
+        return Err(invalid_status_list("statusPurpose does not match the entry"));
+    }
+    let encoded_list = subject
+        .get("encodedList")
+        .and_then(Value::as_str)
+        .ok_or(invalid_status_list("missing encodedList"))?;
+    let bits = decode_status_list(encoded_list)?;
+    if bits.len() * 8 < MIN_STATUS_LIST_LENGTH {
+        return Err(invalid_status_list("status list is too short"));
+    }
+    status_bit(&bits, entry.status_list_index)
+        .ok_or(invalid_status_list("statusListIndex is out of range"))
+}
+
+/// Verifies that the response to the GET request of the status list credential at `url` is
+/// certified by the issuer canister and returns the certified status list credential.
+/// `root_pk_der` is the DER encoded root public key of the IC.
+pub fn verify_status_list_response(
+    url: &str,
+    response: StatusListResponse,
+    issuer_canister_id: &Principal,
+    root_pk_der: &[u8],
+    current_time_ns: u128,
+) -> Result<String, StatusListError> {
+    let path = url
+        .split_once("://")
+        .and_then(|(_, rest)| rest.find('/').map(|start| rest[start..].to_string()))
+        .ok_or(invalid_status_list("statusListCredential is not a URL"))?;
+    let info = verify_request_response_pair(
+        HttpRequest {
+            method: "GET".to_string(),
+            url: path,
+            headers: vec![],
+            body: vec![],
+        },
+        HttpResponse {
+            status_code: response.status_code,
+            headers: response.headers,
+            body: response.body,
+            upgrade: None,
+        },
+        issuer_canister_id.as_slice(),
+        current_time_ns,
+        MAX_CERT_TIME_OFFSET_NS,
+        root_pk_der,
+        MIN_VERIFICATION_VERSION,
+    )
+    .map_err(|e| {
+        StatusListError::InvalidStatusList(format!("status list response is not certified: {}", e))
+    })?;
+    let verified = info
+        .response
+        .ok_or(invalid_status_list("status list response is not certified"))?;
+    if verified.status_code != Some(200) {
+        return Err(StatusListError::FetchFailed(format!(
+            "status list response has status {:?}",
+            verified.status_code
+        )));
+    }
+    String::from_utf8(verified.body)
+        .map_err(|_| invalid_status_list("status list credential is not UTF-8"))
+}
+
+/// Checks the VC claims against the status lists of their `credentialStatus` entries and returns
+/// the purposes whose status bit is set, e.g. `["revocation"]` for a revoked VC.
+///
+/// `fetch_status_list` returns the response of the issuer canister to the GET request of the
+/// given URL, whose certification is verified with `verify_status_list_response`. Each status
+/// list is fetched once.
+pub fn check_credential_status<F>(
+    vc_claims: &Map<String, Value>,
+    issuer: &str,
+    issuer_canister_id: &Principal,
+    root_pk_der: &[u8],
+    current_time_ns: u128,
+    mut fetch_status_list: F,
+) -> Result<Vec<String>, StatusListError>
+where
+    F: FnMut(&str) -> Result<StatusListResponse, String>,
+{
+    check_status_lists(vc_claims, issuer, |url| {
+        let response = fetch_status_list(url).map_err(StatusListError::FetchFailed)?;
+        verify_status_list_response(
+            url,
+            response,
+            issuer_canister_id,
+            root_pk_der,
+            current_time_ns,
+        )
+    })
+}
+
+/// Checks the VC claims against the status list credentials returned by `status_list`.
+fn check_status_lists<F>(
+    vc_claims: &Map<String, Value>,
+    issuer: &str,
+    mut status_list: F,
+) -> Result<Vec<String>, StatusListError>
+where
+    F: FnMut(&str) -> Result<String, StatusListError>,
+{
+    let mut status_lists: HashMap<String, Value> = HashMap::new();
+    let mut set_purposes = Vec::new();
+    for entry in status_list_entries(vc_claims)? {
+        if !status_lists.contains_key(&entry.status_list_credential) {
+            let body = status_list(&entry.status_list_credential)?;
+            let status_list: Value = serde_json::from_str(&body)
+                .map_err(|_| invalid_status_list("status list credential is not JSON"))?;
+            status_lists.insert(entry.status_list_credential.clone(), status_list);
+        }
+        if verify_status(&entry, &status_lists[&entry.status_list_credential], issuer)? {
+            set_purposes.push(entry.status_purpose);
+        }
+    }
+    Ok(set_purposes)
+}
+
+fn invalid_status_list(msg: &str) -> StatusListError {
+    StatusListError::InvalidStatusList(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST_URL: &str = "https://aaaaa-aa.icp0.io/status/issuer/revocation";
+    const ISSUER: &str = "did:icp:v0:issuer";
+
+    fn vc_claims(entries: &[StatusListEntry]) -> Map<String, Value> {
+        let mut claims = Map::new();
+        let entries: Vec<Value> = entries.iter().map(StatusListEntry::to_json).collect();
+        claims.insert("credentialStatus".to_string(), json!(entries));
+        claims
+    }
+
+    fn revocation_entry(index: usize) -> StatusListEntry {
+        StatusListEntry {
+            status_purpose: REVOCATION_PURPOSE.to_string(),
+            status_list_index: index,
+            status_list_credential: LIST_URL.to_string(),
+        }
+    }
+
+    #[test]
+    fn should_address_bits_from_the_left() {
+        let mut bits = vec![0u8; 2];
+        set_status_bit(&mut bits, 0, true);
+        set_status_bit(&mut bits, 9, true);
+        assert_eq!(bits, vec![0x80, 0x40]);
+        assert_eq!(status_bit(&bits, 9), Some(true));
+        assert_eq!(status_bit(&bits, 10), Some(false));
+        assert_eq!(status_bit(&bits, 16), None);
+        set_status_bit(&mut bits, 0, false);
+        assert_eq!(bits, vec![0x00, 0x40]);
+    }
+
+    #[test]
+    fn should_round_trip_encoded_list() {
+        let mut bits = vec![0u8; MIN_STATUS_LIST_LENGTH / 8];
+        set_status_bit(&mut bits, 42, true);
+        let encoded = encode_status_list(&bits);
+        assert!(encoded.starts_with('u'));
+        // The compressed list of a mostly empty bitstring is small
+        assert!(encoded.len() < 1_000);
+        assert_eq!(decode_status_list(&encoded), Ok(bits));
+        assert!(decode_status_list("zabc").is_err());
+    }
+
+    #[test]
+    fn should_check_credential_status() {
+        let mut bits = vec![0u8; MIN_STATUS_LIST_LENGTH / 8];
+        set_status_bit(&mut bits, 7, true);
+        let status_list = build_status_list_credential(
+            LIST_URL,
+            ISSUER,
+            REVOCATION_PURPOSE,
+            "2024-01-01T00:00:00Z",
+            &bits,
+        )
+        .to_string();
+        let fetched = std::cell::Cell::new(0);
+        let mut fetch = |url: &str| -> Result<String, StatusListError> {
+            assert_eq!(url, LIST_URL);
+            fetched.set(fetched.get() + 1);
+            Ok(status_list.clone())
+        };
+
+        let claims = vc_claims(&[revocation_entry(7), revocation_entry(8)]);
+        assert_eq!(
+            check_status_lists(&claims, ISSUER, &mut fetch),
+            Ok(vec![REVOCATION_PURPOSE.to_string()])
+        );
+        assert_eq!(fetched.get(), 1);
+        let claims = vc_claims(&[revocation_entry(8)]);
+        assert_eq!(check_status_lists(&claims, ISSUER, &mut fetch), Ok(vec![]));
+        assert_eq!(check_status_lists(&Map::new(), ISSUER, &mut fetch), Ok(vec![]));
+
+        // The status list must be issued by the VC issuer and match the entry
+        assert!(matches!(
+            check_status_lists(&claims, "did:icp:v0:other", &mut fetch),
+            Err(StatusListError::InvalidStatusList(_))
+        ));
+        let suspension = StatusListEntry {
+            status_purpose: SUSPENSION_PURPOSE.to_string(),
+            ..revocation_entry(7)
+        };
+        assert!(matches!(
+            check_status_lists(&vc_claims(&[suspension]), ISSUER, &mut fetch),
+            Err(StatusListError::InvalidStatusList(_))
+        ));
+    }
+
+    #[test]
+    fn should_reject_uncertified_status_list() {
+        let response = StatusListResponse {
+            status_code: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: b"{}".to_vec(),
+        };
+        let canister_id = Principal::from_text("aaaaa-aa").unwrap();
+        assert!(matches!(
+            verify_status_list_response(LIST_URL, response.clone(), &canister_id, &[], 0),
+            Err(StatusListError::InvalidStatusList(_))
+        ));
+        assert!(matches!(
+            verify_status_list_response("status/issuer", response, &canister_id, &[], 0),
+            Err(StatusListError::InvalidStatusList(_))
+        ));
+
+        let claims = vc_claims(&[revocation_entry(1)]);
+        assert!(matches!(
+            check_credential_status(&claims, ISSUER, &canister_id, &[], 0, |_| Err(
+                "not found".to_string()
+            )),
+            Err(StatusListError::FetchFailed(_))
+        ));
+    }
+
+    #[test]
+    fn should_reject_malformed_entries() {
+        let mut entry = revocation_entry(1).to_json();
+        entry["statusListIndex"] = json!(1);
+        let mut claims = Map::new();
+        claims.insert("credentialStatus".to_string(), entry);
+        assert!(matches!(
+            status_list_entries(&claims),
+            Err(StatusListError::MalformedEntry(_))
+        ));
+
+        let mut entry = revocation_entry(1).to_json();
+        entry["statusSize"] = json!(2);
+        claims.insert("credentialStatus".to_string(), entry);
+        assert!(matches!(
+            status_list_entries(&claims),
+            Err(StatusListError::MalformedEntry(_))
+        ));
+    }
+}
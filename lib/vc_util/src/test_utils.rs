@@ -0,0 +1,44 @@
+//! Helpers for tests of relying parties, available with the `test-utils` feature.
+//!
+//! The presentations created here are not signed (`alg: none`) and must not be used outside tests.
+use crate::did_for_principal;
+use candid::Principal;
+use identity_core::common::Url;
+use identity_credential::credential::Jwt;
+use identity_credential::presentation::{
+    JwtPresentationOptions, Presentation, PresentationBuilder,
+};
+use identity_jose::jws::{CompactJwsEncoder, JwsAlgorithm, JwsHeader};
+
+/// Creates an unsigned JWT presentation of the holder with the given VCs, as a holder presents
+/// the id alias VC and the requested VC to a relying party.
+pub fn create_verifiable_presentation_jwt_for_test(
+    holder: Principal,
+    vcs_jws: Vec<String>,
+) -> Result<String, String> {
+    let holder_url = Url::parse(did_for_principal(holder)).map_err(|_| "Invalid holder")?;
+    let mut builder = PresentationBuilder::new(holder_url, Default::default());
+    for vc in vcs_jws {
+        builder = builder.credential(Jwt::from(vc));
+    }
+    let presentation: Presentation<Jwt> = builder
+        .build()
+        .map_err(|_| "failed building presentation")?;
+    presentation_to_compact_jwt(&presentation)
+}
+
+fn presentation_to_compact_jwt(presentation: &Presentation<Jwt>) -> Result<String, String> {
+    let mut header: JwsHeader = JwsHeader::new();
+    header.set_typ("JWT");
+    header.set_alg(JwsAlgorithm::NONE);
+    let vp_jwt = presentation
+        .serialize_jwt(&JwtPresentationOptions {
+            expiration_date: None,
+            issuance_date: None,
+            audience: None,
+        })
+        .map_err(|_| "failed serializing presentation")?;
+    let encoder: CompactJwsEncoder = CompactJwsEncoder::new(vp_jwt.as_ref(), &header)
+        .map_err(|_| "internal error: JWS encoder failed")?;
+    Ok(encoder.into_jws(&[]))
+}
@@ -110,18 +110,12 @@ echo "Parsed rootkey: ${rootkey_did:0:20}..." >&2
 
 # Add also dev server to alternative origins when deploying locally
 if [ "$DFX_NETWORK" = "local" ]; then
-  ALTERNATIVE_ORIGINS="\"http://$CIVIC_FRONTEND_CANISTER_ID.localhost:4943\""
+  ALTERNATIVE_ORIGIN="http://$CIVIC_FRONTEND_CANISTER_ID.localhost:4943"
   else
-  ALTERNATIVE_ORIGINS="\"https://$CIVIC_FRONTEND_CANISTER_ID.icp0.io\""
+  ALTERNATIVE_ORIGIN="https://$CIVIC_FRONTEND_CANISTER_ID.icp0.io"
 fi
 
-echo "Using Alternative Origin: $ALTERNATIVE_ORIGINS $ISSUER_FRONTEND_HOSTNAME"
-
-# Adjust issuer's .well-known/ii-alternative-origins to contain FE-hostname of local/dev deployments.
-# We had a problem with `sed` command in CI. This is a hack to make it work locally and in CI.
-mv src/civic_canister_backend/dist/.well-known/ii-alternative-origins ./ii-alternative-origins-template
-cat ./ii-alternative-origins-template | sed "s+ISSUER_FE_HOSTNAME_PLACEHOLDER+$ALTERNATIVE_ORIGINS+g"  > src/civic_canister_backend/dist/.well-known/ii-alternative-origins
-rm ./ii-alternative-origins-template
+echo "Using Alternative Origin: $ALTERNATIVE_ORIGIN $ISSUER_FRONTEND_HOSTNAME"
 
 dfx deploy --upgrade-unchanged civic_canister_backend --network "$DFX_NETWORK" --argument '(
     opt record {
@@ -133,5 +127,8 @@ dfx deploy --upgrade-unchanged civic_canister_backend --network "$DFX_NETWORK" -
         authorized_issuers = vec { principal "'"$ADMIN_PRINCIPAL_ID"'" };
     }
 )'
-# Revert changes
-git checkout src/civic_canister_backend/dist/.well-known/ii-alternative-origins
\ No newline at end of file
+
+# Allow the FE-hostname of local/dev deployments in the issuer's .well-known/ii-alternative-origins.
+# The canister certifies the document from its list of alternative origins, so this needs no
+# rebuild. The call has to be made with the admin identity.
+dfx canister call civic_canister_backend add_alternative_origin "(\"$ALTERNATIVE_ORIGIN\")" --network "$DFX_NETWORK"
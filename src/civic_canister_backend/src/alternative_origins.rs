@@ -0,0 +1,147 @@
+//! The alternative origins of the issuer, served as certified asset at
+//! `/.well-known/ii-alternative-origins`, so that the frontends listed there can use the canister
+//! as derivation origin with Internet Identity.
+//!
+//! The origins are managed by the admin and kept in stable memory. The document is certified again
+//! whenever the list changes, so that allowing a frontend doesn't need a new build of the canister.
+//! The list is seeded once with the origins of the embedded file, on the first install or the
+//! upgrade from a version that served the file embedded at build time. Origins are added later with
+//! `add_alternative_origin`, e.g. by the deploy scripts.
+use asset_util::{Asset, CertifiedAssets, ContentEncoding, ContentType};
+use candid::candid_method;
+use ic_cdk::api::caller;
+use ic_cdk_macros::{query, update};
+use serde_json::{json, Value};
+use vc_util::issuer_api::IssuerError;
+
+use crate::config::{
+    is_admin, normalize_origin, static_headers, ALTERNATIVE_ORIGINS, ALTERNATIVE_ORIGINS_SEEDED,
+    ASSETS,
+};
+use crate::credential::update_root_hash;
+
+const ALTERNATIVE_ORIGINS_PATH: &str = "/.well-known/ii-alternative-origins";
+// The embedded file, which the generated document overrides
+const EMBEDDED_ALTERNATIVE_ORIGINS: &str =
+    include_str!("../dist/.well-known/ii-alternative-origins");
+
+/// Adds an origin to the alternative origins. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn add_alternative_origin(origin: String) -> Result<(), IssuerError> {
+    let origin = checked_origin(&origin)?;
+    ALTERNATIVE_ORIGINS.with_borrow_mut(|origins| origins.insert(origin, ()));
+    update_alternative_origins();
+    Ok(())
+}
+
+/// Removes an origin from the alternative origins. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn remove_alternative_origin(origin: String) -> Result<(), IssuerError> {
+    let origin = checked_origin(&origin)?;
+    ALTERNATIVE_ORIGINS.with_borrow_mut(|origins| origins.remove(&origin));
+    update_alternative_origins();
+    Ok(())
+}
+
+/// Retrieves the alternative origins.
+#[query]
+#[candid_method(query)]
+fn get_alternative_origins() -> Vec<String> {
+    ALTERNATIVE_ORIGINS.with_borrow(|origins| origins.iter().map(|(origin, _)| origin).collect())
+}
+
+fn checked_origin(origin: &str) -> Result<String, IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    normalize_origin(origin).map_err(IssuerError::UnsupportedOrigin)
+}
+
+/// Seeds the alternative origins with the ones of the embedded file unless they were seeded before.
+pub(crate) fn init_alternative_origins() {
+    if ALTERNATIVE_ORIGINS_SEEDED.with_borrow(|seeded| *seeded.get()) {
+        return;
+    }
+    ALTERNATIVE_ORIGINS.with_borrow_mut(|origins| {
+        for origin in embedded_alternative_origins(EMBEDDED_ALTERNATIVE_ORIGINS) {
+            origins.insert(origin, ());
+        }
+    });
+    ALTERNATIVE_ORIGINS_SEEDED.with_borrow_mut(|seeded| {
+        seeded
+            .set(true)
+            .expect("failed to store that the alternative origins were seeded")
+    });
+}
+
+/// Reads the origins of an embedded file. The template with the placeholder has none.
+fn embedded_alternative_origins(document: &str) -> Vec<String> {
+    let Ok(document) = serde_json::from_str::<Value>(document) else {
+        return vec![];
+    };
+    document["alternativeOrigins"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter_map(|origin| normalize_origin(origin).ok())
+        .collect()
+}
+
+/// Certifies the alternative origins. The caller must update the certified data afterwards.
+pub(crate) fn certify_alternative_origins(assets: &mut CertifiedAssets) {
+    assets.certify_asset(
+        Asset {
+            url_path: ALTERNATIVE_ORIGINS_PATH.to_string(),
+            content: alternative_origins_document(get_alternative_origins()).into_bytes(),
+            encoding: ContentEncoding::Identity,
+            content_type: ContentType::JSON,
+        },
+        &static_headers(),
+    );
+}
+
+fn update_alternative_origins() {
+    ASSETS.with_borrow_mut(certify_alternative_origins);
+    update_root_hash();
+}
+
+fn alternative_origins_document(origins: Vec<String>) -> String {
+    json!({ "alternativeOrigins": origins }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alternative_origins_document() {
+        assert_eq!(
+            alternative_origins_document(vec![]),
+            r#"{"alternativeOrigins":[]}"#
+        );
+        assert_eq!(
+            alternative_origins_document(vec![
+                "https://a.icp0.io".to_string(),
+                "http://b.localhost:4943".to_string()
+            ]),
+            r#"{"alternativeOrigins":["https://a.icp0.io","http://b.localhost:4943"]}"#
+        );
+    }
+
+    #[test]
+    fn test_embedded_alternative_origins() {
+        let template = r#"{"alternativeOrigins": [ISSUER_FE_HOSTNAME_PLACEHOLDER]}"#;
+        assert!(embedded_alternative_origins(template).is_empty());
+        assert_eq!(
+            embedded_alternative_origins(
+                r#"{"alternativeOrigins": ["http://a.localhost:4943", "a.icp0.io"]}"#
+            ),
+            vec!["http://a.localhost:4943".to_string()]
+        );
+    }
+}
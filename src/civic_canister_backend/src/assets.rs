@@ -0,0 +1,286 @@
+//! Files of the management app, uploaded by the admin at runtime, so that a change of the app
+//! doesn't need an upgrade of the canister.
+//!
+//! The files embedded from `dist` at build time are certified first and remain the baseline. An
+//! uploaded file overrides the embedded file at the same path, and deleting it serves the embedded
+//! file again.
+//!
+//! A file is uploaded in chunks to a batch created for its path, e.g. `index.html` or
+//! `assets/app.js.gz`. Committing the batch checks the SHA-256 hash of the file, stores it in
+//! stable memory and certifies it like `asset_util::collect_assets` does for the files of a
+//! directory. The stored files are certified again after an upgrade. Batches are kept on the heap,
+//! so uploads that are not committed before an upgrade have to be started again. A file can have
+//! at most 20 MiB, and the uploads in progress at most 100 MiB together. Expired batches are
+//! dropped whenever a batch is created or a chunk is uploaded.
+use asset_util::{file_assets, CertifiedAssets, ContentEncoding, ContentType};
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode};
+use ic_cdk::api::{caller, time};
+use ic_cdk_macros::update;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use vc_util::issuer_api::IssuerError;
+
+use crate::config::{init_assets, is_admin, static_headers, ASSETS, ASSET_BATCHES, ASSET_FILES};
+use crate::credential::update_root_hash;
+
+// Batches that are not committed within 30 minutes are dropped
+const BATCH_EXPIRY_NS: u64 = 30 * 60 * 1_000_000_000;
+// Limits on the size of a file and of all uploads in progress, which are kept on the heap
+const MAX_BATCH_SIZE: usize = 20 * 1024 * 1024;
+const MAX_BATCHES_SIZE: usize = 100 * 1024 * 1024;
+// The paths of the assets the canister generates itself
+const RESERVED_PATHS: [&str; 2] = [".well-known/", "status/"];
+
+/// A file of the management app
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct StoredAsset {
+    content: ByteBuf,
+}
+
+impl Storable for StoredAsset {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode StoredAsset"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, StoredAsset).expect("Failed to decode StoredAsset")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// An upload of a file
+struct AssetBatch {
+    path: String,
+    chunks: BTreeMap<u32, ByteBuf>,
+    /// The total size of the chunks
+    size: usize,
+    created_at_ns: u64,
+}
+
+/// The uploads in progress
+#[derive(Default)]
+pub(crate) struct AssetBatches {
+    next_id: u64,
+    batches: HashMap<u64, AssetBatch>,
+}
+
+impl AssetBatches {
+    fn prune_expired(&mut self, now: u64) {
+        self.batches
+            .retain(|_, batch| batch.created_at_ns + BATCH_EXPIRY_NS > now);
+    }
+
+    fn size(&self) -> usize {
+        self.batches.values().map(|batch| batch.size).sum()
+    }
+}
+
+/// Starts the upload of the file at the given path and returns the id of its batch.
+/// Can only be called by the admin.
+#[update]
+#[candid_method]
+fn create_asset_batch(path: String) -> Result<u64, IssuerError> {
+    check_admin()?;
+    check_path(&path)?;
+    let now = time();
+    Ok(ASSET_BATCHES.with_borrow_mut(|uploads| {
+        uploads.prune_expired(now);
+        let id = uploads.next_id;
+        uploads.next_id += 1;
+        uploads.batches.insert(
+            id,
+            AssetBatch {
+                path,
+                chunks: BTreeMap::new(),
+                size: 0,
+                created_at_ns: now,
+            },
+        );
+        id
+    }))
+}
+
+/// Uploads the chunk of a batch with the given index, replacing a previous upload of the chunk.
+/// Can only be called by the admin.
+#[update]
+#[candid_method]
+fn upload_asset_chunk(batch_id: u64, index: u32, content: ByteBuf) -> Result<(), IssuerError> {
+    check_admin()?;
+    ASSET_BATCHES.with_borrow_mut(|uploads| {
+        uploads.prune_expired(time());
+        let uploads_size = uploads.size();
+        let batch = uploads
+            .batches
+            .get_mut(&batch_id)
+            .ok_or_else(|| batch_not_found(batch_id))?;
+        let replaced = batch.chunks.get(&index).map_or(0, |chunk| chunk.len());
+        let batch_size = batch.size - replaced + content.len();
+        if batch_size > MAX_BATCH_SIZE {
+            return Err(IssuerError::InvalidAsset(format!(
+                "{} is larger than {} bytes",
+                batch.path, MAX_BATCH_SIZE
+            )));
+        }
+        if uploads_size - batch.size + batch_size > MAX_BATCHES_SIZE {
+            return Err(IssuerError::InvalidAsset(
+                "Too many uploads in progress, please commit them first".to_string(),
+            ));
+        }
+        batch.size = batch_size;
+        batch.chunks.insert(index, content);
+        Ok(())
+    })
+}
+
+/// Stores and certifies the file of a batch, whose chunks are concatenated in the order of their
+/// indexes, if its SHA-256 hash is the given one. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn commit_asset_batch(batch_id: u64, sha256: ByteBuf) -> Result<(), IssuerError> {
+    check_admin()?;
+    let batch = ASSET_BATCHES
+        .with_borrow_mut(|uploads| uploads.batches.remove(&batch_id))
+        .ok_or_else(|| batch_not_found(batch_id))?;
+    let content = concat_chunks(batch.chunks)?;
+    if Sha256::digest(&content).as_slice() != sha256.as_slice() {
+        return Err(IssuerError::InvalidAsset(format!(
+            "The SHA-256 hash of {} doesn't match",
+            batch.path
+        )));
+    }
+    let asset = StoredAsset {
+        content: ByteBuf::from(content),
+    };
+    ASSETS.with_borrow_mut(|assets| certify_stored_asset(assets, &batch.path, &asset));
+    update_root_hash();
+    ASSET_FILES.with_borrow_mut(|files| files.insert(batch.path, asset));
+    Ok(())
+}
+
+/// Deletes the file at the given path. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn delete_asset(path: String) -> Result<(), IssuerError> {
+    check_admin()?;
+    ASSET_FILES
+        .with_borrow_mut(|files| files.remove(&path))
+        .ok_or_else(|| IssuerError::InvalidAsset(format!("Asset {} not found", path)))?;
+    // The certification of an asset cannot be removed, so all assets are certified again
+    init_assets();
+    Ok(())
+}
+
+/// Certifies the stored files. The caller must update the certified data afterwards.
+pub(crate) fn certify_stored_assets(assets: &mut CertifiedAssets) {
+    ASSET_FILES.with_borrow(|files| {
+        for (path, asset) in files.iter() {
+            certify_stored_asset(assets, &path, &asset);
+        }
+    });
+}
+
+fn certify_stored_asset(assets: &mut CertifiedAssets, path: &str, asset: &StoredAsset) {
+    // The path was checked when the batch was created
+    let files = file_assets(path, asset.content.to_vec()).expect("internal: invalid asset path");
+    for mut file in files {
+        if file.content_type == ContentType::HTML && file.encoding == ContentEncoding::Identity {
+            file.content = fixup_html(&String::from_utf8_lossy(&file.content)).into_bytes();
+        }
+        assets.certify_asset(file, &static_headers());
+    }
+}
+
+pub(crate) fn fixup_html(html: &str) -> String {
+    let canister_id = ic_cdk::id();
+
+    // the string we are replacing here is inserted by vite during the front-end build
+    html.replace(
+            r#"<script type="module" crossorigin src="/index.js"></script>"#,
+            &format!(r#"<script data-canister-id="{canister_id}" type="module" crossorigin src="/index.js"></script>"#).to_string()
+        )
+}
+
+/// Concatenates the chunks, which must have the indexes 0 to n - 1.
+fn concat_chunks(chunks: BTreeMap<u32, ByteBuf>) -> Result<Vec<u8>, IssuerError> {
+    let mut content = Vec::new();
+    for (expected, (index, chunk)) in (0..).zip(chunks) {
+        if index != expected {
+            return Err(IssuerError::InvalidAsset(format!(
+                "Chunk {} is missing",
+                expected
+            )));
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content)
+}
+
+fn check_path(path: &str) -> Result<(), IssuerError> {
+    if RESERVED_PATHS
+        .iter()
+        .any(|reserved| path.starts_with(reserved))
+    {
+        return Err(IssuerError::InvalidAsset(format!(
+            "The path {} is reserved",
+            path
+        )));
+    }
+    file_assets(path, vec![])
+        .map(|_| ())
+        .map_err(IssuerError::InvalidAsset)
+}
+
+fn check_admin() -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn batch_not_found(batch_id: u64) -> IssuerError {
+    IssuerError::InvalidAsset(format!("Batch {} not found", batch_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the chunks are concatenated in the order of their indexes
+    #[test]
+    fn test_concat_chunks() {
+        let chunk = |content: &[u8]| ByteBuf::from(content.to_vec());
+        let chunks = BTreeMap::from([(1, chunk(b"def")), (0, chunk(b"abc"))]);
+        assert_eq!(concat_chunks(chunks).unwrap(), b"abcdef".to_vec());
+        assert!(concat_chunks(BTreeMap::new()).unwrap().is_empty());
+
+        let chunks = BTreeMap::from([(0, chunk(b"abc")), (2, chunk(b"ghi"))]);
+        assert!(matches!(
+            concat_chunks(chunks),
+            Err(IssuerError::InvalidAsset(_))
+        ));
+    }
+
+    /// Test that the paths of the generated assets are reserved
+    #[test]
+    fn test_check_path() {
+        assert!(check_path("index.html").is_ok());
+        assert!(check_path("assets/app.js.gz").is_ok());
+        for path in [
+            ".well-known/did.json",
+            "status/issuer/revocation",
+            "/index.html",
+        ] {
+            assert!(matches!(
+                check_path(path),
+                Err(IssuerError::InvalidAsset(_))
+            ));
+        }
+    }
+}
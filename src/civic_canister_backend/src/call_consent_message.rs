@@ -0,0 +1,580 @@
+//! ICRC-21 consent messages for the calls of the canister's own mutating methods, so that admins,
+//! issuers and holders who sign the calls in a wallet see what they consent to instead of a
+//! candid blob.
+//!
+//! The arguments of the call are decoded with the candid types of the method and summarized in
+//! English, e.g. "Add 3 credentials of type VerifiedAdult for principal X". The summary only
+//! depends on the arguments, so that it doesn't reveal any state of the canister. Consent to
+//! share a VC is asked with `vc_consent_message` instead.
+use candid::utils::ArgumentDecoder;
+use candid::{candid_method, decode_args, Principal};
+use ic_cdk_macros::update;
+use identity_core::common::Timestamp;
+use serde_bytes::ByteBuf;
+use std::slice;
+use vc_util::issuer_api::{
+    Icrc21ConsentMessageInfo, Icrc21ConsentMessageMetadata, Icrc21ConsentMessageRequest,
+    Icrc21Error, Icrc21ErrorInfo,
+};
+
+use crate::config::IssuerInit;
+use crate::consent_message::{escape_markdown, ConsentMessage};
+use crate::credential::Credential;
+use crate::credential_type::{CredentialTypeDefinition, LifetimePolicy, SelectionPolicy};
+
+const LANGUAGE: &str = "en";
+const BASE_CREDENTIAL_TYPE: &str = "VerifiableCredential";
+
+/// Get the consent message for a call of one of the canister's mutating methods
+#[update]
+#[candid_method]
+async fn icrc21_canister_call_consent_message(
+    req: Icrc21ConsentMessageRequest,
+) -> Result<Icrc21ConsentMessageInfo, Icrc21Error> {
+    let preferences = req.user_preferences;
+    let utc_offset_minutes = preferences.metadata.utc_offset_minutes;
+    let message = call_consent_message(&req.method, &req.arg, utc_offset_minutes)?;
+    Ok(Icrc21ConsentMessageInfo {
+        consent_message: message.display(preferences.device_spec.as_ref())?,
+        metadata: Icrc21ConsentMessageMetadata {
+            language: message.language,
+            utc_offset_minutes,
+        },
+    })
+}
+
+/// A summary of a call: the title, a sentence and the details
+type Summary = (&'static str, String, Vec<(&'static str, String)>);
+
+/// Decodes the arguments of the call and summarizes it.
+fn call_consent_message(
+    method: &str,
+    arg: &[u8],
+    utc_offset_minutes: Option<i16>,
+) -> Result<ConsentMessage, Icrc21Error> {
+    let (title, sentence, fields): Summary = match method {
+        "add_credentials" => {
+            let (holder, credentials): (Principal, Vec<Credential>) = decode(method, arg)?;
+            let types = credential_types(&credentials);
+            let ids: Vec<&str> = credentials.iter().map(|c| c.id.as_str()).collect();
+            (
+                "Add credentials",
+                format!(
+                    "Add {} of {} for principal {}.",
+                    count(credentials.len(), "credential", "credentials"),
+                    types_text(&types),
+                    holder
+                ),
+                vec![
+                    ("Holder", holder.to_text()),
+                    ("Credentials", ids.join(", ")),
+                    ("Types", types.join(", ")),
+                ],
+            )
+        }
+        "update_credential" => {
+            let (holder, credential_id, credential): (Principal, String, Credential) =
+                decode(method, arg)?;
+            let types = credential_types(slice::from_ref(&credential));
+            let mut fields = vec![
+                ("Holder", holder.to_text()),
+                ("Credential", credential_id.clone()),
+                ("Types", types.join(", ")),
+            ];
+            if let Some(expires_at_ns) = credential.expires_at_ns {
+                fields.push(("Expires", timestamp(expires_at_ns, utc_offset_minutes)));
+            }
+            (
+                "Update credential",
+                format!(
+                    "Replace the credential {} of principal {} by a credential of {}.",
+                    credential_id,
+                    holder,
+                    types_text(&types)
+                ),
+                fields,
+            )
+        }
+        "remove_credential" => {
+            let (holder, credential_id): (Principal, String) = decode(method, arg)?;
+            (
+                "Remove credential",
+                format!(
+                    "Remove the credential {} of principal {} and revoke the VCs issued from it.",
+                    credential_id, holder
+                ),
+                vec![("Holder", holder.to_text()), ("Credential", credential_id)],
+            )
+        }
+        "set_credential_blocked" => {
+            let (credential_id, blocked): (String, bool) = decode(method, arg)?;
+            let (title, sentence) = if blocked {
+                ("Block credential", "Block the sharing of your credential")
+            } else {
+                ("Unblock credential", "Allow the sharing of your credential")
+            };
+            (
+                title,
+                format!("{} {}.", sentence, credential_id),
+                vec![("Credential", credential_id)],
+            )
+        }
+        "set_credential_suspended" => {
+            let (holder, credential_id, suspended): (Principal, String, bool) =
+                decode(method, arg)?;
+            let (title, verb) = if suspended {
+                ("Suspend credential", "Suspend")
+            } else {
+                ("Reinstate credential", "Reinstate")
+            };
+            (
+                title,
+                format!(
+                    "{} the credential {} of principal {} and the VCs issued from it.",
+                    verb, credential_id, holder
+                ),
+                vec![("Holder", holder.to_text()), ("Credential", credential_id)],
+            )
+        }
+        "migrate_credentials" => {
+            let (from, to): (Principal, Principal) = decode(method, arg)?;
+            (
+                "Migrate credentials",
+                format!(
+                    "Move the credentials of principal {} to principal {}.",
+                    from, to
+                ),
+                vec![("From", from.to_text()), ("To", to.to_text())],
+            )
+        }
+        "approve_credential_migration" => {
+            let (to,): (Principal,) = decode(method, arg)?;
+            (
+                "Approve credential migration",
+                format!("Allow principal {} to claim all of your credentials.", to),
+                vec![("To", to.to_text())],
+            )
+        }
+        "complete_credential_migration" => {
+            let (from,): (Principal,) = decode(method, arg)?;
+            (
+                "Complete credential migration",
+                format!("Claim all credentials of principal {}.", from),
+                vec![("From", from.to_text())],
+            )
+        }
+        "request_credential" => {
+            let (credential_type, evidence_refs): (String, Vec<String>) = decode(method, arg)?;
+            (
+                "Request credential",
+                format!(
+                    "Request a credential of type {} with {}.",
+                    credential_type,
+                    count(
+                        evidence_refs.len(),
+                        "evidence reference",
+                        "evidence references"
+                    )
+                ),
+                vec![
+                    ("Type", credential_type),
+                    ("Evidence", evidence_refs.join(", ")),
+                ],
+            )
+        }
+        "approve_credential_request" => {
+            let (request_id, credential): (u64, Credential) = decode(method, arg)?;
+            let types = credential_types(slice::from_ref(&credential));
+            let mut fields = vec![
+                ("Request", request_id.to_string()),
+                ("Types", types.join(", ")),
+            ];
+            if let Some(expires_at_ns) = credential.expires_at_ns {
+                fields.push(("Expires", timestamp(expires_at_ns, utc_offset_minutes)));
+            }
+            (
+                "Approve credential request",
+                format!(
+                    "Approve the credential request {} with a credential of {}.",
+                    request_id,
+                    types_text(&types)
+                ),
+                fields,
+            )
+        }
+        "reject_credential_request" => {
+            let (request_id, reason): (u64, String) = decode(method, arg)?;
+            (
+                "Reject credential request",
+                format!("Reject the credential request {}.", request_id),
+                vec![("Request", request_id.to_string()), ("Reason", reason)],
+            )
+        }
+        "set_credential_type" => {
+            let (definition,): (CredentialTypeDefinition,) = decode(method, arg)?;
+            (
+                "Set credential type",
+                format!("Add or replace the credential type {}.", definition.name),
+                vec![
+                    ("Type", definition.name),
+                    ("Accepted types", definition.accepted_types.join(", ")),
+                    (
+                        "Disclosable claims",
+                        definition.disclosable_claims.join(", "),
+                    ),
+                ],
+            )
+        }
+        "remove_credential_type" => {
+            let (name,): (String,) = decode(method, arg)?;
+            (
+                "Remove credential type",
+                format!("Remove the credential type {} from the registry.", name),
+                vec![("Type", name)],
+            )
+        }
+        "set_credential_lifetime_policy" => {
+            let (name, policy): (String, LifetimePolicy) = decode(method, arg)?;
+            (
+                "Set lifetime policy",
+                format!("Set the lifetime of the VCs of type {}.", name),
+                vec![
+                    ("Type", name),
+                    ("Minimum lifetime", duration(policy.min_ns)),
+                    ("Maximum lifetime", duration(policy.max_ns)),
+                    ("Default lifetime", duration(policy.default_ns)),
+                ],
+            )
+        }
+        "set_credential_selection_policy" => {
+            let (name, policy): (String, SelectionPolicy) = decode(method, arg)?;
+            let issuers: Vec<String> = policy
+                .preferred_issuers
+                .iter()
+                .map(Principal::to_text)
+                .collect();
+            let only = if policy.preferred_issuers_only {
+                "Yes"
+            } else {
+                "No"
+            };
+            (
+                "Set selection policy",
+                format!(
+                    "Set the issuers whose credentials are used for the VCs of type {}.",
+                    name
+                ),
+                vec![
+                    ("Type", name),
+                    ("Preferred issuers", issuers.join(", ")),
+                    ("Preferred issuers only", only.to_string()),
+                ],
+            )
+        }
+        "set_consent_template" => {
+            let (name, language, template): (String, String, Option<String>) = decode(method, arg)?;
+            let (title, verb) = match template {
+                Some(_) => ("Set consent template", "Set"),
+                None => ("Remove consent template", "Remove"),
+            };
+            (
+                title,
+                format!(
+                    "{} the consent message in {} of the credential type {}.",
+                    verb, language, name
+                ),
+                vec![("Type", name), ("Language", language)],
+            )
+        }
+        "rotate_signing_key" => {
+            let () = decode(method, arg)?;
+            (
+                "Rotate signing key",
+                "Replace the key that signs the VCs by a new one.".to_string(),
+                vec![],
+            )
+        }
+        "add_issuer" => {
+            let (issuer,): (Principal,) = decode(method, arg)?;
+            (
+                "Add issuer",
+                format!("Authorize principal {} to issue credentials.", issuer),
+                vec![("Issuer", issuer.to_text())],
+            )
+        }
+        "remove_issuer" => {
+            let (issuer,): (Principal,) = decode(method, arg)?;
+            (
+                "Remove issuer",
+                format!(
+                    "Revoke the authorization of principal {} to issue credentials.",
+                    issuer
+                ),
+                vec![("Issuer", issuer.to_text())],
+            )
+        }
+        "set_derivation_origin" => {
+            let (hostname, origin): (String, Option<String>) = decode(method, arg)?;
+            match origin {
+                Some(origin) => (
+                    "Allow frontend",
+                    format!(
+                        "Allow the frontend {} to use the derivation origin {}.",
+                        hostname, origin
+                    ),
+                    vec![("Frontend", hostname), ("Derivation origin", origin)],
+                ),
+                None => (
+                    "Remove frontend",
+                    format!("Remove the frontend {} from the allowlist.", hostname),
+                    vec![("Frontend", hostname)],
+                ),
+            }
+        }
+        "add_alternative_origin" => {
+            let (origin,): (String,) = decode(method, arg)?;
+            (
+                "Add alternative origin",
+                format!("Allow {} to use the issuer as derivation origin.", origin),
+                vec![("Origin", origin)],
+            )
+        }
+        "remove_alternative_origin" => {
+            let (origin,): (String,) = decode(method, arg)?;
+            (
+                "Remove alternative origin",
+                format!(
+                    "Revoke the permission of {} to use the issuer as derivation origin.",
+                    origin
+                ),
+                vec![("Origin", origin)],
+            )
+        }
+        "create_asset_batch" => {
+            let (path,): (String,) = decode(method, arg)?;
+            (
+                "Upload file",
+                format!(
+                    "Start the upload of the file {} of the management app.",
+                    path
+                ),
+                vec![("File", path)],
+            )
+        }
+        "upload_asset_chunk" => {
+            let (batch_id, index, content): (u64, u32, ByteBuf) = decode(method, arg)?;
+            (
+                "Upload file chunk",
+                format!("Upload chunk {} of upload {}.", index, batch_id),
+                vec![
+                    ("Upload", batch_id.to_string()),
+                    ("Chunk", index.to_string()),
+                    ("Size", count(content.len(), "byte", "bytes")),
+                ],
+            )
+        }
+        "commit_asset_batch" => {
+            let (batch_id, sha256): (u64, ByteBuf) = decode(method, arg)?;
+            (
+                "Publish file",
+                format!(
+                    "Publish the file of upload {} in the management app.",
+                    batch_id
+                ),
+                vec![
+                    ("Upload", batch_id.to_string()),
+                    ("SHA-256", hex::encode(sha256)),
+                ],
+            )
+        }
+        "delete_asset" => {
+            let (path,): (String,) = decode(method, arg)?;
+            (
+                "Delete file",
+                format!("Delete the file {} of the management app.", path),
+                vec![("File", path)],
+            )
+        }
+        "configure" => {
+            let (config,): (IssuerInit,) = decode(method, arg)?;
+            let principals = |principals: &[Principal]| {
+                let texts: Vec<String> = principals.iter().map(Principal::to_text).collect();
+                texts.join(", ")
+            };
+            (
+                "Configure issuer",
+                "Replace the configuration of the issuer canister.".to_string(),
+                vec![
+                    ("Admin", config.admin.to_text()),
+                    ("Authorized issuers", principals(&config.authorized_issuers)),
+                    ("Derivation origin", config.derivation_origin.clone()),
+                    ("Frontend hostname", config.frontend_hostname),
+                    (
+                        "VC issuer",
+                        config.vc_issuer.unwrap_or(config.derivation_origin),
+                    ),
+                    ("II canisters", principals(&config.idp_canister_ids)),
+                ],
+            )
+        }
+        "prepare_credential" => {
+            return Err(Icrc21Error::UnsupportedCanisterCall(Icrc21ErrorInfo {
+                description: "Consent to the issuance of a VC is given with vc_consent_message"
+                    .to_string(),
+            }));
+        }
+        _ => {
+            return Err(Icrc21Error::UnsupportedCanisterCall(Icrc21ErrorInfo {
+                description: format!("No consent message for method {}", method),
+            }));
+        }
+    };
+    Ok(ConsentMessage {
+        language: LANGUAGE.to_string(),
+        text: format!("# {}\n\n{}", title, escape_markdown(&sentence)),
+        fields_heading: "Details".to_string(),
+        fields: fields
+            .into_iter()
+            .map(|(label, value)| (label.to_string(), Some(value)))
+            .collect(),
+    })
+}
+
+fn decode<'a, T: ArgumentDecoder<'a>>(method: &str, arg: &'a [u8]) -> Result<T, Icrc21Error> {
+    decode_args(arg).map_err(|err| {
+        Icrc21Error::UnsupportedCanisterCall(Icrc21ErrorInfo {
+            description: format!("Invalid arguments for method {}: {}", method, err),
+        })
+    })
+}
+
+/// The types of the credentials without the base type
+fn credential_types(credentials: &[Credential]) -> Vec<String> {
+    let mut types: Vec<String> = Vec::new();
+    for type_ in credentials.iter().flat_map(|credential| &credential.type_) {
+        if type_ != BASE_CREDENTIAL_TYPE && !types.contains(type_) {
+            types.push(type_.clone());
+        }
+    }
+    types
+}
+
+fn types_text(types: &[String]) -> String {
+    match types.len() {
+        0 => "no specific type".to_string(),
+        1 => format!("type {}", types[0]),
+        _ => format!("types {}", types.join(", ")),
+    }
+}
+
+fn count(n: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+fn duration(ns: u64) -> String {
+    format!("{} s", ns / 1_000_000_000)
+}
+
+/// Shows the time in the UTC offset of the user, e.g. "2024-05-17 14:30 UTC+02:00".
+fn timestamp(ns: u64, utc_offset_minutes: Option<i16>) -> String {
+    let offset = utc_offset_minutes.unwrap_or(0) as i64;
+    let local = Timestamp::from_unix((ns / 1_000_000_000) as i64 + offset * 60)
+        .map(|timestamp| timestamp.to_rfc3339())
+        .unwrap_or_default();
+    let (date, time) = local.split_at(local.find('T').unwrap_or(local.len()));
+    let time = time.get(1..6).unwrap_or_default();
+    if offset == 0 {
+        return format!("{} {} UTC", date, time);
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "{} {} UTC{}{:02}:{:02}",
+        date,
+        time,
+        sign,
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::encode_args;
+
+    fn credential(id: &str, type_: &str) -> Credential {
+        Credential {
+            id: id.to_string(),
+            type_: vec![BASE_CREDENTIAL_TYPE.to_string(), type_.to_string()],
+            context: vec![],
+            claim: vec![],
+            expires_at_ns: None,
+        }
+    }
+
+    /// Test that the arguments of add_credentials are summarized
+    #[test]
+    fn test_add_credentials_message() {
+        let holder = Principal::anonymous();
+        let credentials = vec![
+            credential("1", "VerifiedAdult"),
+            credential("2", "VerifiedAdult"),
+            credential("3", "VerifiedAdult"),
+        ];
+        let arg = encode_args((holder, credentials)).unwrap();
+        let message = call_consent_message("add_credentials", &arg, None).unwrap();
+        assert_eq!(message.language, "en");
+        assert_eq!(
+            message.text,
+            "# Add credentials\n\n\
+            Add 3 credentials of type VerifiedAdult for principal 2vxsx\\-fae\\."
+        );
+        assert_eq!(
+            message.fields,
+            vec![
+                ("Holder".to_string(), Some("2vxsx-fae".to_string())),
+                ("Credentials".to_string(), Some("1, 2, 3".to_string())),
+                ("Types".to_string(), Some("VerifiedAdult".to_string())),
+            ]
+        );
+    }
+
+    /// Test that the expiration is shown in the UTC offset of the user
+    #[test]
+    fn test_update_credential_message() {
+        let credential = Credential {
+            expires_at_ns: Some(1_715_956_200_000_000_000),
+            ..credential("1", "VerifiedResident")
+        };
+        let arg = encode_args((Principal::anonymous(), "1", credential)).unwrap();
+        let message = call_consent_message("update_credential", &arg, Some(120)).unwrap();
+        assert_eq!(
+            message.fields.last(),
+            Some(&(
+                "Expires".to_string(),
+                Some("2024-05-17 16:30 UTC+02:00".to_string())
+            ))
+        );
+    }
+
+    /// Test that unknown methods and invalid arguments are unsupported calls
+    #[test]
+    fn test_unsupported_calls() {
+        let arg = encode_args((Principal::anonymous(),)).unwrap();
+        for method in ["get_admin", "prepare_credential", "add_credentials"] {
+            assert!(matches!(
+                call_consent_message(method, &arg, None),
+                Err(Icrc21Error::UnsupportedCanisterCall(_))
+            ));
+        }
+        assert!(call_consent_message("add_issuer", &arg, None).is_ok());
+    }
+
+    /// Test that the time is shifted by the UTC offset
+    #[test]
+    fn test_timestamp() {
+        let ns = 1_715_956_200_000_000_000;
+        assert_eq!(timestamp(ns, None), "2024-05-17 14:30 UTC");
+        assert_eq!(timestamp(ns, Some(-90)), "2024-05-17 13:00 UTC-01:30");
+    }
+}
@@ -6,18 +6,26 @@
 //! - Managing assets and their certification.
 //! - Handling HTTP requests with CORS support.
 
-use crate::credential::{update_root_hash, CredentialList, CANISTER_SIG_SEED};
+use crate::alternative_origins::{certify_alternative_origins, init_alternative_origins};
+use crate::assets::{certify_stored_assets, fixup_html, AssetBatches, StoredAsset};
+use crate::credential::{update_root_hash, CredentialList};
+use crate::credential_request::{init_request_indexes, CredentialRequest};
+use crate::credential_type::{init_credential_types, CredentialTypeDefinition};
+use crate::did_document::certify_did_document;
+use crate::history::IssuanceHistory;
+use crate::migration::{MigrationRecord, PendingMigration};
+use crate::signing_key::{restore_signatures, schedule_signing_seed, SigningKeys};
+use crate::status_list::{certify_all_status_lists, StatusEntry, StatusList};
 use asset_util::{collect_assets, CertifiedAssets};
 use candid::{candid_method, CandidType, Deserialize, Principal};
 use canister_sig_util::signature_map::{SignatureMap, LABEL_SIG};
 use canister_sig_util::{extract_raw_root_pk_from_der, IC_ROOT_PK_DER};
-use ic_cdk::api;
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use ic_certification::{labeled_hash, pruned};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory}, writer::Writer, Memory,
-    DefaultMemoryImpl, StableBTreeMap, StableCell, StableVec, Storable,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
 use include_dir::{include_dir, Dir};
 use serde::Serialize;
@@ -31,14 +39,58 @@ const PROD_II_CANISTER_ID: &str = "rdmx6-jaaaa-aaaaa-aaadq-cai";
 
 // A memory for config, where data from the heap can be serialized/deserialized.
 const CONF: MemoryId = MemoryId::new(0);
-// A memory for Signatures, where data from the heap can be serialized/deserialized.
-const SIG: MemoryId = MemoryId::new(1);
+// Memory 1 held all message hashes ever signed, it has been replaced by SIG_HASHES.
 
 // A memory for the Credential data
 const CREDENTIAL: MemoryId = MemoryId::new(2);
 
 const UPGRADES: MemoryId = MemoryId::new(3);
 
+// A memory for the issuance history of each holder
+const HISTORY: MemoryId = MemoryId::new(4);
+
+// A memory for the audit log of credential migrations
+const MIGRATIONS: MemoryId = MemoryId::new(5);
+
+// A memory for the credential requests of holders
+const REQUESTS: MemoryId = MemoryId::new(6);
+
+// A memory for the registry of credential types
+const TYPES: MemoryId = MemoryId::new(7);
+
+// A memory for the key that authenticates prepared contexts
+const CONTEXT: MemoryId = MemoryId::new(8);
+
+// A memory for the seeds of the canister signatures
+const KEYS: MemoryId = MemoryId::new(9);
+
+// A memory for the message hashes of the unexpired signatures
+const SIG_HASHES: MemoryId = MemoryId::new(10);
+
+// A memory for the status lists of the issued VCs
+const STATUS: MemoryId = MemoryId::new(11);
+
+// A memory for the VCs that the indexes of the status lists were handed out for
+const STATUS_INDEXES: MemoryId = MemoryId::new(12);
+
+// A memory for the allowlist of frontend hostnames and their derivation origins
+const ORIGINS: MemoryId = MemoryId::new(13);
+// A memory for the alternative origins served at `/.well-known/ii-alternative-origins`
+const II_ORIGINS: MemoryId = MemoryId::new(14);
+// A memory for the files of the management app uploaded at runtime
+const FILES: MemoryId = MemoryId::new(15);
+// A memory for the migrations approved by the old principal of a holder
+const PENDING: MemoryId = MemoryId::new(16);
+// Memories for the indexes of the credential requests by holder, of the pending requests and of
+// the processed requests by processing time
+const REQUESTS_BY_HOLDER: MemoryId = MemoryId::new(17);
+const REQUESTS_PENDING: MemoryId = MemoryId::new(18);
+const REQUESTS_PROCESSED: MemoryId = MemoryId::new(19);
+// A memory for the next credential request id, so that the ids of pruned requests are not reused
+const REQUEST_IDS: MemoryId = MemoryId::new(20);
+// A memory for whether the alternative origins were seeded from the embedded file
+const II_ORIGINS_SEEDED: MemoryId = MemoryId::new(21);
+
 type ConfigCell = StableCell<IssuerConfig, VirtualMemory<DefaultMemoryImpl>>;
 
 thread_local! {
@@ -54,21 +106,144 @@ thread_local! {
         )
     );
    
-    // Stable vector to restore the signatures when the canister is upgraded
-    pub(crate) static MSG_HASHES: RefCell<StableVec<[u8; 32], VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
-        StableVec::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(SIG))
-        ).expect("failed to initialize stable vector")
+    pub(crate) static ISSUANCE_HISTORY: RefCell<StableBTreeMap<Principal, IssuanceHistory, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HISTORY))
+        )
+    );
+
+    // Audit log of the credentials moved between principals, keyed by sequence number
+    pub(crate) static MIGRATION_LOG: RefCell<StableBTreeMap<u64, MigrationRecord, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MIGRATIONS))
+        )
+    );
+
+    // Credential requests created by holders, keyed by request id
+    pub(crate) static CREDENTIAL_REQUESTS: RefCell<StableBTreeMap<u64, CredentialRequest, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS))
+        )
+    );
+
+    // Ids of the credential requests of each holder
+    pub(crate) static HOLDER_REQUESTS: RefCell<StableBTreeMap<(Principal, u64), (), VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_BY_HOLDER))
+        )
+    );
+
+    // Ids of the pending credential requests
+    pub(crate) static PENDING_REQUESTS: RefCell<StableBTreeMap<u64, (), VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_PENDING))
+        )
+    );
+
+    // Ids of the processed credential requests, keyed by the time they were processed
+    pub(crate) static PROCESSED_REQUESTS: RefCell<StableBTreeMap<(u64, u64), (), VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUESTS_PROCESSED))
+        )
+    );
+
+    // Id of the next credential request
+    pub(crate) static NEXT_REQUEST_ID: RefCell<StableCell<u64, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REQUEST_IDS)),
+            0
+        ).expect("failed to initialize stable cell")
+    );
+
+    // Registry of the credential types that can be issued, keyed by type name
+    pub(crate) static CREDENTIAL_TYPES: RefCell<StableBTreeMap<String, CredentialTypeDefinition, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TYPES))
+        )
+    );
+
+    // Secret key to authenticate the prepared contexts, empty until the first VC is prepared
+    pub(crate) static CONTEXT_KEY: RefCell<StableCell<Vec<u8>, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CONTEXT)),
+            Vec::new()
+        ).expect("failed to initialize stable cell")
+    );
+
+    // Current and previous seeds of the canister signatures
+    pub(crate) static SIGNING_KEYS: RefCell<StableCell<SigningKeys, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(KEYS)),
+            SigningKeys::default()
+        ).expect("failed to initialize stable cell")
+    );
+
+    // Migrations approved by the old principal that wait to be completed by the new principal, keyed by the old principal
+    pub(crate) static PENDING_MIGRATIONS: RefCell<StableBTreeMap<Principal, PendingMigration, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING))
+        )
+    );
+
+    // Message hashes of the signatures keyed by expiry, to restore the signatures when the canister is upgraded.
+    // The value is the id of the signing seed.
+    pub(crate) static MSG_HASHES: RefCell<StableBTreeMap<(u64, [u8; 32]), u32, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SIG_HASHES))
+        )
+    );
+
+    // Revocation and suspension status lists of the issued VCs, keyed by issuer
+    pub(crate) static STATUS_LISTS: RefCell<StableBTreeMap<Principal, StatusList, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STATUS))
+        )
+    );
+
+    // The VC each index of the status lists was handed out for, keyed by issuer and index
+    pub(crate) static STATUS_ENTRIES: RefCell<StableBTreeMap<(Principal, u32), StatusEntry, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STATUS_INDEXES))
+        )
+    );
+
+    // Derivation origins of the frontend hostnames other than the configured one, keyed by hostname
+    pub(crate) static DERIVATION_ORIGINS: RefCell<StableBTreeMap<String, String, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ORIGINS))
+        )
+    );
+
+    // The alternative origins of the issuer
+    pub(crate) static ALTERNATIVE_ORIGINS: RefCell<StableBTreeMap<String, (), VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(II_ORIGINS))
+        )
+    );
+
+    // Whether the alternative origins were seeded, so that origins the admin removed are not seeded again
+    pub(crate) static ALTERNATIVE_ORIGINS_SEEDED: RefCell<StableCell<bool, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(II_ORIGINS_SEEDED)),
+            false
+        ).expect("failed to initialize stable cell")
+    );
+
+    // The files of the management app, keyed by path
+    pub(crate) static ASSET_FILES: RefCell<StableBTreeMap<String, StoredAsset, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FILES))
+        )
     );
     // Lookup table for the url fields to compress repeated information inside the credentials
     pub(crate) static LOOKUP_TABLE: RefCell<LookupTable> = RefCell::new(LookupTable::new());
     
     // Assets for the management app
     pub(crate) static ASSETS: RefCell<CertifiedAssets> = RefCell::new(CertifiedAssets::default());
-}
 
-#[cfg(target_arch = "wasm32")]
-use ic_cdk::println;
+    // Uploads of files of the management app in progress
+    pub(crate) static ASSET_BATCHES: RefCell<AssetBatches> = RefCell::new(AssetBatches::default());
+}
 
 /// Configuration for the canister.
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -81,6 +256,8 @@ pub(crate) struct IssuerConfig {
     derivation_origin: String,
     /// Frontend hostname to be used by the issuer.
     frontend_hostname: String,
+    /// URL or DID used as the issuer (`iss`) of the VCs.
+    vc_issuer: Option<String>,
     // Admin who can add authorized issuers
     admin: Principal,
     // List of authorized issuers who can issue credentials
@@ -106,6 +283,7 @@ impl Default for IssuerConfig {
             idp_canister_ids: vec![Principal::from_text(PROD_II_CANISTER_ID).unwrap()],
             derivation_origin: derivation_origin.clone(),
             frontend_hostname: derivation_origin,
+            vc_issuer: None,
             admin: ic_cdk::api::caller(),
             authorized_issuers: vec![ic_cdk::api::caller()],
         }
@@ -120,6 +298,7 @@ impl From<IssuerInit> for IssuerConfig {
             idp_canister_ids: init.idp_canister_ids,
             derivation_origin: init.derivation_origin,
             frontend_hostname: init.frontend_hostname,
+            vc_issuer: init.vc_issuer,
             admin: init.admin,
             authorized_issuers: init.authorized_issuers,
         }
@@ -137,6 +316,8 @@ pub struct IssuerInit {
     pub derivation_origin: String,
     /// Frontend hostname be used by the issuer.
     pub frontend_hostname: String,
+    /// URL or DID used as the issuer (`iss`) of the VCs, defaults to the derivation origin.
+    pub vc_issuer: Option<String>,
     /// Admin who can add authorized issuers
     pub admin: Principal,
     /// List of authorized issuers who can issue credentials
@@ -158,7 +339,11 @@ fn init(init_arg: Option<IssuerInit>) {
                 .expect("Failed to initialize config");
         });
     }
+    init_credential_types();
+    init_request_indexes();
+    init_alternative_origins();
     init_assets();
+    schedule_signing_seed();
 }
 
 #[update]
@@ -216,6 +401,10 @@ fn get_admin() -> Principal {
     })
 }
 
+pub(crate) fn is_admin(caller: Principal) -> bool {
+    CONFIG.with(|config_cell| config_cell.borrow().get().admin == caller)
+}
+
 // A pre-upgrade hook for serializing the data stored on the heap.
 #[pre_upgrade]
 fn pre_upgrade() {
@@ -241,15 +430,8 @@ fn post_upgrade(init_arg: Option<IssuerInit>) {
     // Initialize the CONFIG
     init(init_arg);
 
-    // Restore the signatures
-    SIGNATURES.with(|sigs| {
-        let mut sigs = sigs.borrow_mut();
-        MSG_HASHES.with(|hashes| {
-            hashes.borrow().iter().for_each(|hash| {
-                sigs.add_signature(&CANISTER_SIG_SEED, hash);
-            })
-        });
-    });
+    // Restore the signatures that have not expired yet
+    restore_signatures();
 
     update_root_hash();
 
@@ -277,14 +459,72 @@ fn post_upgrade(init_arg: Option<IssuerInit>) {
 #[candid_method]
 fn configure(config: IssuerInit) {
     apply_config(config);
+    // The URLs of the status lists and the DID document depend on the derivation origin
+    init_assets();
 }
 
 fn apply_config(init: IssuerInit) {
+    if let Some(vc_issuer) = &init.vc_issuer {
+        if let Err(err) = verify_vc_issuer(vc_issuer, &init.derivation_origin) {
+            ic_cdk::trap(&format!("Invalid VC issuer: {}", err));
+        }
+    }
     CONFIG
         .with_borrow_mut(|config_cell| config_cell.set(IssuerConfig::from(init)))
         .expect("failed to apply issuer config");
 }
 
+/// Get the issuer (`iss`) of the VCs.
+pub(crate) fn vc_issuer() -> String {
+    CONFIG.with_borrow(|config| {
+        let config = config.get();
+        config
+            .vc_issuer
+            .clone()
+            .unwrap_or_else(|| config.derivation_origin.clone())
+    })
+}
+
+/// Get the configured derivation origin, which the assets of the issuer are served at.
+pub(crate) fn configured_derivation_origin() -> String {
+    CONFIG.with_borrow(|config| {
+        config
+            .get()
+            .derivation_origin
+            .trim_end_matches('/')
+            .to_string()
+    })
+}
+
+/// Checks that the VC issuer belongs to the derivation origin, so that relying parties can
+/// match the issuer of the VCs with the origin they requested them from: a URL must be the origin
+/// or a path below it and a DID must be the `did:web` DID of the origin's host or a path below it.
+fn verify_vc_issuer(vc_issuer: &str, derivation_origin: &str) -> Result<(), String> {
+    let origin = derivation_origin.trim_end_matches('/');
+    if vc_issuer.starts_with("did:") {
+        let host = origin
+            .split_once("://")
+            .map(|(_, host)| host)
+            .ok_or_else(|| format!("invalid derivation origin {}", derivation_origin))?;
+        return match vc_issuer.strip_prefix(&did_web_of_host(host)) {
+            Some(path) if path.is_empty() || path.starts_with(':') => Ok(()),
+            _ => Err(format!("{} is not the did:web DID of {}", vc_issuer, origin)),
+        };
+    }
+    let url = vc_issuer.trim_end_matches('/');
+    if url == origin || url.starts_with(&format!("{}/", origin)) {
+        Ok(())
+    } else {
+        Err(format!("{} is not below the derivation origin {}", vc_issuer, origin))
+    }
+}
+
+/// Get the `did:web` DID of the host of an origin.
+pub(crate) fn did_web_of_host(host: &str) -> String {
+    // The port of the host is percent-encoded in a did:web DID
+    format!("did:web:{}", host.replace(':', "%3A"))
+}
+
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct LookupTable {
     map: HashMap<u16, (String, Vec<String>)>,
@@ -318,7 +558,7 @@ impl LookupTable {
     }
 }
 
-fn static_headers() -> Vec<HeaderField> {
+pub(crate) fn static_headers() -> Vec<HeaderField> {
     vec![("Access-Control-Allow-Origin".to_string(), "*".to_string())]
 }
 
@@ -330,22 +570,18 @@ pub fn init_assets() {
             collect_assets(&ASSET_DIR, Some(fixup_html)),
             &static_headers(),
         );
+        // The uploaded files and the status lists are kept in stable memory and have to be
+        // certified again, the uploaded files override the embedded ones
+        certify_stored_assets(assets);
+        certify_all_status_lists(assets);
+        certify_did_document(assets);
+        certify_alternative_origins(assets);
     });
 
     update_root_hash()
 }
 pub type HeaderField = (String, String);
 
-fn fixup_html(html: &str) -> String {
-    let canister_id = api::id();
-
-    // the string we are replacing here is inserted by vite during the front-end build
-    html.replace(
-            r#"<script type="module" crossorigin src="/index.js"></script>"#,
-            &format!(r#"<script data-canister-id="{canister_id}" type="module" crossorigin src="/index.js"></script>"#).to_string()
-        )
-}
-
 /// Get the derivation origin used by the canister
 #[update]
 #[candid_method]
@@ -355,20 +591,69 @@ async fn derivation_origin(
     get_derivation_origin(&req.frontend_hostname)
 }
 
+/// Looks up the derivation origin of the configured frontend hostname or of the allowlist.
 fn get_derivation_origin(hostname: &str) -> Result<DerivationOriginData, DerivationOriginError> {
-    CONFIG.with_borrow(|config| {
+    let hostname = normalize_origin(hostname).map_err(DerivationOriginError::UnsupportedOrigin)?;
+    let configured = CONFIG.with_borrow(|config| {
         let config = config.get();
+        let configured = normalize_origin(&config.frontend_hostname).is_ok_and(|h| h == hostname);
+        configured.then(|| config.derivation_origin.clone())
+    });
+    configured
+        .or_else(|| DERIVATION_ORIGINS.with_borrow(|origins| origins.get(&hostname)))
+        .map(|origin| DerivationOriginData { origin })
+        .ok_or_else(|| {
+            DerivationOriginError::UnsupportedOrigin(format!(
+                "Frontend hostname {} is not allowed",
+                hostname
+            ))
+        })
+}
 
-        // We don't currently rely on the value provided, so if it doesn't match
-        // we just print a warning
-        if hostname != config.frontend_hostname {
-            println!("*** achtung! bad frontend hostname {}", hostname,);
+/// Adds a frontend hostname with its derivation origin to the allowlist, or removes it if no
+/// derivation origin is given. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn set_derivation_origin(
+    frontend_hostname: String,
+    derivation_origin: Option<String>,
+) -> Result<(), IssuerError> {
+    if !is_admin(ic_cdk::api::caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    let hostname = normalize_origin(&frontend_hostname).map_err(IssuerError::UnsupportedOrigin)?;
+    match derivation_origin {
+        Some(origin) => {
+            let origin = normalize_origin(&origin).map_err(IssuerError::UnsupportedOrigin)?;
+            DERIVATION_ORIGINS.with_borrow_mut(|origins| origins.insert(hostname, origin));
         }
+        None => {
+            DERIVATION_ORIGINS.with_borrow_mut(|origins| origins.remove(&hostname));
+        }
+    }
+    Ok(())
+}
 
-        Ok(DerivationOriginData {
-            origin: config.derivation_origin.clone(),
-        })
-    })
+/// Retrieves the allowlist of frontend hostnames and their derivation origins.
+#[query]
+#[candid_method(query)]
+fn get_derivation_origins() -> Vec<(String, String)> {
+    DERIVATION_ORIGINS.with_borrow(|origins| origins.iter().collect())
+}
+
+/// Normalizes an origin (e.g. "https://Example.com/" to "https://example.com") and checks that it
+/// has no path.
+pub(crate) fn normalize_origin(origin: &str) -> Result<String, String> {
+    let normalized = origin.trim().trim_end_matches('/').to_lowercase();
+    let host = normalized
+        .strip_prefix("https://")
+        .or_else(|| normalized.strip_prefix("http://"));
+    match host {
+        Some(host) if !host.is_empty() && !host.contains(['/', '?', '#']) => Ok(normalized),
+        _ => Err(format!("{} is not an origin", origin)),
+    }
 }
 
 /// Handle HTTP requests with CORS support.
@@ -418,3 +703,62 @@ pub struct HttpResponse {
 }
 
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: &str = "https://civic.example.com";
+
+    /// Test that only URLs and DIDs of the derivation origin are accepted as VC issuer
+    #[test]
+    fn test_verify_vc_issuer() {
+        for vc_issuer in [
+            "https://civic.example.com",
+            "https://civic.example.com/",
+            "https://civic.example.com/issuers/kyc",
+            "did:web:civic.example.com",
+            "did:web:civic.example.com:issuers:kyc",
+        ] {
+            assert_eq!(verify_vc_issuer(vc_issuer, ORIGIN), Ok(()), "{}", vc_issuer);
+        }
+        for vc_issuer in [
+            "https://civic.example.com.evil.com",
+            "https://civic.example.community",
+            "http://civic.example.com",
+            "did:web:civic.example.community",
+            "did:icp:v0:aaaaa-aa",
+        ] {
+            assert!(
+                verify_vc_issuer(vc_issuer, ORIGIN).is_err(),
+                "{}",
+                vc_issuer
+            );
+        }
+        assert_eq!(
+            verify_vc_issuer("did:web:localhost%3A8080", "http://localhost:8080/"),
+            Ok(())
+        );
+    }
+
+    /// Test that origins are normalized and that paths are rejected
+    #[test]
+    fn test_normalize_origin() {
+        assert_eq!(
+            normalize_origin(" https://Staging.Example.com/ "),
+            Ok("https://staging.example.com".to_string())
+        );
+        assert_eq!(
+            normalize_origin("http://localhost:8080"),
+            Ok("http://localhost:8080".to_string())
+        );
+        for origin in [
+            "staging.example.com",
+            "https://",
+            "https://example.com/app",
+            "ftp://a",
+        ] {
+            assert!(normalize_origin(origin).is_err(), "{}", origin);
+        }
+    }
+}
@@ -1,77 +1,124 @@
 //! Handles consent messages that are displayed to the user when they are asked to consent to the sharing of a VC by the Civic Canister.
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+//!
+//! The consent message templates are part of the credential type registry (see `credential_type`)
+//! and are managed by the admin per credential type and BCP-47 language tag. The placeholders of a
+//! template are filled with the spec arguments and the rendered message lists what goes into the
+//! VC: the arguments and the stored claims to disclose, with localized labels and, where the
+//! credential type allows it, a masked preview of the holder's values.
+//!
+//! The consent message is not requested by the holder itself, but by II or the wallet. The stored
+//! credentials are keyed by the holder's principal for the issuer (`id_dapp`), so the holder is
+//! taken from the signed ID alias of the request, as in `prepare_credential`. Without an ID alias
+//! the message has no previews and a blocked credential is only refused when the VC is prepared.
+//!
+//! II gets the message as markdown from `vc_consent_message`. Wallets that implement the ICRC-21
+//! display modes get it from `icrc21_vc_consent_message`, broken into pages for line displays or
+//! as intent and fields for fields displays.
 use candid::candid_method;
+use ic_cdk::api::time;
 use ic_cdk_macros::update;
-use lazy_static::lazy_static;
-use crate::credential::{SupportedCredentialType, verify_credential_spec};
+use std::ops::Range;
+use crate::credential::{
+    id_alias_holder, is_credential_type_blocked, selected_claims, verify_credential_spec, Claim,
+    ClaimValue,
+};
+use crate::credential_type::CredentialTypeDefinition;
 use vc_util::issuer_api::{
-    CredentialSpec, Icrc21ConsentInfo,Icrc21VcConsentMessageRequest,  Icrc21ConsentPreferences, Icrc21Error, Icrc21ErrorInfo,
+    ArgumentValue, CredentialSpec, Icrc21ConsentInfo, Icrc21ConsentMessage,
+    Icrc21ConsentMessageInfo, Icrc21ConsentMessageMetadata, Icrc21DeviceSpec, Icrc21Error,
+    Icrc21ErrorInfo, Icrc21LineDisplayPage, Icrc21Value, Icrc21VcConsentMessageRequest,
+    Icrc21VcStructuredConsentMessageRequest, SignedIdAlias,
 };
-use SupportedLanguage::{English, German};
-
-/// Consent messages for the VerifiedAdult VC to be shown and approved to the user during the VC sharing flow 
-const ADULT_VC_DESCRIPTION_EN: &str = r###"# Verified Adult
-
-Credential that states that the holder's age is at least 18 years."###;
-const ADULT_VC_DESCRIPTION_DE: &str = r###"# Erwachsene Person
-
-Ausweis, der bestätigt, dass der Besitzer oder die Besitzerin mindestens 18 Jahre alt ist."###;
-
-lazy_static! {
-    static ref CONSENT_MESSAGE_TEMPLATES: HashMap<(CredentialTemplateType, SupportedLanguage), &'static str> =
-        HashMap::from([
-            (
-                (CredentialTemplateType::VerifiedAdult, English),
-                ADULT_VC_DESCRIPTION_EN
-            ),
-            (
-                (CredentialTemplateType::VerifiedAdult, German),
-                ADULT_VC_DESCRIPTION_DE
-            )
-        ]);
-}
 
-/// Supported consent message types
-#[derive(Clone, Eq, PartialEq, Hash)]
-pub enum CredentialTemplateType {
-    VerifiedAdult,
+/// Placeholder `{name}` or `{name|default}` of a consent template
+pub(crate) struct Placeholder<'a> {
+    range: Range<usize>,
+    pub(crate) name: &'a str,
+    default: Option<&'a str>,
 }
 
-/// Supported languages for consent messages
-#[derive(Clone, Eq, PartialEq, Hash)]
-pub enum SupportedLanguage {
-    English,
-    German,
+/// Finds the placeholders of a template. Braces that don't enclose an argument name (letters,
+/// digits and `_`) with an optional default are kept as text.
+pub(crate) fn template_placeholders(template: &str) -> Vec<Placeholder> {
+    let mut placeholders = Vec::new();
+    let mut start = 0;
+    while let Some(open) = template[start..].find('{').map(|i| start + i) {
+        let Some(close) = template[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        let inner = &template[open + 1..close];
+        let (name, default) = match inner.split_once('|') {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        if !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !inner.contains('{')
+        {
+            placeholders.push(Placeholder {
+                range: open..close + 1,
+                name,
+                default,
+            });
+            start = close + 1;
+        } else {
+            start = open + 1;
+        }
+    }
+    placeholders
 }
 
-impl From<&SupportedCredentialType> for CredentialTemplateType {
-    fn from(value: &SupportedCredentialType) -> Self {
-        match value {
-            SupportedCredentialType::VerifiedAdult => CredentialTemplateType::VerifiedAdult,
-        }
+/// Fills the placeholders of a template with the spec arguments or their defaults. Placeholders
+/// of missing arguments without a default are kept.
+fn fill_placeholders(template: &str, spec: &CredentialSpec) -> String {
+    let mut message = String::new();
+    let mut filled_until = 0;
+    for placeholder in template_placeholders(template) {
+        let value = match spec
+            .arguments
+            .as_ref()
+            .and_then(|args| args.get(placeholder.name))
+        {
+            Some(value) => argument_text(value),
+            None => match placeholder.default {
+                Some(default) => default.to_string(),
+                None => continue,
+            },
+        };
+        message.push_str(&template[filled_until..placeholder.range.start]);
+        message.push_str(&value);
+        filled_until = placeholder.range.end;
     }
+    message.push_str(&template[filled_until..]);
+    message
 }
 
-impl From<Icrc21ConsentPreferences> for SupportedLanguage {
-    fn from(value: Icrc21ConsentPreferences) -> Self {
-        match &value.language.to_lowercase()[..2] {
-            "de" => German,
-            _ => English, // english is also the fallback
-        }
+fn argument_text(value: &ArgumentValue) -> String {
+    match value {
+        ArgumentValue::Int(i) => i.to_string(),
+        // The arguments are chosen by the relying party, so they must not add any markup
+        ArgumentValue::String(s) => escape_markdown(s),
     }
 }
 
-impl Display for SupportedLanguage {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            English => write!(f, "en"),
-            German => write!(f, "de"),
+/// Escapes the characters that have a meaning in markdown.
+pub(crate) fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '(' | ')' | '#' | '+' | '-' | '.'
+            | '!' | '|' | '<' | '>' | '~' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            // Line breaks would allow to start new blocks
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(c),
         }
     }
+    escaped
 }
 
-
 /// Get the consent message for the given credential spec to be used during the VC sharing flow
 #[update]
 #[candid_method]
@@ -79,27 +126,157 @@ async fn vc_consent_message(
     req: Icrc21VcConsentMessageRequest,
 ) -> Result<Icrc21ConsentInfo, Icrc21Error> {
     get_vc_consent_message(
+        req.signed_id_alias.as_ref(),
         &req.credential_spec,
-        &SupportedLanguage::from(req.preferences),
+        &req.preferences.language,
     )
 }
 
+/// Get the consent message for the given credential spec in the display mode of the wallet
+#[update]
+#[candid_method]
+async fn icrc21_vc_consent_message(
+    req: Icrc21VcStructuredConsentMessageRequest,
+) -> Result<Icrc21ConsentMessageInfo, Icrc21Error> {
+    let preferences = req.user_preferences;
+    let message = render_consent_message(
+        req.signed_id_alias.as_ref(),
+        &req.credential_spec,
+        &preferences.metadata.language,
+    )?;
+    Ok(Icrc21ConsentMessageInfo {
+        consent_message: message.display(preferences.device_spec.as_ref())?,
+        metadata: Icrc21ConsentMessageMetadata {
+            language: message.language,
+            utc_offset_minutes: preferences.metadata.utc_offset_minutes,
+        },
+    })
+}
+
 /// Retrieve the consent message for the given credential type and language.
 fn get_vc_consent_message(
+    signed_id_alias: Option<&SignedIdAlias>,
     credential_spec: &CredentialSpec,
-    language: &SupportedLanguage,
+    language: &str,
 ) -> Result<Icrc21ConsentInfo, Icrc21Error> {
-    render_consent_message(credential_spec, language).map(|message| Icrc21ConsentInfo {
-        consent_message: message,
-        language: format!("{}", language),
+    render_consent_message(signed_id_alias, credential_spec, language).map(|message| {
+        Icrc21ConsentInfo {
+            consent_message: message.markdown(),
+            language: message.language,
+        }
     })
 }
 
-/// Show the consent message with any arguments 
+/// A consent message before it is rendered for a display
+pub(crate) struct ConsentMessage {
+    /// The language that was used
+    pub(crate) language: String,
+    /// The text of the message in markdown
+    pub(crate) text: String,
+    /// Heading shown above the fields
+    pub(crate) fields_heading: String,
+    /// The details of the message: the label and, if shown, the value
+    pub(crate) fields: Vec<(String, Option<String>)>,
+}
+
+impl ConsentMessage {
+    /// The message in markdown as shown by II
+    pub(crate) fn markdown(&self) -> String {
+        if self.fields.is_empty() {
+            return self.text.clone();
+        }
+        let lines: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(label, value)| match value {
+                Some(value) => format!("- {}: {}", label, escape_markdown(value)),
+                None => format!("- {}", label),
+            })
+            .collect();
+        format!(
+            "{}\n\n## {}\n\n{}",
+            self.text,
+            self.fields_heading,
+            lines.join("\n")
+        )
+    }
+
+    /// Renders the message for the device of the wallet, as markdown if no device is given.
+    pub(crate) fn display(
+        &self,
+        device_spec: Option<&Icrc21DeviceSpec>,
+    ) -> Result<Icrc21ConsentMessage, Icrc21Error> {
+        match device_spec {
+            None | Some(Icrc21DeviceSpec::GenericDisplay) => {
+                Ok(Icrc21ConsentMessage::GenericDisplayMessage(self.markdown()))
+            }
+            Some(Icrc21DeviceSpec::LineDisplay {
+                characters_per_line,
+                lines_per_page,
+            }) => {
+                if *characters_per_line == 0 || *lines_per_page == 0 {
+                    return Err(Icrc21Error::ConsentMessageUnavailable(Icrc21ErrorInfo {
+                        description: "The line display has no room for the message".to_string(),
+                    }));
+                }
+                let mut lines = wrap_lines(&self.plain_text(), *characters_per_line as usize);
+                if !self.fields.is_empty() {
+                    lines.extend(wrap_lines(
+                        &format!("{}:", self.fields_heading),
+                        *characters_per_line as usize,
+                    ));
+                }
+                for (label, value) in &self.fields {
+                    let line = match value {
+                        Some(value) => format!("{}: {}", label, value),
+                        None => label.clone(),
+                    };
+                    lines.extend(wrap_lines(&line, *characters_per_line as usize));
+                }
+                let pages = lines
+                    .chunks(*lines_per_page as usize)
+                    .map(|lines| Icrc21LineDisplayPage {
+                        lines: lines.to_vec(),
+                    })
+                    .collect();
+                Ok(Icrc21ConsentMessage::LineDisplayMessage { pages })
+            }
+            Some(Icrc21DeviceSpec::FieldsDisplay) => {
+                let fields = self
+                    .fields
+                    .iter()
+                    .map(|(label, value)| {
+                        let content = value
+                            .clone()
+                            .unwrap_or_else(|| not_shown_text(&self.language).to_string());
+                        (label.clone(), Icrc21Value::Text { content })
+                    })
+                    .collect();
+                let plain_text = self.plain_text();
+                let intent = plain_text.lines().next().unwrap_or_default().to_string();
+                Ok(Icrc21ConsentMessage::FieldsDisplayMessage { intent, fields })
+            }
+        }
+    }
+
+    /// The text of the message without markup, for displays that cannot show markdown
+    fn plain_text(&self) -> String {
+        self.text
+            .lines()
+            .map(|line| strip_markdown(line.trim_start_matches('#').trim()))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Show the consent message with any arguments, and with the previews of the holder's claims if
+/// the request has the holder's ID alias.
 fn render_consent_message(
+    signed_id_alias: Option<&SignedIdAlias>,
     credential_spec: &CredentialSpec,
-    language: &SupportedLanguage,
-) -> Result<String, Icrc21Error> {
+    language: &str,
+) -> Result<ConsentMessage, Icrc21Error> {
     let credential_type = match verify_credential_spec(credential_spec) {
         Ok(credential_type) => credential_type,
         Err(err) => {
@@ -108,13 +285,372 @@ fn render_consent_message(
             }));
         }
     };
-    let template = CONSENT_MESSAGE_TEMPLATES
-        .get(&(
-            CredentialTemplateType::from(&credential_type),
-            language.clone(),
-        ))
-        .ok_or(Icrc21Error::ConsentMessageUnavailable(Icrc21ErrorInfo {
+    let holder = signed_id_alias
+        .map(|alias| id_alias_holder(alias, time().into()))
+        .transpose()
+        .map_err(|_| {
+            Icrc21Error::ConsentMessageUnavailable(Icrc21ErrorInfo {
+                description: "Id alias could not be verified".to_string(),
+            })
+        })?;
+    // Don't ask for consent to share a credential the holder has blocked
+    if holder.is_some_and(|holder| is_credential_type_blocked(&holder, &credential_type)) {
+        return Err(Icrc21Error::ConsentMessageUnavailable(Icrc21ErrorInfo {
+            description: format!(
+                "Credential of type {} has been blocked from sharing by its holder",
+                credential_type.name
+            ),
+        }));
+    }
+    // Falls back to shorter language tags and the default language if there is no template for
+    // the requested one
+    let (language, template) = credential_type.consent_template(language).ok_or(
+        Icrc21Error::ConsentMessageUnavailable(Icrc21ErrorInfo {
             description: "Consent message template not found".to_string(),
-        }))?;
-        Ok(template.to_string())   
-}
\ No newline at end of file
+        }),
+    )?;
+    let holder_claims =
+        holder.and_then(|holder| selected_claims(&holder, &credential_type, credential_spec));
+    Ok(ConsentMessage {
+        text: fill_placeholders(&template, credential_spec),
+        fields_heading: disclosed_claims_heading(&language).to_string(),
+        fields: disclosed_fields(
+            &credential_type,
+            credential_spec,
+            holder_claims.as_deref(),
+            &language,
+        ),
+        language,
+    })
+}
+
+/// Lists the arguments and the stored claims that go into the VC. Claims the holder's credential
+/// doesn't have are left out, as they won't be disclosed.
+fn disclosed_fields(
+    credential_type: &CredentialTypeDefinition,
+    spec: &CredentialSpec,
+    holder_claims: Option<&[Claim]>,
+    language: &str,
+) -> Vec<(String, Option<String>)> {
+    let mut fields = Vec::new();
+    for argument in &credential_type.arguments {
+        if let Some(value) = spec
+            .arguments
+            .as_ref()
+            .and_then(|args| args.get(&argument.name))
+        {
+            let value = match value {
+                ArgumentValue::Int(i) => i.to_string(),
+                ArgumentValue::String(s) => s.clone(),
+            };
+            let label = credential_type.claim_label(&argument.name, language);
+            fields.push((label, Some(value)));
+        }
+    }
+    for name in credential_type.claims_to_disclose(spec) {
+        let label = credential_type.claim_label(&name, language);
+        let value = holder_claims.map(|claims| claims.iter().find_map(|c| c.claims.get(&name)));
+        let preview = match value {
+            Some(None) => continue,
+            Some(Some(value)) if credential_type.shows_masked_preview(&name) => {
+                masked_preview(value)
+            }
+            _ => None,
+        };
+        fields.push((label, preview));
+    }
+    fields
+}
+
+fn disclosed_claims_heading(language: &str) -> &'static str {
+    match language.split('-').next() {
+        Some("de") => "Offengelegte Angaben",
+        _ => "Disclosed claims",
+    }
+}
+
+/// Shown in the fields display for the claims without a preview
+fn not_shown_text(language: &str) -> &'static str {
+    match language.split('-').next() {
+        Some("de") => "nicht angezeigt",
+        _ => "not shown",
+    }
+}
+
+/// Shows the first third of a text, date or number and masks the rest (e.g. "Zü****"). Other
+/// values have no preview.
+fn masked_preview(value: &ClaimValue) -> Option<String> {
+    let text = match value {
+        ClaimValue::Text(text) | ClaimValue::Date(text) => text.clone(),
+        ClaimValue::Number(n) => n.to_string(),
+        ClaimValue::Boolean(_) | ClaimValue::Claim(_) => return None,
+    };
+    let length = text.chars().count();
+    let shown: String = text.chars().take(length / 3).collect();
+    Some(shown + &"*".repeat(length - length / 3))
+}
+
+/// Removes the escapes and the emphasis of a line of markdown.
+fn strip_markdown(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            '*' | '_' | '`' => {}
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Breaks the text into lines of at most `width` characters, at spaces where possible.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let line_length = line.chars().count();
+            if line_length > 0 && line_length + 1 + word.len() <= width {
+                line.push(' ');
+                line.extend(word);
+                continue;
+            }
+            if line_length > 0 {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words that are longer than a line are broken up
+            while word.len() > width {
+                lines.push(word.drain(..width).collect());
+            }
+            line.extend(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_type::{default_credential_types, ClaimDisplay};
+    use std::collections::HashMap;
+
+    fn spec(arguments: Vec<(&str, ArgumentValue)>) -> CredentialSpec {
+        CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(
+                arguments
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect::<HashMap<_, _>>(),
+            ),
+        }
+    }
+
+    /// Test that placeholders are filled with the arguments or their defaults
+    #[test]
+    fn test_fill_placeholders() {
+        let template = "At least {minAge|18} years, in {country}, {not a placeholder} {";
+        assert_eq!(
+            fill_placeholders(template, &spec(vec![])),
+            "At least 18 years, in {country}, {not a placeholder} {"
+        );
+        assert_eq!(
+            fill_placeholders(
+                template,
+                &spec(vec![
+                    ("minAge", ArgumentValue::Int(21)),
+                    ("country", ArgumentValue::String("CH".to_string())),
+                ])
+            ),
+            "At least 21 years, in CH, {not a placeholder} {"
+        );
+        let names: Vec<&str> = template_placeholders(template)
+            .iter()
+            .map(|placeholder| placeholder.name)
+            .collect();
+        assert_eq!(names, vec!["minAge", "country"]);
+    }
+
+    /// Test that string arguments cannot add markup to the consent message
+    #[test]
+    fn test_fill_placeholders_escapes_markdown() {
+        let injected = spec(vec![(
+            "country",
+            ArgumentValue::String("CH\n# [Approve](https://evil.com)".to_string()),
+        )]);
+        assert_eq!(
+            fill_placeholders("In {country}", &injected),
+            "In CH \\# \\[Approve\\]\\(https://evil\\.com\\)"
+        );
+    }
+
+    /// Test that the fields list the arguments and the claims the VC will contain
+    #[test]
+    fn test_disclosed_fields() {
+        let mut adult = default_credential_types().remove(0);
+        adult.disclosable_claims = Some(vec!["city".to_string(), "country".to_string()]);
+        adult.claim_display = Some(HashMap::from([
+            (
+                "city".to_string(),
+                ClaimDisplay {
+                    labels: HashMap::from([("de".to_string(), "Wohnort".to_string())]),
+                    masked_preview: true,
+                },
+            ),
+            (
+                "minAge".to_string(),
+                ClaimDisplay {
+                    labels: HashMap::from([("de".to_string(), "Mindestalter".to_string())]),
+                    masked_preview: false,
+                },
+            ),
+        ]));
+        let spec = spec(vec![
+            ("minAge", ArgumentValue::Int(21)),
+            (
+                "disclose",
+                ArgumentValue::String("city,country".to_string()),
+            ),
+        ]);
+        let field = |label: &str, value: Option<&str>| (label.to_string(), value.map(String::from));
+        assert_eq!(
+            disclosed_fields(&adult, &spec, None, "de-CH"),
+            vec![
+                field("Mindestalter", Some("21")),
+                field("Wohnort", None),
+                field("country", None)
+            ]
+        );
+
+        // The holder's credential has no country, which is then not disclosed
+        let holder_claims = vec![Claim {
+            claims: HashMap::from([("city".to_string(), ClaimValue::Text("Zürich".to_string()))]),
+        }];
+        assert_eq!(
+            disclosed_fields(&adult, &spec, Some(&holder_claims), "en"),
+            vec![field("minAge", Some("21")), field("city", Some("Zü****"))]
+        );
+        let spec = CredentialSpec {
+            arguments: None,
+            ..spec
+        };
+        assert!(disclosed_fields(&adult, &spec, None, "en").is_empty());
+    }
+
+    fn consent_message() -> ConsentMessage {
+        ConsentMessage {
+            language: "de".to_string(),
+            fields_heading: "Offengelegte Angaben".to_string(),
+            text: "# Volljährig\n\nMindestens **21** Jahre alt, in \\[CH\\].".to_string(),
+            fields: vec![
+                ("Mindestalter".to_string(), Some("21".to_string())),
+                ("Wohnort".to_string(), Some("Zü****".to_string())),
+                ("Land".to_string(), None),
+            ],
+        }
+    }
+
+    /// Test that the fields are listed below the text in markdown
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            consent_message().markdown(),
+            "# Volljährig\n\nMindestens **21** Jahre alt, in \\[CH\\].\n\n\
+            ## Offengelegte Angaben\n\n- Mindestalter: 21\n- Wohnort: Zü\\*\\*\\*\\*\n- Land"
+        );
+        let message = ConsentMessage {
+            fields: vec![],
+            ..consent_message()
+        };
+        assert_eq!(message.markdown(), message.text);
+        assert_eq!(
+            message.display(Some(&Icrc21DeviceSpec::GenericDisplay)),
+            Ok(Icrc21ConsentMessage::GenericDisplayMessage(
+                message.text.clone()
+            ))
+        );
+    }
+
+    /// Test that the message is broken into pages that fit the line display
+    #[test]
+    fn test_line_display() {
+        let device_spec = Icrc21DeviceSpec::LineDisplay {
+            characters_per_line: 16,
+            lines_per_page: 4,
+        };
+        let Ok(Icrc21ConsentMessage::LineDisplayMessage { pages }) =
+            consent_message().display(Some(&device_spec))
+        else {
+            panic!("expected a line display message");
+        };
+        let pages: Vec<Vec<String>> = pages.into_iter().map(|page| page.lines).collect();
+        assert_eq!(
+            pages,
+            vec![
+                vec!["Volljährig", "Mindestens 21", "Jahre alt, in", "[CH]."],
+                vec![
+                    "Offengelegte",
+                    "Angaben:",
+                    "Mindestalter: 21",
+                    "Wohnort: Zü****"
+                ],
+                vec!["Land"],
+            ]
+        );
+
+        let empty_display = Icrc21DeviceSpec::LineDisplay {
+            characters_per_line: 0,
+            lines_per_page: 4,
+        };
+        assert!(matches!(
+            consent_message().display(Some(&empty_display)),
+            Err(Icrc21Error::ConsentMessageUnavailable(_))
+        ));
+    }
+
+    /// Test that the heading is the intent and the fields are shown as text
+    #[test]
+    fn test_fields_display() {
+        let text = |content: &str| Icrc21Value::Text {
+            content: content.to_string(),
+        };
+        assert_eq!(
+            consent_message().display(Some(&Icrc21DeviceSpec::FieldsDisplay)),
+            Ok(Icrc21ConsentMessage::FieldsDisplayMessage {
+                intent: "Volljährig".to_string(),
+                fields: vec![
+                    ("Mindestalter".to_string(), text("21")),
+                    ("Wohnort".to_string(), text("Zü****")),
+                    ("Land".to_string(), text("nicht angezeigt")),
+                ],
+            })
+        );
+    }
+
+    /// Test that words are only broken up if they don't fit on a line
+    #[test]
+    fn test_wrap_lines() {
+        assert_eq!(
+            wrap_lines("a bb ccc\nddddddddd", 4),
+            vec!["a bb", "ccc", "dddd", "dddd", "d"]
+        );
+        assert_eq!(wrap_lines("", 4), Vec::<String>::new());
+    }
+
+    /// Test that previews show only the start of the value
+    #[test]
+    fn test_masked_preview() {
+        let text = |t: &str| ClaimValue::Text(t.to_string());
+        assert_eq!(masked_preview(&text("CH")), Some("**".to_string()));
+        assert_eq!(
+            masked_preview(&ClaimValue::Date("1990-05-17".to_string())),
+            Some("199*******".to_string())
+        );
+        assert_eq!(masked_preview(&ClaimValue::Boolean(true)), None);
+    }
+}
@@ -4,59 +4,45 @@
 //! including issuing, updating, and retrieving credentials. It also handles authorization
 //! and verification processes related to credential operations.
 use candid::{candid_method, CandidType, Decode, Deserialize, Encode, Principal};
-use canister_sig_util::signature_map::LABEL_SIG;
-use canister_sig_util::CanisterSigPublicKey;
+use canister_sig_util::signature_map::{LABEL_SIG, SIGNATURE_EXPIRATION_PERIOD_NS};
 use ic_cdk::api::{caller, set_certified_data, time};
 use ic_cdk_macros::{query, update};
-use ic_certification::{fork_hash, labeled_hash, Hash};
+use ic_certification::{fork_hash, labeled_hash};
 use ic_stable_structures::storable::{Bound, Storable};
 use identity_core::common::Timestamp;
 use identity_core::common::Url;
 use identity_credential::credential::{CredentialBuilder, Subject};
-use lazy_static::lazy_static;
 use serde::Serialize;
 use serde_bytes::ByteBuf;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
-use std::fmt;
-use std::iter::repeat;
+use std::iter::{once, repeat};
 use vc_util::issuer_api::{
     CredentialSpec, GetCredentialRequest, IssueCredentialError, IssuedCredentialData,
     PrepareCredentialRequest, PreparedCredentialData, SignedIdAlias,
 };
+use vc_util::sd_jwt::{sd_jwt_from_jws, sd_jwt_issuer_jws, sd_jwt_signing_input};
+use vc_util::status_list::StatusListEntry;
 use vc_util::{
-    did_for_principal, get_verified_id_alias_from_jws, vc_jwt_to_jws, vc_signing_input,
+    did_for_principal, get_verified_id_alias_from_jws,
+    get_verified_id_alias_from_jws_for_any_subject, vc_jwt_to_jws, vc_signing_input,
     vc_signing_input_hash, AliasTuple,
 };
 
 extern crate asset_util;
 
-use crate::config::{ASSETS, CONFIG, CREDENTIALS, LOOKUP_TABLE, MSG_HASHES, SIGNATURES};
-
-// The expiration of issued verifiable credentials.
-const MINUTE_NS: u64 = 60 * 1_000_000_000;
-const VC_EXPIRATION_PERIOD_NS: u64 = 15 * MINUTE_NS;
-
-lazy_static! {
-    /// Seed and public key used for signing the credentials.
-    pub(crate) static ref CANISTER_SIG_SEED: Vec<u8> = hash_bytes("a_random_seed").to_vec();
-    static ref CANISTER_SIG_PK: CanisterSigPublicKey = CanisterSigPublicKey::new(ic_cdk::id(), CANISTER_SIG_SEED.clone());
-}
-
-/// Supported types of credentials that can be issued by this canister.
-#[derive(Debug)]
-pub enum SupportedCredentialType {
-    VerifiedAdult,
-}
-impl fmt::Display for SupportedCredentialType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            SupportedCredentialType::VerifiedAdult => write!(f, "VerifiedAdult"),
-        }
-    }
-}
+use crate::config::{vc_issuer, ASSETS, CONFIG, CREDENTIALS, LOOKUP_TABLE, SIGNATURES};
+use crate::credential_type::{get_credential_type, is_sd_jwt, CredentialTypeDefinition};
+use crate::history::record_issuance;
+use crate::prepared_context::{
+    context_key, decode_prepared_context, encode_prepared_context, ensure_context_key,
+    PreparedContext,
+};
+use crate::sd_jwt::{build_sd_jwt, random_salt_seed, SdJwtParams};
+use crate::signing_key::{add_signature, ensure_signing_seed, signing_seed};
+use crate::status_list::{allocate_status_entries, set_credential_status, StatusPurpose};
 
 /// Represents different types of claim values that can be part of a credential.
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
@@ -107,6 +93,8 @@ pub struct Credential{
     pub type_: Vec<String>,
     pub context: Vec<String>,
     pub claim: Vec<Claim>,
+    /// Time (in ns since the epoch) after which the credential can no longer be used to issue VCs
+    pub expires_at_ns: Option<u64>,
 }
 
 /// Represents a full credential that includes the issuer and context url in full. This is the type that will be returned from the canister
@@ -117,6 +105,12 @@ pub struct FullCredential {
     pub issuer: String,
     pub context: Vec<String>,
     pub claim: Vec<Claim>,
+    /// Set by the holder to exclude the credential from being shared through II
+    pub blocked: bool,
+    /// Set by the issuer to temporarily exclude the credential from being shared through II
+    pub suspended: bool,
+    pub updated_at_ns: u64,
+    pub expires_at_ns: Option<u64>,
 }
 
 /// Convert to a FullCredential by adding the caller as the issuer field 
@@ -128,6 +122,10 @@ impl From<Credential> for FullCredential {
             issuer: ic_cdk::api::caller().to_string(),
             context: credential.context,
             claim: credential.claim,
+            blocked: false,
+            suspended: false,
+            updated_at_ns: time(),
+            expires_at_ns: credential.expires_at_ns,
         }
     }
 }
@@ -139,6 +137,13 @@ struct StoredCredential {
     type_: Vec<String>,
     context_issuer_id: u16,
     claim: Vec<Claim>,
+    /// Absent in credentials stored before it was added, which are not blocked
+    blocked: Option<bool>,
+    /// Absent in credentials stored before it was added, which are not suspended
+    suspended: Option<bool>,
+    /// Absent in credentials stored before it was added, which rank as updated at 0
+    updated_at_ns: Option<u64>,
+    expires_at_ns: Option<u64>,
 }
 
 /// Convert from a single full credential to a single stored credential
@@ -153,10 +158,28 @@ impl From<FullCredential> for StoredCredential {
             type_: full_credential.type_,
             context_issuer_id: url_id,
             claim: full_credential.claim,
+            blocked: Some(full_credential.blocked),
+            suspended: Some(full_credential.suspended),
+            updated_at_ns: Some(full_credential.updated_at_ns),
+            expires_at_ns: full_credential.expires_at_ns,
         }
     }
 }
 
+impl StoredCredential {
+    fn is_blocked(&self) -> bool {
+        self.blocked.unwrap_or(false)
+    }
+
+    fn is_suspended(&self) -> bool {
+        self.suspended.unwrap_or(false)
+    }
+
+    fn updated_at_ns(&self) -> u64 {
+        self.updated_at_ns.unwrap_or(0)
+    }
+}
+
 /// Define a wrapper type around a list of credentials so that we can store it inside Stable Storage as well as implement to and from conversion to a list of full credentials
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub struct CredentialList(Vec<StoredCredential>);
@@ -196,6 +219,10 @@ impl From<CredentialList> for Vec<FullCredential> {
                     issuer,
                     context,
                     claim: c.claim,
+                    blocked: c.is_blocked(),
+                    suspended: c.is_suspended(),
+                    updated_at_ns: c.updated_at_ns(),
+                    expires_at_ns: c.expires_at_ns,
                 };
                 new_full_credentials.push(full_credential);
             }
@@ -216,9 +243,10 @@ impl From<Vec<FullCredential>> for CredentialList {
 pub enum CredentialError {
     NoCredentialFound(String),
     UnauthorizedSubject(String),
+    InvalidRequest(String),
 }
 
-fn is_authorized_issuer(caller: Principal) -> bool {
+pub(crate) fn is_authorized_issuer(caller: Principal) -> bool {
     CONFIG.with(|config_cell| {
         let config = config_cell.borrow();
         let current_config = config.get();
@@ -239,6 +267,17 @@ async fn add_credentials(
             "Unauthorized: You do not have permission to add credentials.".to_string(),
         ));
     }
+    let full_credentials = store_credentials(principal, new_credentials);
+
+    let credential_info = format!("Added credentials: \n{:?}", full_credentials);
+    Ok(credential_info)
+}
+
+/// Stores the credentials for the given principal with the caller as issuer, replacing credentials with the same id.
+pub(crate) fn store_credentials(
+    principal: Principal,
+    new_credentials: Vec<Credential>,
+) -> Vec<FullCredential> {
     let full_credentials: Vec<FullCredential> = new_credentials.into_iter().map(FullCredential::from).collect();
     // First get the it in compressed form of StoredCredential
     let new_stored_credentials: CredentialList = CredentialList::from(full_credentials.clone());
@@ -257,8 +296,13 @@ async fn add_credentials(
                     .iter()
                     .position(|existing_c| existing_c.id == new_c.id)
                 {
-                    // Replace existing credential
-                    existing_credentials[pos] = new_c;
+                    // Replace existing credential, keeping the holder's sharing preference and the suspension
+                    let StoredCredential { blocked, suspended, .. } = existing_credentials[pos];
+                    existing_credentials[pos] = StoredCredential {
+                        blocked,
+                        suspended,
+                        ..new_c
+                    };
                 } else {
                     // Insert new credential
                     existing_credentials.push(new_c);
@@ -270,9 +314,7 @@ async fn add_credentials(
             credentials.insert(principal, new_stored_credentials);
         }
     });
-
-    let credential_info = format!("Added credentials: \n{:?}", full_credentials);
-    Ok(credential_info)
+    full_credentials
 }
 
 #[update]
@@ -308,9 +350,16 @@ async fn remove_credential(
                         .clone()
                 });
                 if *issuer == ic_cdk::api::caller().to_string() {
-                    // Remove the credential 
+                    // Remove the credential and revoke the VCs issued from it
                     existing_credentials_vec.remove(pos);
                     credentials.insert(principal, CredentialList(existing_credentials_vec));
+                    set_credential_status(
+                        caller(),
+                        principal,
+                        &credential_id,
+                        StatusPurpose::Revocation,
+                        true,
+                    );
                     Ok("Credential removed successfully".to_string())
                 } else {
                     Err(CredentialError::UnauthorizedSubject(
@@ -369,8 +418,11 @@ async fn update_credential(
                 });
                 if *issuer == ic_cdk::api::caller().to_text() {
                     // Convert the updated credential to a full credential and then to a stored credential
-                    let updated_stored_credential =
-                        StoredCredential::from(FullCredential::from(updated_credential));
+                    let updated_stored_credential = StoredCredential {
+                        blocked: credentials[pos].blocked,
+                        suspended: credentials[pos].suspended,
+                        ..StoredCredential::from(FullCredential::from(updated_credential))
+                    };
                     // Update the credential with the new data
                     credentials[pos] = updated_stored_credential.clone();
                     // Update the principal with the new list of credentials
@@ -416,6 +468,156 @@ fn get_all_credentials(principal: Principal) -> Result<Vec<FullCredential>, Cred
     }
 }
 
+/// Moves the credentials stored for `from` to `to` and returns the ids of the moved credentials.
+/// If an issuer is given, only the credentials it issued are moved, otherwise all of them.
+/// Credentials keep their issuer/context id and blocked flag. Nothing is moved if `to` already has
+/// a credential with the id of one of the moved credentials.
+pub(crate) fn move_credentials(
+    from: &Principal,
+    to: Principal,
+    issuer: Option<Principal>,
+) -> Result<Vec<String>, CredentialError> {
+    CREDENTIALS.with(|c| {
+        let mut credentials = c.borrow_mut();
+        let (moved, kept): (Vec<StoredCredential>, Vec<StoredCredential>) = credentials
+            .get(from)
+            .map(Into::<Vec<StoredCredential>>::into)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|c| issuer.map_or(true, |issuer| is_issued_by(c, &issuer)));
+        if moved.is_empty() {
+            return Err(CredentialError::NoCredentialFound(format!(
+                "No credentials found for principal {}",
+                from.to_text()
+            )));
+        }
+        let mut existing: Vec<StoredCredential> =
+            credentials.get(&to).map(Into::into).unwrap_or_default();
+        if let Some(c) = moved.iter().find(|c| existing.iter().any(|e| e.id == c.id)) {
+            return Err(CredentialError::InvalidRequest(format!(
+                "Credential {} already exists for principal {}",
+                c.id,
+                to.to_text()
+            )));
+        }
+        let ids = moved.iter().map(|c| c.id.clone()).collect();
+        existing.extend(moved);
+        credentials.insert(to, CredentialList(existing));
+        if kept.is_empty() {
+            credentials.remove(from);
+        } else {
+            credentials.insert(*from, CredentialList(kept));
+        }
+        Ok(ids)
+    })
+}
+
+fn is_issued_by(credential: &StoredCredential, issuer: &Principal) -> bool {
+    LOOKUP_TABLE.with(|map| {
+        map.borrow()
+            .get(credential.context_issuer_id)
+            .map_or(false, |(id, _)| *id == issuer.to_text())
+    })
+}
+
+/// Lets the holder block or unblock the sharing of one of their credentials.
+/// A blocked credential stays stored in the canister but is never used to issue a VC.
+#[update]
+#[candid_method]
+async fn set_credential_blocked(
+    credential_id: String,
+    blocked: bool,
+) -> Result<String, CredentialError> {
+    // Only the subject of the credential can change its sharing preference
+    let principal = caller();
+
+    CREDENTIALS.with(|c| {
+        let mut creds = c.borrow_mut();
+        if let Some(credentials) = creds.get(&principal) {
+            let mut credentials: Vec<StoredCredential> = credentials.into();
+            if let Some(pos) = credentials.iter().position(|c| c.id == credential_id) {
+                credentials[pos].blocked = Some(blocked);
+                creds.insert(principal, CredentialList(credentials));
+                Ok(format!(
+                    "Credential {} is now {}",
+                    credential_id,
+                    if blocked { "blocked" } else { "unblocked" }
+                ))
+            } else {
+                Err(CredentialError::NoCredentialFound(format!(
+                    "No credential found with ID {} for principal {}",
+                    credential_id,
+                    principal.to_text()
+                )))
+            }
+        } else {
+            Err(CredentialError::NoCredentialFound(format!(
+                "No credentials found for principal {}",
+                principal.to_text()
+            )))
+        }
+    })
+}
+
+/// Lets the original issuer suspend or reinstate a credential of the given principal.
+/// A suspended credential stays stored in the canister but is never used to issue a VC, and the
+/// VCs issued from it are suspended in the status list.
+#[update]
+#[candid_method]
+async fn set_credential_suspended(
+    principal: Principal,
+    credential_id: String,
+    suspended: bool,
+) -> Result<String, CredentialError> {
+    // Check if the caller is an authorized issuer
+    if !is_authorized_issuer(caller()) {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: You do not have permission to suspend credentials.".to_string(),
+        ));
+    }
+
+    CREDENTIALS.with(|c| {
+        let mut creds = c.borrow_mut();
+        if let Some(credentials) = creds.get(&principal) {
+            let mut credentials: Vec<StoredCredential> = credentials.into();
+            if let Some(pos) = credentials.iter().position(|c| c.id == credential_id) {
+                // Check if the original credential issuer matches the caller
+                if issuer_of(&credentials[pos]) != caller().to_text() {
+                    return Err(CredentialError::UnauthorizedSubject(
+                        "Unauthorized: You do not have permission to suspend this credential."
+                            .to_string(),
+                    ));
+                }
+                credentials[pos].suspended = Some(suspended);
+                creds.insert(principal, CredentialList(credentials));
+                set_credential_status(
+                    caller(),
+                    principal,
+                    &credential_id,
+                    StatusPurpose::Suspension,
+                    suspended,
+                );
+                Ok(format!(
+                    "Credential {} is now {}",
+                    credential_id,
+                    if suspended { "suspended" } else { "reinstated" }
+                ))
+            } else {
+                Err(CredentialError::NoCredentialFound(format!(
+                    "No credential found with ID {} for principal {}",
+                    credential_id,
+                    principal.to_text()
+                )))
+            }
+        } else {
+            Err(CredentialError::NoCredentialFound(format!(
+                "No credentials found for principal {}",
+                principal.to_text()
+            )))
+        }
+    })
+}
+
 /// Request to prepare a VC for issuance.
 #[update]
 #[candid_method]
@@ -427,46 +629,112 @@ async fn prepare_credential(
         Ok(alias_tuple) => alias_tuple,
         Err(err) => return Err(err),
     };
+    // Get the keys for signing and for the prepared context before reading any state that the VC is built from
+    let signing_seed = ensure_signing_seed()
+        .await
+        .map_err(IssueCredentialError::Internal)?;
+    let context_key = ensure_context_key().await?;
+    let salt_seed = if is_sd_jwt(&req.credential_spec) {
+        Some(random_salt_seed().await?)
+    } else {
+        None
+    };
 
     // Construct the JWT of the VC to be issued.
-    let credential_jwt = match prepare_credential_jwt(&req.credential_spec, &alias_tuple) {
-        Ok(credential) => credential,
+    let prepared = match prepare_credential_jwt(
+        &req.credential_spec,
+        &alias_tuple,
+        salt_seed.as_deref(),
+    ) {
+        Ok(prepared) => prepared,
         Err(err) => return Result::<PreparedCredentialData, IssueCredentialError>::Err(err),
     };
     // And sign the JWT
-    let signing_input =
-        vc_signing_input(&credential_jwt, &CANISTER_SIG_PK).expect("Failed getting signing_input.");
+    let signing_input = if is_sd_jwt(&req.credential_spec) {
+        sd_jwt_signing_input(&prepared.jwt, &signing_seed.public_key())
+    } else {
+        vc_signing_input(&prepared.jwt, &signing_seed.public_key())
+    }
+    .expect("Failed getting signing_input.");
     let msg_hash = vc_signing_input_hash(&signing_input);
 
     // Add the signed JWT to the signature storage
-    SIGNATURES.with(|sigs| {
-        let mut sigs = sigs.borrow_mut();
-        sigs.add_signature(&CANISTER_SIG_SEED, msg_hash);
-        // Add the msg hash to the stable storage to restore the signatures when the canister is upgraded
-        MSG_HASHES.with(|hashes| {
-            let _ = hashes.borrow_mut().push(&msg_hash);
-        });
-    });
+    add_signature(&signing_seed, msg_hash);
     update_root_hash();
+    // Bind the JWT to this request in an authenticated prepared context
+    let prepared_context = encode_prepared_context(
+        &context_key,
+        &PreparedContext::new(
+            prepared.jwt,
+            prepared.disclosures,
+            signing_seed.id,
+            &req.credential_spec,
+            &alias_tuple,
+            // The context is valid as long as the signature of its VC
+            time() + SIGNATURE_EXPIRATION_PERIOD_NS,
+        ),
+    );
+    // Log the issuance for the holder (without anything that identifies the relying party)
+    record_issuance(
+        alias_tuple.id_dapp,
+        req.credential_spec.credential_type,
+        prepared.credential_id,
+        req.credential_spec.arguments,
+    );
     // Return a prepared context that includes the signed JWT
     Ok(PreparedCredentialData {
-        prepared_context: Some(ByteBuf::from(credential_jwt.as_bytes())),
+        prepared_context: Some(ByteBuf::from(prepared_context)),
+        expiration_timestamp_s: Some(prepared.expiration_timestamp_s),
     })
 }
 
-/// Obtain a VC from the canister after it was prepared.
+/// Obtain a VC from the canister after it was prepared. A VC prepared with the `sd-jwt` format is
+/// returned as its issuer JWS without disclosures, as Internet Identity expects a JWS.
 #[query]
 #[candid_method(query)]
 fn get_credential(req: GetCredentialRequest) -> Result<IssuedCredentialData, IssueCredentialError> {
-    if let Err(err) = authorize_vc_request(&req.signed_id_alias, &caller(), time().into()) {
-        return Result::<IssuedCredentialData, IssueCredentialError>::Err(err);
+    issue_credential(req, false)
+}
+
+/// Obtain a VC prepared with the `sd-jwt` format as SD-JWT, i.e. with the disclosures appended to
+/// the issuer JWS. Meant for holders that call the canister directly instead of through Internet
+/// Identity.
+#[query]
+#[candid_method(query)]
+fn get_sd_jwt_credential(
+    req: GetCredentialRequest,
+) -> Result<IssuedCredentialData, IssueCredentialError> {
+    if !is_sd_jwt(&req.credential_spec) {
+        return Err(IssueCredentialError::UnsupportedCredentialSpec(
+            "The credential spec does not request the sd-jwt format".to_string(),
+        ));
+    }
+    issue_credential(req, true)
+}
+
+fn issue_credential(
+    req: GetCredentialRequest,
+    with_disclosures: bool,
+) -> Result<IssuedCredentialData, IssueCredentialError> {
+    let alias_tuple = match authorize_vc_request(&req.signed_id_alias, &caller(), time().into()) {
+        Ok(alias_tuple) => alias_tuple,
+        Err(err) => return Result::<IssuedCredentialData, IssueCredentialError>::Err(err),
     };
-    if let Err(err) = verify_credential_spec(&req.credential_spec) {
-        return Result::<IssuedCredentialData, IssueCredentialError>::Err(
-            IssueCredentialError::UnsupportedCredentialSpec(err),
-        );
+    let credential_type = match verify_credential_spec(&req.credential_spec) {
+        Ok(credential_type) => credential_type,
+        Err(err) => {
+            return Result::<IssuedCredentialData, IssueCredentialError>::Err(
+                IssueCredentialError::UnsupportedCredentialSpec(err),
+            );
+        }
+    };
+    // The holder may have blocked the credential after it was prepared
+    if let Err(err) =
+        verify_authorized_principal(&credential_type, &req.credential_spec, &alias_tuple)
+    {
+        return Result::<IssuedCredentialData, IssueCredentialError>::Err(err);
     }
-    // Check if the prepared context is present in the request. This context should contain the JWT of the VC, get it as a string
+    // Check if the prepared context is present in the request. This context should contain the JWT of the VC
     let prepared_context = match req.prepared_context {
         Some(context) => context,
         None => {
@@ -475,25 +743,44 @@ fn get_credential(req: GetCredentialRequest) -> Result<IssuedCredentialData, Iss
             ))
         }
     };
-    let credential_jwt: String = match String::from_utf8(prepared_context.into_vec()) {
-        Ok(s) => s,
-        Err(_) => {
-            return Result::<IssuedCredentialData, IssueCredentialError>::Err(internal_error(
-                "Invalid prepared_context",
-            ))
+    // Authenticate the context and check that it was prepared for this request
+    let context = match decode_prepared_context(&context_key(), &prepared_context)
+        .and_then(|context| {
+            context.verify(&req.credential_spec, &alias_tuple, time())?;
+            Ok(context)
+        }) {
+        Ok(context) => context,
+        Err(err) => return Result::<IssuedCredentialData, IssueCredentialError>::Err(err.into()),
+    };
+    let credential_jwt = context.credential_jwt;
+    // Get the seed the JWT was signed with, which may have been rotated since
+    let signing_seed = match signing_seed(context.signing_key_id, time()) {
+        Some(signing_seed) => signing_seed,
+        None => {
+            return Result::<IssuedCredentialData, IssueCredentialError>::Err(
+                IssueCredentialError::SignatureNotFound(
+                    "The signing key of the prepared_context is no longer valid".to_string(),
+                ),
+            )
         }
     };
+    let canister_sig_pk = signing_seed.public_key();
 
     // Sign the JWT
-    let signing_input =
-        vc_signing_input(&credential_jwt, &CANISTER_SIG_PK).expect("failed getting signing_input");
+    let sd_jwt = is_sd_jwt(&req.credential_spec);
+    let signing_input = if sd_jwt {
+        sd_jwt_signing_input(&credential_jwt, &canister_sig_pk)
+    } else {
+        vc_signing_input(&credential_jwt, &canister_sig_pk)
+    }
+    .expect("failed getting signing_input");
     let message_hash = vc_signing_input_hash(&signing_input);
     // Match it to the signature from the signature storage.
     let sig_result = SIGNATURES.with(|sigs| {
         let sig_map = sigs.borrow();
         let certified_assets_root_hash = ASSETS.with_borrow(|assets| assets.root_hash());
         sig_map.get_signature_as_cbor(
-            &CANISTER_SIG_SEED,
+            &signing_seed.seed,
             message_hash,
             Some(certified_assets_root_hash),
         )
@@ -511,8 +798,15 @@ fn get_credential(req: GetCredentialRequest) -> Result<IssuedCredentialData, Iss
         }
     };
 
-    let vc_jws =
-        vc_jwt_to_jws(&credential_jwt, &CANISTER_SIG_PK, &sig).expect("failed constructing JWS");
+    let mut vc_jws = if sd_jwt {
+        sd_jwt_issuer_jws(&credential_jwt, &canister_sig_pk, &sig)
+    } else {
+        vc_jwt_to_jws(&credential_jwt, &canister_sig_pk, &sig)
+    }
+    .expect("failed constructing JWS");
+    if with_disclosures {
+        vc_jws = sd_jwt_from_jws(&vc_jws, &context.disclosures);
+    }
     Result::<IssuedCredentialData, IssueCredentialError>::Ok(IssuedCredentialData { vc_jws })
 }
 
@@ -543,53 +837,174 @@ fn authorize_vc_request(
     })
 }
 
-/// Check if the given user has a credential of the type and return it.
+/// Get the holder (`id_dapp`) of a valid ID alias, for calls that are not made by the holder.
+pub(crate) fn id_alias_holder(
+    alias: &SignedIdAlias,
+    current_time_ns: u128,
+) -> Result<Principal, IssueCredentialError> {
+    CONFIG.with_borrow(|config| {
+        let config = config.get();
+        for idp_canister_id in &config.idp_canister_ids {
+            if let Ok(alias_tuple) = get_verified_id_alias_from_jws_for_any_subject(
+                &alias.credential_jws,
+                idp_canister_id,
+                &config.ic_root_key_raw,
+                current_time_ns,
+            ) {
+                return Ok(alias_tuple.id_dapp);
+            }
+        }
+        Err(IssueCredentialError::InvalidIdAlias(
+            "Id alias could not be verified".to_string(),
+        ))
+    })
+}
+
+/// Check if the given user has a credential of the type that can be used for the spec and return it.
 fn verify_authorized_principal(
-    credential_type: SupportedCredentialType,
+    credential_type: &CredentialTypeDefinition,
+    spec: &CredentialSpec,
     alias_tuple: &AliasTuple,
 ) -> Result<StoredCredential, IssueCredentialError> {
-    // Get the credentials of this user
-    if let Some(credentials) = CREDENTIALS.with(|c| c.borrow().get(&alias_tuple.id_dapp)) {
-        // Check if the user has a credential of the type and return it
-        let v: Vec<StoredCredential> = credentials.into();
-        for c in v {
-            if c.type_.contains(&credential_type.to_string()) {
-                return Ok(c);
-            }
+    // Get the credentials of this user that have the type
+    let matching: Vec<StoredCredential> = CREDENTIALS
+        .with(|c| c.borrow().get(&alias_tuple.id_dapp))
+        .map(Vec::<StoredCredential>::from)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|c| credential_type.accepts(&c.type_))
+        .collect();
+    if matching.is_empty() {
+        // No matching credential found for this user
+        println!(
+            "*** Principal {} it is not authorized for credential type {}",
+            alias_tuple.id_dapp.to_text(),
+            credential_type.name
+        );
+        return Err(IssueCredentialError::UnauthorizedSubject(format!(
+            "Unauthorized principal {}",
+            alias_tuple.id_dapp.to_text()
+        )));
+    }
+    select_credential(credential_type, spec, matching, time())
+}
+
+/// Choose the credential to issue a VC from, following the selection policy of the credential type.
+///
+/// Blocked, suspended or expired credentials, credentials from issuers excluded by the policy and credentials
+/// that cannot satisfy the spec arguments are skipped. Of the remaining ones the credential of the best ranked
+/// issuer is chosen, then the most recently updated one, then the one with the smallest id.
+fn select_credential(
+    credential_type: &CredentialTypeDefinition,
+    spec: &CredentialSpec,
+    matching: Vec<StoredCredential>,
+    now_ns: u64,
+) -> Result<StoredCredential, IssueCredentialError> {
+    let mut invalid_match = false;
+    let mut excluded_issuer_match = false;
+    let mut unsatisfied_match = false;
+    let mut candidates: Vec<(usize, StoredCredential)> = Vec::new();
+    for c in matching.into_iter().filter(|c| !c.is_blocked()) {
+        if c.is_suspended() || c.expires_at_ns.is_some_and(|expires_at| expires_at <= now_ns) {
+            invalid_match = true;
+        } else if !credential_type.is_satisfied_by(spec, &c.claim, now_ns) {
+            unsatisfied_match = true;
+        } else if let Some(rank) = credential_type.selection().issuer_rank(&issuer_of(&c)) {
+            candidates.push((rank, c));
+        } else {
+            excluded_issuer_match = true;
         }
     }
-    // No (matching) credential found for this user
-    println!(
-        "*** Principal {} it is not authorized for credential type {:?}",
-        alias_tuple.id_dapp.to_text(),
-        credential_type
-    );
+    if let Some((_, c)) = candidates.into_iter().min_by(|(rank_a, a), (rank_b, b)| {
+        rank_a
+            .cmp(rank_b)
+            .then(b.updated_at_ns().cmp(&a.updated_at_ns()))
+            .then(a.id.cmp(&b.id))
+    }) {
+        return Ok(c);
+    }
+    // The matching credentials cannot prove what the spec arguments ask for
+    if unsatisfied_match {
+        return Err(IssueCredentialError::UnsupportedCredentialSpec(format!(
+            "The stored credential of type {} cannot satisfy the requested arguments",
+            credential_type.name
+        )));
+    }
+    if invalid_match {
+        return Err(IssueCredentialError::UnauthorizedSubject(format!(
+            "Credential of type {} has expired or has been suspended by its issuer",
+            credential_type.name
+        )));
+    }
+    if excluded_issuer_match {
+        return Err(IssueCredentialError::UnauthorizedSubject(format!(
+            "Credential of type {} was not issued by an accepted issuer",
+            credential_type.name
+        )));
+    }
+    // The only matching credentials have been blocked from sharing by the holder
     Err(IssueCredentialError::UnauthorizedSubject(format!(
-        "Unauthorized principal {}",
-        alias_tuple.id_dapp.to_text()
+        "Credential of type {} has been blocked from sharing by its holder",
+        credential_type.name
     )))
 }
 
-/// Verifies if the credential spec is supported and returns the corresponding credential type.
+/// Get the issuer of a stored credential from the lookup table.
+fn issuer_of(credential: &StoredCredential) -> String {
+    LOOKUP_TABLE.with_borrow(|map| {
+        map.get(credential.context_issuer_id)
+            .map(|(issuer, _)| issuer.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Returns true if the principal holds credentials of the given type but all of them are blocked from sharing.
+pub(crate) fn is_credential_type_blocked(
+    principal: &Principal,
+    credential_type: &CredentialTypeDefinition,
+) -> bool {
+    CREDENTIALS.with(|c| c.borrow().get(principal)).is_some_and(|credentials| {
+        let v: Vec<StoredCredential> = credentials.into();
+        let mut matching = v
+            .iter()
+            .filter(|c| credential_type.accepts(&c.type_))
+            .peekable();
+        matching.peek().is_some() && matching.all(|c| c.is_blocked())
+    })
+}
+
+/// Returns the claims of the stored credential a VC for the spec would be issued from, if the
+/// principal has one.
+pub(crate) fn selected_claims(
+    principal: &Principal,
+    credential_type: &CredentialTypeDefinition,
+    spec: &CredentialSpec,
+) -> Option<Vec<Claim>> {
+    let matching: Vec<StoredCredential> = CREDENTIALS
+        .with(|c| c.borrow().get(principal))
+        .map(Vec::<StoredCredential>::from)?
+        .into_iter()
+        .filter(|c| credential_type.accepts(&c.type_))
+        .collect();
+    select_credential(credential_type, spec, matching, time())
+        .ok()
+        .map(|credential| credential.claim)
+}
+
+/// Verifies if the credential spec and its arguments are supported and returns the registry entry of its credential type.
 pub(crate) fn verify_credential_spec(
     spec: &CredentialSpec,
-) -> Result<SupportedCredentialType, String> {
-    match spec.credential_type.as_str() {
-        "VerifiedAdult" => Ok(SupportedCredentialType::VerifiedAdult),
-        other => Err(format!("Credential {} is not supported", other)),
-    }
+) -> Result<CredentialTypeDefinition, String> {
+    let credential_type = get_credential_type(&spec.credential_type)
+        .ok_or_else(|| format!("Credential {} is not supported", spec.credential_type))?;
+    credential_type.verify_arguments(spec)?;
+    Ok(credential_type)
 }
 
 fn internal_error(msg: &str) -> IssueCredentialError {
     IssueCredentialError::Internal(String::from(msg))
 }
 
-fn hash_bytes(value: impl AsRef<[u8]>) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(value.as_ref());
-    hasher.finalize().into()
-}
-
 pub(crate) fn update_root_hash() {
     SIGNATURES.with_borrow(|sigs| {
         ASSETS.with_borrow(|assets| {
@@ -604,82 +1019,227 @@ pub(crate) fn update_root_hash() {
     })
 }
 
+// Context and type of every VC, which the credential builder adds itself
+const BASE_CREDENTIAL_CONTEXT: &str = "https://www.w3.org/2018/credentials/v1";
+const BASE_CREDENTIAL_TYPE: &str = "VerifiableCredential";
+
+/// A VC that was built from a stored credential and is ready to be signed.
+struct PreparedCredential {
+    jwt: String,
+    /// Id of the stored credential the VC was built from
+    credential_id: String,
+    expiration_timestamp_s: u32,
+    /// Disclosures of an SD-JWT, to be returned with the signed JWT
+    disclosures: Vec<String>,
+}
+
+/// Builds the JWT of the VC with an expiration within the lifetime policy of its credential type.
+/// With a seed for the salts of the disclosures, the JWT is the issuer JWT of an SD-JWT.
 fn prepare_credential_jwt(
     credential_spec: &CredentialSpec,
     alias_tuple: &AliasTuple,
-) -> Result<String, IssueCredentialError> {
+    salt_seed: Option<&[u8]>,
+) -> Result<PreparedCredential, IssueCredentialError> {
     let credential_type = match verify_credential_spec(credential_spec) {
         Ok(credential_type) => credential_type,
         Err(err) => {
             return Err(IssueCredentialError::UnsupportedCredentialSpec(err));
         }
     };
-    // Currently only supports VerifiedAdults spec
-    let credential = verify_authorized_principal(credential_type, alias_tuple)?;
-    Ok(build_credential(
-        alias_tuple.id_alias,
-        credential_spec,
-        credential,
-    ))
+    let mut credential =
+        verify_authorized_principal(&credential_type, credential_spec, alias_tuple)?;
+    let credential_id = credential.id.clone();
+    let expiration_timestamp_s =
+        exp_timestamp_s(credential_type.lifetime().vc_lifetime_ns(credential_spec));
+    let issuer = Principal::from_text(issuer_of(&credential))
+        .map_err(|_| internal_error("The issuer of the credential is not a principal"))?;
+    let status_entries = allocate_status_entries(
+        issuer,
+        alias_tuple.id_dapp,
+        &credential_id,
+        expiration_timestamp_s as u64 * 1_000_000_000,
+    )?;
+    let id = vc_id(&issuer, status_entries[0].status_list_index);
+    let status: Vec<Value> = status_entries
+        .iter()
+        .map(StatusListEntry::to_json)
+        .collect();
+    let issued_at_s = (time() / 1_000_000_000) as u32;
+    if let Some(salt_seed) = salt_seed {
+        let (jwt, disclosures) = build_sd_jwt(
+            SdJwtParams {
+                spec: credential_spec,
+                id,
+                subject_id: did_for_principal(alias_tuple.id_alias),
+                issuer: vc_issuer(),
+                claims: &credential.claim,
+                disclosed: &credential_type.claims_to_disclose(credential_spec),
+                issued_at_s,
+                expiration_timestamp_s,
+                status,
+            },
+            salt_seed,
+        );
+        return Ok(PreparedCredential {
+            jwt,
+            credential_id,
+            expiration_timestamp_s,
+            disclosures,
+        });
+    }
+    // Only put the claims into the VC that the spec asks for
+    credential.claim = disclose_claims(
+        credential.claim,
+        &credential_type.claims_to_disclose(credential_spec),
+    );
+    let context = LOOKUP_TABLE
+        .with_borrow(|map| map.get(credential.context_issuer_id).cloned())
+        .map(|(_, context)| context)
+        .unwrap_or_default();
+    Ok(PreparedCredential {
+        jwt: build_credential_jwt(CredentialParams {
+            spec: credential_spec.clone(),
+            id,
+            subject_id: did_for_principal(alias_tuple.id_alias),
+            types: credential.type_,
+            context,
+            issuer: vc_issuer(),
+            claims: credential.claim,
+            issued_at_s,
+            expiration_timestamp_s,
+            status,
+        }),
+        credential_id,
+        expiration_timestamp_s,
+        disclosures: vec![],
+    })
+}
+
+/// Keeps only the disclosed claims, but at least one (empty) claim so that the VC has a credential subject.
+fn disclose_claims(claims: Vec<Claim>, disclosed: &[String]) -> Vec<Claim> {
+    let disclosed_claims: Vec<Claim> = claims
+        .into_iter()
+        .map(|mut claim| {
+            claim.claims.retain(|name, _| disclosed.contains(name));
+            claim
+        })
+        .filter(|claim| !claim.claims.is_empty())
+        .collect();
+    if disclosed_claims.is_empty() {
+        vec![Claim {
+            claims: HashMap::new(),
+        }]
+    } else {
+        disclosed_claims
+    }
+}
+
+/// Returns a unique id for a VC, which does not reveal the stored credential it was built from.
+/// The index in the status lists is unique among the unexpired VCs of the issuer.
+fn vc_id(issuer: &Principal, status_list_index: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(issuer.as_slice());
+    hasher.update((status_list_index as u64).to_be_bytes());
+    hasher.update(time().to_be_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hasher.finalize()[..16]);
+    // Mark the id as a custom UUID (version 8, RFC 9562 variant)
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
 }
 
 /// Internal parameters to pass to the build_credential_jwt function.
 struct CredentialParams {
     spec: CredentialSpec,
+    /// Unique id of the VC (`jti`)
+    id: String,
     subject_id: String,
-    credential_id: String,
+    /// Types of the stored credential
+    types: Vec<String>,
     context: Vec<String>,
     issuer: String,
     claims: Vec<Claim>,
+    issued_at_s: u32,
     expiration_timestamp_s: u32,
+    /// `credentialStatus` entries of the VC
+    status: Vec<Value>,
 }
 
-fn build_credential(
-    subject_principal: Principal,
-    credential_spec: &CredentialSpec,
-    credential: StoredCredential,
-) -> String {
-    // Retrieve the context and issuer url from the LookupTable
-    LOOKUP_TABLE.with(|map| {
-        let map = map.borrow();
-        let (issuer, context) = map.get(credential.context_issuer_id).unwrap().to_owned();
-        let params = CredentialParams {
-            spec: credential_spec.clone(),
-            subject_id: did_for_principal(subject_principal),
-            credential_id: credential.id,
-            context,
-            issuer: format!("did:icp:v0:{}", issuer),
-            expiration_timestamp_s: exp_timestamp_s(),
-            claims: credential.claim,
-        };
-        build_credential_jwt(params)
-    })
-}
-
-fn exp_timestamp_s() -> u32 {
-    ((time() + VC_EXPIRATION_PERIOD_NS) / 1_000_000_000) as u32
+fn exp_timestamp_s(vc_lifetime_ns: u64) -> u32 {
+    ((time() + vc_lifetime_ns) / 1_000_000_000) as u32
 }
 
 /// Build a VC and return it as a JWT-string.
 fn build_credential_jwt(params: CredentialParams) -> String {
     // Build "credentialSubject" objects
-    let subjects = build_claims_into_credential_subjects(params.claims, params.subject_id);
+    let mut subjects = build_claims_into_credential_subjects(params.claims, params.subject_id);
+    // Embed the spec arguments as `credentialSubject[credential_type]`, following II's convention
+    // (cf. https://github.com/dfinity/internet-identity/blob/main/docs/vc-spec.md#recommended-convention-connecting-credential-specification-with-the-returned-credentials)
+    for subject in subjects.iter_mut() {
+        subject.properties.insert(
+            params.spec.credential_type.clone(),
+            credential_spec_args_to_json(&params.spec),
+        );
+    }
+    let issuance_date = Timestamp::from_unix(params.issued_at_s as i64)
+        .expect("internal: failed computing issuance timestamp");
     let expiration_date = Timestamp::from_unix(params.expiration_timestamp_s as i64)
         .expect("internal: failed computing expiration timestamp");
 
-    // Build the VC a
+    // Build the VC (its id becomes the `jti` and its issuance date the `nbf` of the JWT)
     let mut credential = CredentialBuilder::default()
-        .id(Url::parse(params.credential_id).unwrap())
-        .issuer(Url::parse(params.issuer).unwrap())
-        .type_("VerifiedCredential".to_string())
-        .type_(params.spec.credential_type)
+        .id(Url::parse(params.id).unwrap())
+        .issuer(Url::parse(params.issuer.clone()).unwrap())
         .subjects(subjects) // add objects to the credentialSubject
+        .issuance_date(issuance_date)
         .expiration_date(expiration_date);
+    // The builder adds the base type first
+    let mut types: Vec<String> = Vec::new();
+    for type_ in params
+        .types
+        .into_iter()
+        .chain(once(params.spec.credential_type))
+    {
+        if type_ != BASE_CREDENTIAL_TYPE && !types.contains(&type_) {
+            types.push(type_);
+        }
+    }
+    for type_ in types {
+        credential = credential.type_(type_);
+    }
     // Add all the context
     credential = add_context(credential, params.context);
     // Serialize the VC object into a JWT-string
     let credential = credential.build().unwrap();
-    credential.serialize_jwt().unwrap()
+    let jwt = credential.serialize_jwt().unwrap();
+    let mut claims: Value =
+        serde_json::from_str(&jwt).expect("internal: failed parsing the JWT claims");
+    // The URL parser adds a trailing slash to an origin, so the issuer is set as configured
+    claims["iss"] = Value::String(params.issuer);
+    claims["iat"] = Value::from(params.issued_at_s);
+    // The credential builder supports a single status only, so the entries are added to the JWT
+    claims["vc"]["credentialStatus"] = Value::Array(params.status);
+    claims.to_string()
+}
+
+/// Converts the arguments of the spec into a JSON object (empty if there are none).
+pub(crate) fn credential_spec_args_to_json(spec: &CredentialSpec) -> Value {
+    let args_map = spec
+        .arguments
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.clone(), value.clone().into()))
+        .collect();
+    Value::Object(args_map)
 }
 
 /// Helper function to construct the claims stored in the canister into a CredentialSubject containing the subject and given claims
@@ -702,7 +1262,7 @@ pub(crate) fn add_context(
     mut credential: CredentialBuilder,
     context: Vec<String>,
 ) -> CredentialBuilder {
-    for c in context {
+    for c in context.into_iter().filter(|c| c != BASE_CREDENTIAL_CONTEXT) {
         credential = credential.context(Url::parse(c).unwrap());
     }
     credential
@@ -712,6 +1272,7 @@ pub(crate) fn add_context(
 mod tests {
     use super::*;
     use crate::credential::Claim;
+    use crate::credential_type::SelectionPolicy;
     use std::collections::HashMap;
 
     /// Test that new entry is added to the table if there doesn't exist one for the given values
@@ -728,6 +1289,10 @@ mod tests {
             claim: vec![Claim {
                 claims: HashMap::new(),
             }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns: 0,
+            expires_at_ns: None,
         };
 
         let stored_credential = StoredCredential::from(full_credential);
@@ -755,6 +1320,10 @@ mod tests {
             claim: vec![Claim {
                 claims: HashMap::new(),
             }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns: 0,
+            expires_at_ns: None,
         };
         // Convert the credential and verify the context_issuer_id remains the same
         let stored_credential = StoredCredential::from(full_credential);
@@ -775,6 +1344,10 @@ mod tests {
             claim: vec![Claim {
                 claims: HashMap::new(),
             }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns: 0,
+            expires_at_ns: None,
         };
         let stored_credential = StoredCredential::from(full_credential);
         assert_eq!(stored_credential.context_issuer_id, 1);
@@ -795,6 +1368,10 @@ mod tests {
             claim: vec![Claim {
                 claims: HashMap::new(),
             }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns: 0,
+            expires_at_ns: None,
         };
 
         let credential2 = FullCredential {
@@ -808,6 +1385,10 @@ mod tests {
             claim: vec![Claim {
                 claims: HashMap::new(),
             }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns: 0,
+            expires_at_ns: None,
         };
         // Convert them so that the table will be filled with entries for '1' and '2'
         let c1 = StoredCredential::from(credential1);
@@ -841,4 +1422,126 @@ mod tests {
             "https://www.example.com/credentials/extension".to_string()
         );
     }
+
+    /// Test that the credentials stored before the optional fields were added can be decoded
+    #[test]
+    fn test_decode_baseline_credential_list() {
+        #[derive(CandidType)]
+        struct BaselineStoredCredential {
+            id: String,
+            type_: Vec<String>,
+            context_issuer_id: u16,
+            claim: Vec<Claim>,
+        }
+        let baseline = vec![BaselineStoredCredential {
+            id: "1".to_string(),
+            type_: vec!["VerifiedAdult".to_string()],
+            context_issuer_id: 1,
+            claim: vec![Claim {
+                claims: HashMap::from([("age".to_string(), ClaimValue::Number(20))]),
+            }],
+        }];
+        let bytes = Encode!(&baseline).unwrap();
+
+        let credentials: Vec<StoredCredential> = CredentialList::from_bytes(bytes.into()).into();
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].id, "1");
+        assert_eq!(credentials[0].context_issuer_id, 1);
+        assert!(!credentials[0].is_blocked());
+        assert!(!credentials[0].is_suspended());
+        assert_eq!(credentials[0].updated_at_ns(), 0);
+    }
+
+    fn adult_credential(id: &str, issuer: &str, updated_at_ns: u64) -> StoredCredential {
+        StoredCredential::from(FullCredential {
+            id: id.to_string(),
+            type_: vec!["VerifiedAdult".to_string()],
+            issuer: issuer.to_string(),
+            context: vec!["https://www.w3.org/ns/credentials/v2".to_string()],
+            claim: vec![Claim {
+                claims: HashMap::new(),
+            }],
+            blocked: false,
+            suspended: false,
+            updated_at_ns,
+            expires_at_ns: None,
+        })
+    }
+
+    /// Test that the selection policy picks the same credential regardless of the stored order
+    #[test]
+    fn test_select_credential_by_issuer_rank_and_update_time() {
+        let mut adult = crate::credential_type::default_credential_types().remove(0);
+        let spec = CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: None,
+        };
+        let issuer_a = Principal::from_slice(&[1]);
+        let issuer_b = Principal::from_slice(&[2]);
+        let old_a = adult_credential("old_a", &issuer_a.to_text(), 1);
+        let new_a = adult_credential("new_a", &issuer_a.to_text(), 2);
+        let old_b = adult_credential("old_b", &issuer_b.to_text(), 0);
+
+        // Without preferred issuers the most recently updated credential is used
+        let credentials = vec![old_a.clone(), new_a.clone(), old_b.clone()];
+        let selected = select_credential(&adult, &spec, credentials.clone(), 10).unwrap();
+        assert_eq!(selected.id, "new_a");
+        let reversed = credentials.iter().rev().cloned().collect();
+        assert_eq!(select_credential(&adult, &spec, reversed, 10).unwrap().id, "new_a");
+
+        // A preferred issuer wins over more recent credentials
+        adult.selection = Some(SelectionPolicy {
+            preferred_issuers: vec![issuer_b],
+            preferred_issuers_only: false,
+        });
+        let selected = select_credential(&adult, &spec, credentials.clone(), 10).unwrap();
+        assert_eq!(selected.id, "old_b");
+
+        // Suspended and expired credentials are skipped
+        let suspended_b = StoredCredential {
+            suspended: Some(true),
+            ..old_b
+        };
+        let expired_a = StoredCredential {
+            expires_at_ns: Some(10),
+            ..new_a
+        };
+        let credentials = vec![old_a.clone(), expired_a, suspended_b];
+        let selected = select_credential(&adult, &spec, credentials, 10).unwrap();
+        assert_eq!(selected.id, "old_a");
+
+        // Credentials of other issuers are not used if only preferred issuers are accepted
+        adult.selection = Some(SelectionPolicy {
+            preferred_issuers: vec![issuer_b],
+            preferred_issuers_only: true,
+        });
+        assert!(matches!(
+            select_credential(&adult, &spec, vec![old_a], 10),
+            Err(IssueCredentialError::UnauthorizedSubject(e)) if e.contains("accepted issuer")
+        ));
+    }
+
+    /// Test that only the disclosed claims are kept
+    #[test]
+    fn test_disclose_claims() {
+        let claims = vec![
+            Claim {
+                claims: HashMap::from([
+                    ("name".to_string(), ClaimValue::Text("Jane".to_string())),
+                    ("dateOfBirth".to_string(), ClaimValue::Date("2000-01-01".to_string())),
+                ]),
+            },
+            Claim {
+                claims: HashMap::from([("age".to_string(), ClaimValue::Number(24))]),
+            },
+        ];
+        let disclosed = disclose_claims(claims.clone(), &["name".to_string()]);
+        assert_eq!(disclosed.len(), 1);
+        assert_eq!(disclosed[0].claims.len(), 1);
+        assert!(disclosed[0].claims.contains_key("name"));
+
+        let disclosed = disclose_claims(claims, &[]);
+        assert_eq!(disclosed.len(), 1);
+        assert!(disclosed[0].claims.is_empty());
+    }
 }
@@ -0,0 +1,322 @@
+//! Lets holders ask for a credential instead of waiting for an issuer to push one.
+//!
+//! A holder creates a pending request with `request_credential` for a type of the credential type
+//! registry. Authorized issuers list the pending requests and either approve them (which stores the
+//! attached credential for the holder) or reject them with a reason. If the selection policy of the
+//! type only uses the credentials of its preferred issuers, only these issuers can process the
+//! requests for the type. Holders can follow the state of their own requests.
+//!
+//! The requests are indexed by holder, by pending state and by processing time, so neither the
+//! limits nor the listings scan all requests. At most 10000 requests can be pending at once, and
+//! processed requests are pruned 30 days after they were approved or rejected.
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::config::{
+    CREDENTIAL_REQUESTS, HOLDER_REQUESTS, NEXT_REQUEST_ID, PENDING_REQUESTS, PROCESSED_REQUESTS,
+};
+use crate::credential::{is_authorized_issuer, store_credentials, Credential, CredentialError};
+use crate::credential_type::get_credential_type;
+
+// Limits that keep a holder from filling the stable memory with requests
+const MAX_PENDING_REQUESTS_PER_HOLDER: usize = 10;
+const MAX_EVIDENCE_REFS: usize = 10;
+const MAX_EVIDENCE_REF_LENGTH: usize = 256;
+// Limit on the pending requests of all holders together
+const MAX_PENDING_REQUESTS: u64 = 10_000;
+// Processed requests are kept for 30 days, and pruned in batches of 50 when requests are created or processed
+const REQUEST_RETENTION_PERIOD_NS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_REQUESTS_TO_PRUNE: usize = 50;
+
+/// State of a credential request.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CredentialRequestStatus {
+    Pending,
+    Approved { credential_id: String },
+    Rejected { reason: String },
+}
+
+/// A credential requested by a holder.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialRequest {
+    pub id: u64,
+    pub holder: Principal,
+    pub credential_type: String,
+    /// References to the evidence backing the request (e.g. document hashes or urls), never the evidence itself
+    pub evidence_refs: Vec<String>,
+    pub status: CredentialRequestStatus,
+    pub created_at_ns: u64,
+    /// The issuer that approved or rejected the request
+    pub processed_by: Option<Principal>,
+    /// When the request was approved or rejected
+    pub processed_at_ns: Option<u64>,
+}
+
+impl Storable for CredentialRequest {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CredentialRequest"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, CredentialRequest).expect("Failed to decode CredentialRequest")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Creates a pending request for a credential of the given type for the caller and returns its id.
+#[update]
+#[candid_method]
+async fn request_credential(
+    credential_type: String,
+    evidence_refs: Vec<String>,
+) -> Result<u64, CredentialError> {
+    let holder = caller();
+    if holder == Principal::anonymous() {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: The anonymous principal cannot request credentials.".to_string(),
+        ));
+    }
+    if get_credential_type(&credential_type).is_none() {
+        return Err(CredentialError::InvalidRequest(format!(
+            "Credential {} is not supported",
+            credential_type
+        )));
+    }
+    if evidence_refs.len() > MAX_EVIDENCE_REFS
+        || evidence_refs
+            .iter()
+            .any(|evidence| evidence.len() > MAX_EVIDENCE_REF_LENGTH)
+    {
+        return Err(CredentialError::InvalidRequest(format!(
+            "At most {} evidence references of up to {} bytes are allowed",
+            MAX_EVIDENCE_REFS, MAX_EVIDENCE_REF_LENGTH
+        )));
+    }
+    let now = time();
+    prune_processed_requests(now);
+    if PENDING_REQUESTS.with_borrow(|pending| pending.len()) >= MAX_PENDING_REQUESTS {
+        return Err(CredentialError::InvalidRequest(
+            "Too many pending credential requests, please try again later".to_string(),
+        ));
+    }
+    let pending = holder_request_ids(&holder)
+        .into_iter()
+        .filter(|id| PENDING_REQUESTS.with_borrow(|pending| pending.contains_key(id)))
+        .count();
+    if pending >= MAX_PENDING_REQUESTS_PER_HOLDER {
+        return Err(CredentialError::InvalidRequest(format!(
+            "At most {} pending credential requests are allowed",
+            MAX_PENDING_REQUESTS_PER_HOLDER
+        )));
+    }
+    let id = NEXT_REQUEST_ID.with_borrow_mut(|next_id| {
+        let id = *next_id.get();
+        next_id
+            .set(id + 1)
+            .expect("failed to store the next credential request id");
+        id
+    });
+    store_request(CredentialRequest {
+        id,
+        holder,
+        credential_type,
+        evidence_refs,
+        status: CredentialRequestStatus::Pending,
+        created_at_ns: now,
+        processed_by: None,
+        processed_at_ns: None,
+    });
+    Ok(id)
+}
+
+/// Retrieves the pending credential requests the caller can process. Can only be called by an
+/// authorized issuer.
+#[query]
+#[candid_method(query)]
+fn list_pending_credential_requests() -> Result<Vec<CredentialRequest>, CredentialError> {
+    let issuer = caller();
+    if !is_authorized_issuer(issuer) {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: You do not have permission to list credential requests.".to_string(),
+        ));
+    }
+    let pending_ids: Vec<u64> =
+        PENDING_REQUESTS.with_borrow(|pending| pending.iter().map(|(id, _)| id).collect());
+    Ok(CREDENTIAL_REQUESTS.with_borrow(|requests| {
+        pending_ids
+            .into_iter()
+            .filter_map(|id| requests.get(&id))
+            .filter(|request| can_process(&issuer, &request.credential_type))
+            .collect()
+    }))
+}
+
+/// Approves a pending request and stores the attached credential for the holder with the caller as issuer.
+#[update]
+#[candid_method]
+async fn approve_credential_request(
+    request_id: u64,
+    credential: Credential,
+) -> Result<String, CredentialError> {
+    let issuer = caller();
+    if !is_authorized_issuer(issuer) {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: You do not have permission to approve credential requests.".to_string(),
+        ));
+    }
+    let mut request = get_pending_request(request_id, &issuer)?;
+    let accepted = get_credential_type(&request.credential_type)
+        .is_some_and(|credential_type| credential_type.accepts(&credential.type_));
+    if !accepted {
+        return Err(CredentialError::InvalidRequest(format!(
+            "Credential does not have the requested type {}",
+            request.credential_type
+        )));
+    }
+
+    request.status = CredentialRequestStatus::Approved {
+        credential_id: credential.id.clone(),
+    };
+    request.processed_by = Some(issuer);
+    request.processed_at_ns = Some(time());
+    store_credentials(request.holder, vec![credential]);
+    store_request(request);
+    prune_processed_requests(time());
+    Ok(format!("Credential request {} approved", request_id))
+}
+
+/// Rejects a pending request with the given reason.
+#[update]
+#[candid_method]
+async fn reject_credential_request(
+    request_id: u64,
+    reason: String,
+) -> Result<String, CredentialError> {
+    let issuer = caller();
+    if !is_authorized_issuer(issuer) {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: You do not have permission to reject credential requests.".to_string(),
+        ));
+    }
+    let mut request = get_pending_request(request_id, &issuer)?;
+    request.status = CredentialRequestStatus::Rejected { reason };
+    request.processed_by = Some(issuer);
+    request.processed_at_ns = Some(time());
+    store_request(request);
+    prune_processed_requests(time());
+    Ok(format!("Credential request {} rejected", request_id))
+}
+
+/// Retrieves all credential requests of the caller.
+#[query]
+#[candid_method(query)]
+fn my_credential_requests() -> Vec<CredentialRequest> {
+    let holder = caller();
+    let ids = holder_request_ids(&holder);
+    CREDENTIAL_REQUESTS
+        .with_borrow(|requests| ids.into_iter().filter_map(|id| requests.get(&id)).collect())
+}
+
+/// Indexes the existing requests if the indexes are still empty, e.g. after upgrading from a
+/// version without them.
+pub(crate) fn init_request_indexes() {
+    if !HOLDER_REQUESTS.with_borrow(|index| index.is_empty()) {
+        return;
+    }
+    let requests: Vec<CredentialRequest> = CREDENTIAL_REQUESTS
+        .with_borrow(|requests| requests.iter().map(|(_, request)| request).collect());
+    if let Some(last) = requests.last() {
+        NEXT_REQUEST_ID.with_borrow_mut(|next_id| {
+            next_id
+                .set(last.id + 1)
+                .expect("failed to store the next credential request id")
+        });
+    }
+    for request in requests {
+        store_request(request);
+    }
+}
+
+/// Stores the request and updates the indexes. Processed requests without a processing time
+/// are indexed by their creation time.
+fn store_request(request: CredentialRequest) {
+    let id = request.id;
+    HOLDER_REQUESTS.with_borrow_mut(|index| index.insert((request.holder, id), ()));
+    if request.status == CredentialRequestStatus::Pending {
+        PENDING_REQUESTS.with_borrow_mut(|pending| pending.insert(id, ()));
+    } else {
+        PENDING_REQUESTS.with_borrow_mut(|pending| pending.remove(&id));
+        let processed_at = request.processed_at_ns.unwrap_or(request.created_at_ns);
+        PROCESSED_REQUESTS.with_borrow_mut(|processed| processed.insert((processed_at, id), ()));
+    }
+    CREDENTIAL_REQUESTS.with_borrow_mut(|requests| requests.insert(id, request));
+}
+
+/// Removes up to 50 of the requests that were processed longer than the retention period ago.
+fn prune_processed_requests(now: u64) {
+    let cutoff = now.saturating_sub(REQUEST_RETENTION_PERIOD_NS);
+    let expired: Vec<(u64, u64)> = PROCESSED_REQUESTS.with_borrow(|processed| {
+        processed
+            .range(..(cutoff, 0))
+            .take(MAX_REQUESTS_TO_PRUNE)
+            .map(|(key, _)| key)
+            .collect()
+    });
+    for (processed_at, id) in expired {
+        PROCESSED_REQUESTS.with_borrow_mut(|processed| processed.remove(&(processed_at, id)));
+        if let Some(request) = CREDENTIAL_REQUESTS.with_borrow_mut(|requests| requests.remove(&id))
+        {
+            HOLDER_REQUESTS.with_borrow_mut(|index| index.remove(&(request.holder, id)));
+        }
+    }
+}
+
+/// Returns the ids of the requests of the holder, oldest first.
+fn holder_request_ids(holder: &Principal) -> Vec<u64> {
+    HOLDER_REQUESTS.with_borrow(|index| {
+        index
+            .range((*holder, 0)..)
+            .take_while(|((request_holder, _), _)| request_holder == holder)
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// Returns true if the issuer can process the requests for the credential type. The requests for a
+/// type that was removed from the registry can be processed by every authorized issuer.
+fn can_process(issuer: &Principal, credential_type: &str) -> bool {
+    get_credential_type(credential_type).map_or(true, |credential_type| {
+        credential_type
+            .selection()
+            .issuer_rank(&issuer.to_text())
+            .is_some()
+    })
+}
+
+fn get_pending_request(
+    request_id: u64,
+    issuer: &Principal,
+) -> Result<CredentialRequest, CredentialError> {
+    match CREDENTIAL_REQUESTS.with_borrow(|requests| requests.get(&request_id)) {
+        Some(request) if !can_process(issuer, &request.credential_type) => {
+            Err(CredentialError::UnauthorizedSubject(format!(
+                "Unauthorized: You do not have permission to process requests for {}.",
+                request.credential_type
+            )))
+        }
+        Some(request) if request.status == CredentialRequestStatus::Pending => Ok(request),
+        Some(_) => Err(CredentialError::InvalidRequest(format!(
+            "Credential request {} has already been processed",
+            request_id
+        ))),
+        None => Err(CredentialError::NoCredentialFound(format!(
+            "No credential request found with id {}",
+            request_id
+        ))),
+    }
+}
@@ -0,0 +1,1128 @@
+//! Registry of the credential types the Civic Canister can issue VCs for.
+//!
+//! The registry is kept in stable memory and managed by the admin, so new credential types can be
+//! added without a new wasm release. Each entry defines which stored credentials can be used for the type,
+//! which spec arguments are accepted, which stored claims may be disclosed in the VC, how long issued
+//! VCs are valid, which stored credential is chosen
+//! if the holder has several, the consent messages shown to the user and the metadata wallets can use
+//! to display the credential.
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::caller;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::iter::once;
+use vc_util::issuer_api::{
+    ArgumentType, ArgumentValue, CredentialSpec, CredentialTypeManifest, IssuerError,
+    ManifestArgument, ManifestLifetime,
+};
+
+use crate::config::{is_admin, CREDENTIAL_TYPES};
+use crate::consent_message::template_placeholders;
+use crate::credential::{Claim, ClaimValue};
+
+// The default lifetime policy of issued verifiable credentials.
+const SECOND_NS: u64 = 1_000_000_000;
+const MINUTE_NS: u64 = 60 * SECOND_NS;
+const DEFAULT_VC_LIFETIME_NS: u64 = 15 * MINUTE_NS;
+const MIN_VC_LIFETIME_NS: u64 = MINUTE_NS;
+const MAX_VC_LIFETIME_NS: u64 = 24 * 60 * MINUTE_NS;
+const DAY_NS: u64 = 24 * 60 * MINUTE_NS;
+
+/// Spec argument, accepted for every credential type, to request a VC lifetime in seconds
+pub(crate) const LIFETIME_ARGUMENT: &str = "lifetimeSeconds";
+
+/// Spec argument, accepted for every credential type, with the comma separated names of the stored
+/// claims to disclose in the VC. No stored claims are disclosed without it.
+pub(crate) const DISCLOSE_ARGUMENT: &str = "disclose";
+
+/// Spec argument, accepted for every credential type, to request the VC in another format than a
+/// JWT. With `sd-jwt` the VC is issued as SD-JWT, with a disclosure for each claim to disclose or,
+/// if the `disclose` argument is missing, for each disclosable claim. The SD-JWT with the
+/// disclosures is obtained with `get_sd_jwt_credential`.
+const FORMAT_ARGUMENT: &str = "format";
+const JWT_FORMAT: &str = "jwt";
+const SD_JWT_FORMAT: &str = "sd-jwt";
+
+/// Stored claim with the birth date of the holder, from which age predicates are derived
+const BIRTH_DATE_CLAIM: &str = "dateOfBirth";
+
+/// Language used when no consent message exists for the requested one or its shorter tags
+pub(crate) const DEFAULT_LANGUAGE: &str = "en";
+
+/// Consent messages for the VerifiedAdult VC to be shown and approved to the user during the VC sharing flow
+const ADULT_VC_DESCRIPTION_EN: &str = r###"# Verified Adult
+
+Credential that states that the holder's age is at least {minAge|18} years."###;
+const ADULT_VC_DESCRIPTION_DE: &str = r###"# Erwachsene Person
+
+Ausweis, der bestätigt, dass der Besitzer oder die Besitzerin mindestens {minAge|18} Jahre alt ist."###;
+
+/// Consent messages for the AgeRange VC
+const AGE_RANGE_VC_DESCRIPTION_EN: &str = r###"# Verified Age Range
+
+Credential that states that the holder's age is within the requested range. The date of birth is not shared."###;
+const AGE_RANGE_VC_DESCRIPTION_DE: &str = r###"# Bestätigte Altersspanne
+
+Ausweis, der bestätigt, dass das Alter des Besitzers oder der Besitzerin in der angefragten Spanne liegt. Das Geburtsdatum wird nicht geteilt."###;
+
+/// Type of the value of a spec argument
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ArgumentKind {
+    Int,
+    String,
+}
+
+/// How a spec argument is proven by the claims of the stored credential
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ArgumentRule {
+    /// The stored claim must have the same value as the argument
+    ClaimEquals(String),
+    /// The stored numeric claim must be at least the argument. Values up to `implied_by_type`
+    /// are proven by the credential type itself (e.g. a VerifiedAdult is at least 18).
+    ClaimAtLeast {
+        claim: String,
+        implied_by_type: Option<i32>,
+    },
+    /// The age computed at issuance from the stored birth date ("YYYY-MM-DD") must be at least the
+    /// argument. Only the argument ends up in the VC, not the birth date.
+    AgeAtLeast { birth_date_claim: String },
+    /// The age computed at issuance from the stored birth date must be at most the argument.
+    /// All age rules of a spec use the same birth date, the first valid one of the claims.
+    AgeAtMost { birth_date_claim: String },
+    /// The argument is proven if any of the rules proves it.
+    AnyOf(Vec<ArgumentRule>),
+}
+
+/// A spec argument accepted for a credential type
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArgumentDefinition {
+    pub name: String,
+    pub kind: ArgumentKind,
+    pub rule: ArgumentRule,
+}
+
+/// Bounds for the lifetime of the VCs issued for a credential type
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LifetimePolicy {
+    pub min_ns: u64,
+    pub max_ns: u64,
+    /// Used if the spec doesn't request a lifetime
+    pub default_ns: u64,
+}
+
+impl Default for LifetimePolicy {
+    fn default() -> Self {
+        LifetimePolicy {
+            min_ns: MIN_VC_LIFETIME_NS,
+            max_ns: MAX_VC_LIFETIME_NS,
+            default_ns: DEFAULT_VC_LIFETIME_NS,
+        }
+    }
+}
+
+impl LifetimePolicy {
+    fn is_valid(&self) -> bool {
+        self.min_ns > 0 && self.min_ns <= self.default_ns && self.default_ns <= self.max_ns
+    }
+
+    /// Returns the lifetime requested by the spec clamped to the policy, or the default lifetime.
+    pub(crate) fn vc_lifetime_ns(&self, spec: &CredentialSpec) -> u64 {
+        let requested = match spec
+            .arguments
+            .as_ref()
+            .and_then(|args| args.get(LIFETIME_ARGUMENT))
+        {
+            Some(ArgumentValue::Int(seconds)) => u64::try_from(*seconds).unwrap_or(0) * SECOND_NS,
+            _ => self.default_ns,
+        };
+        requested.clamp(self.min_ns, self.max_ns)
+    }
+}
+
+/// How the stored credential used to issue a VC is chosen if the holder has several usable ones.
+/// The credential of the best ranked issuer is used, ties are broken by the most recent update.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SelectionPolicy {
+    /// Issuers in order of preference, credentials of other issuers rank after them
+    pub preferred_issuers: Vec<Principal>,
+    /// Only use credentials of the preferred issuers
+    pub preferred_issuers_only: bool,
+}
+
+impl SelectionPolicy {
+    /// Returns the rank of the issuer (lower is better) or None if its credentials must not be used.
+    pub(crate) fn issuer_rank(&self, issuer: &str) -> Option<usize> {
+        match self
+            .preferred_issuers
+            .iter()
+            .position(|preferred| preferred.to_text() == issuer)
+        {
+            Some(rank) => Some(rank),
+            None if self.preferred_issuers_only => None,
+            None => Some(self.preferred_issuers.len()),
+        }
+    }
+}
+
+/// Metadata wallets can use to display the credential
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialDisplay {
+    pub name: String,
+    pub description: String,
+    /// Url or data uri of the icon
+    pub icon: Option<String>,
+}
+
+/// How a stored claim or an argument that goes into the VC is shown in the consent message
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClaimDisplay {
+    /// Labels keyed by lowercase BCP-47 language tag, the name is shown if there is none
+    pub labels: HashMap<String, String>,
+    /// Show a masked preview of the holder's value of a stored claim (e.g. "Zü****") in consent
+    /// messages requested with the holder's ID alias
+    pub masked_preview: bool,
+}
+
+/// Entry of the credential type registry. The fields added after the registry was introduced are
+/// optional, so that the entries stored before can still be decoded.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialTypeDefinition {
+    /// The `credential_type` used in the credential spec
+    pub name: String,
+    /// Values of the `type_` field of stored credentials that can be used to issue this type
+    pub accepted_types: Vec<String>,
+    pub arguments: Vec<ArgumentDefinition>,
+    /// Names of the arguments of which a spec must have at least one, e.g. the bounds of an age
+    /// range. No argument is required if absent.
+    pub required_any_of: Option<Vec<String>>,
+    /// Names of the stored claims that can be requested with the `disclose` argument, none if absent
+    pub disclosable_claims: Option<Vec<String>>,
+    /// The default lifetime policy applies if absent
+    pub lifetime: Option<LifetimePolicy>,
+    /// The default selection policy applies if absent
+    pub selection: Option<SelectionPolicy>,
+    /// Consent message templates keyed by lowercase BCP-47 language tag (e.g. "en" or "de-ch").
+    /// A template may contain placeholders `{argument}` or `{argument|default}`, which are filled
+    /// with the spec arguments.
+    pub consent_templates: HashMap<String, String>,
+    pub display: CredentialDisplay,
+    /// Display of the stored claims and arguments keyed by name
+    pub claim_display: Option<HashMap<String, ClaimDisplay>>,
+}
+
+impl Storable for CredentialTypeDefinition {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CredentialTypeDefinition"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, CredentialTypeDefinition)
+            .expect("Failed to decode CredentialTypeDefinition")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl CredentialTypeDefinition {
+    pub(crate) fn disclosable_claims(&self) -> &[String] {
+        self.disclosable_claims.as_deref().unwrap_or_default()
+    }
+
+    pub(crate) fn lifetime(&self) -> LifetimePolicy {
+        self.lifetime.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn selection(&self) -> SelectionPolicy {
+        self.selection.clone().unwrap_or_default()
+    }
+
+    /// Returns true if a stored credential with the given types can be used for this credential type.
+    pub(crate) fn accepts(&self, type_: &[String]) -> bool {
+        type_.iter().any(|t| self.accepted_types.contains(t))
+    }
+
+    /// Verifies that every argument of the spec is known for this credential type and has the right kind,
+    /// and that only disclosable claims are requested.
+    pub(crate) fn verify_arguments(&self, spec: &CredentialSpec) -> Result<(), String> {
+        for (name, value) in spec.arguments.iter().flatten() {
+            let supported = (name == LIFETIME_ARGUMENT && matches!(value, ArgumentValue::Int(_)))
+                || (name == DISCLOSE_ARGUMENT && matches!(value, ArgumentValue::String(_)))
+                || (name == FORMAT_ARGUMENT
+                    && matches!(value, ArgumentValue::String(format)
+                        if format == JWT_FORMAT || format == SD_JWT_FORMAT))
+                || self.arguments.iter().any(|arg| {
+                    arg.name == *name
+                        && matches!(
+                            (&arg.kind, value),
+                            (ArgumentKind::Int, ArgumentValue::Int(_))
+                                | (ArgumentKind::String, ArgumentValue::String(_))
+                        )
+                });
+            if !supported {
+                return Err(format!(
+                    "Argument {} with value {} is not supported for credential {}",
+                    name, value, self.name
+                ));
+            }
+        }
+        if let Some(required) = self
+            .required_any_of
+            .as_ref()
+            .filter(|names| !names.is_empty())
+        {
+            let has_any = spec
+                .arguments
+                .as_ref()
+                .is_some_and(|args| required.iter().any(|name| args.contains_key(name)));
+            if !has_any {
+                return Err(format!(
+                    "Credential {} requires at least one of the arguments {}",
+                    self.name,
+                    required.join(", ")
+                ));
+            }
+        }
+        if let Some(claim) = self
+            .disclosed_claims(spec)
+            .into_iter()
+            .find(|claim| !self.disclosable_claims().contains(claim))
+        {
+            return Err(format!(
+                "Claim {} cannot be disclosed for credential {}",
+                claim, self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the stored claims the spec asks to disclose.
+    pub(crate) fn disclosed_claims(&self, spec: &CredentialSpec) -> Vec<String> {
+        let mut claims: Vec<String> = Vec::new();
+        if let Some(ArgumentValue::String(names)) = spec
+            .arguments
+            .as_ref()
+            .and_then(|args| args.get(DISCLOSE_ARGUMENT))
+        {
+            for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                if !claims.iter().any(|claim| claim == name) {
+                    claims.push(name.to_string());
+                }
+            }
+        }
+        claims
+    }
+
+    /// Returns the names of the stored claims that go into the VC. An SD-JWT contains a disclosure
+    /// of every disclosable claim unless the spec asks for specific claims.
+    pub(crate) fn claims_to_disclose(&self, spec: &CredentialSpec) -> Vec<String> {
+        let disclose_requested = spec
+            .arguments
+            .as_ref()
+            .is_some_and(|args| args.contains_key(DISCLOSE_ARGUMENT));
+        if is_sd_jwt(spec) && !disclose_requested {
+            self.disclosable_claims().to_vec()
+        } else {
+            self.disclosed_claims(spec)
+        }
+    }
+
+    /// Checks that the given claims can prove every argument of the spec at the given time.
+    pub(crate) fn is_satisfied_by(
+        &self,
+        spec: &CredentialSpec,
+        claims: &[Claim],
+        now_ns: u64,
+    ) -> bool {
+        spec.arguments.iter().flatten().all(|(name, value)| {
+            // The requested lifetime, disclosure and format are not claims about the holder
+            name == LIFETIME_ARGUMENT
+                || name == DISCLOSE_ARGUMENT
+                || name == FORMAT_ARGUMENT
+                || self
+                    .arguments
+                    .iter()
+                    .find(|arg| arg.name == *name)
+                    .is_some_and(|arg| arg.rule.is_satisfied(value, claims, now_ns))
+        })
+    }
+
+    /// Returns the consent template for the language tag and the tag of the template. The tag is
+    /// shortened (e.g. "de-CH" to "de") before falling back to the default language.
+    pub(crate) fn consent_template(&self, language: &str) -> Option<(String, String)> {
+        localized(&self.consent_templates, language)
+            .map(|(lang, template)| (lang, template.clone()))
+    }
+
+    fn claim_display(&self, name: &str) -> Option<&ClaimDisplay> {
+        self.claim_display.as_ref()?.get(name)
+    }
+
+    /// Returns the label of a stored claim or an argument in the language, or its name.
+    pub(crate) fn claim_label(&self, name: &str, language: &str) -> String {
+        self.claim_display(name)
+            .and_then(|display| localized(&display.labels, language))
+            .map_or_else(|| name.to_string(), |(_, label)| label.clone())
+    }
+
+    /// Returns true if the consent message may show a masked preview of the stored claim.
+    pub(crate) fn shows_masked_preview(&self, name: &str) -> bool {
+        self.claim_display(name)
+            .is_some_and(|display| display.masked_preview)
+    }
+
+    /// Describes the credential type for the relying parties.
+    pub(crate) fn manifest(&self) -> CredentialTypeManifest {
+        let argument = |name: &str, value_type: ArgumentType| ManifestArgument {
+            name: name.to_string(),
+            value_type,
+        };
+        let mut arguments: Vec<ManifestArgument> = self
+            .arguments
+            .iter()
+            .map(|arg| {
+                let value_type = match arg.kind {
+                    ArgumentKind::Int => ArgumentType::Int,
+                    ArgumentKind::String => ArgumentType::String,
+                };
+                argument(&arg.name, value_type)
+            })
+            .collect();
+        arguments.push(argument(LIFETIME_ARGUMENT, ArgumentType::Int));
+        if !self.disclosable_claims().is_empty() {
+            arguments.push(argument(DISCLOSE_ARGUMENT, ArgumentType::String));
+        }
+        arguments.push(argument(FORMAT_ARGUMENT, ArgumentType::String));
+        let mut consent_languages: Vec<String> = self.consent_templates.keys().cloned().collect();
+        consent_languages.sort();
+        let lifetime = self.lifetime();
+        CredentialTypeManifest {
+            credential_type: self.name.clone(),
+            name: self.display.name.clone(),
+            description: self.display.description.clone(),
+            arguments,
+            disclosable_claims: self.disclosable_claims().to_vec(),
+            lifetime: ManifestLifetime {
+                min_seconds: lifetime.min_ns / SECOND_NS,
+                max_seconds: lifetime.max_ns / SECOND_NS,
+                default_seconds: lifetime.default_ns / SECOND_NS,
+            },
+            formats: vec![JWT_FORMAT.to_string(), SD_JWT_FORMAT.to_string()],
+            consent_languages,
+        }
+    }
+
+    /// Checks that the placeholders of a consent template refer to arguments of this type.
+    fn verify_consent_template(&self, template: &str) -> Result<(), String> {
+        for placeholder in template_placeholders(template) {
+            let known = [LIFETIME_ARGUMENT, DISCLOSE_ARGUMENT, FORMAT_ARGUMENT]
+                .contains(&placeholder.name)
+                || self
+                    .arguments
+                    .iter()
+                    .any(|arg| arg.name == placeholder.name);
+            if !known {
+                return Err(format!(
+                    "Placeholder {{{}}} is not an argument of credential {}",
+                    placeholder.name, self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Normalizes the language tags of the consent templates and claim labels, and checks that
+    /// the templates and the claim display refer to arguments and claims of this type.
+    fn normalize_consent(&mut self) -> Result<(), String> {
+        self.consent_templates = normalize_language_tags(&self.consent_templates)?;
+        self.consent_templates
+            .values()
+            .try_for_each(|template| self.verify_consent_template(template))?;
+        for (name, display) in self.claim_display.iter_mut().flatten() {
+            let known = self.disclosable_claims().contains(name)
+                || self.arguments.iter().any(|arg| arg.name == *name);
+            if !known {
+                return Err(format!(
+                    "{} is neither a disclosable claim nor an argument of credential {}",
+                    name, self.name
+                ));
+            }
+            display.labels = normalize_language_tags(&display.labels)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the entry of the map for the language tag and the tag of the entry. The tag is
+/// shortened (e.g. "de-CH" to "de") before falling back to the default language.
+fn localized<'a>(map: &'a HashMap<String, String>, language: &str) -> Option<(String, &'a String)> {
+    language_fallbacks(language)
+        .into_iter()
+        .chain(once(DEFAULT_LANGUAGE.to_string()))
+        .find_map(|lang| map.get(&lang).map(|entry| (lang, entry)))
+}
+
+fn normalize_language_tags(
+    map: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    map.iter()
+        .map(|(language, entry)| {
+            normalize_language_tag(language)
+                .map(|tag| (tag, entry.clone()))
+                .ok_or_else(|| format!("{} is not a valid language tag", language))
+        })
+        .collect()
+}
+
+/// Returns the lowercase form of a BCP-47 language tag, or None if it is malformed. Only the
+/// syntax is checked: a language of 2-8 letters followed by subtags of 1-8 letters or digits.
+pub(crate) fn normalize_language_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+    let mut subtags = tag.split('-');
+    let language = subtags.next()?;
+    let valid = (2..=8).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    valid.then_some(tag)
+}
+
+/// Returns the language tag followed by its shorter forms, in the order of the lookup of RFC 4647
+/// (e.g. "de-CH-1996" gives "de-ch-1996", "de-ch" and "de"). A malformed tag has none.
+pub(crate) fn language_fallbacks(tag: &str) -> Vec<String> {
+    let Some(tag) = normalize_language_tag(tag) else {
+        return vec![];
+    };
+    let mut subtags: Vec<&str> = tag.split('-').collect();
+    let mut fallbacks = Vec::new();
+    while !subtags.is_empty() {
+        fallbacks.push(subtags.join("-"));
+        subtags.pop();
+        // A single character subtag (e.g. "x" of private use subtags) is never the last one
+        if subtags.last().is_some_and(|subtag| subtag.len() == 1) {
+            subtags.pop();
+        }
+    }
+    fallbacks
+}
+
+impl ArgumentRule {
+    fn is_satisfied(&self, value: &ArgumentValue, claims: &[Claim], now_ns: u64) -> bool {
+        match (self, value) {
+            (ArgumentRule::ClaimEquals(claim), value) => claims
+                .iter()
+                .any(|c| c.claims.get(claim).is_some_and(|v| claim_equals(v, value))),
+            (
+                ArgumentRule::ClaimAtLeast {
+                    claim,
+                    implied_by_type,
+                },
+                ArgumentValue::Int(min),
+            ) => {
+                implied_by_type.is_some_and(|implied| *min <= implied)
+                    || claims.iter().any(|c| {
+                        matches!(
+                            c.claims.get(claim),
+                            Some(ClaimValue::Number(n)) if *n >= i64::from(*min)
+                        )
+                    })
+            }
+            (ArgumentRule::AgeAtLeast { birth_date_claim }, ArgumentValue::Int(min)) => {
+                age_from_claims(claims, birth_date_claim, now_ns)
+                    .is_some_and(|age| age >= i64::from(*min))
+            }
+            (ArgumentRule::AgeAtMost { birth_date_claim }, ArgumentValue::Int(max)) => {
+                age_from_claims(claims, birth_date_claim, now_ns)
+                    .is_some_and(|age| age <= i64::from(*max))
+            }
+            (ArgumentRule::AnyOf(rules), value) => rules
+                .iter()
+                .any(|rule| rule.is_satisfied(value, claims, now_ns)),
+            _ => false,
+        }
+    }
+}
+
+/// Returns true if the spec asks for the VC to be issued as SD-JWT.
+pub(crate) fn is_sd_jwt(spec: &CredentialSpec) -> bool {
+    matches!(
+        spec.arguments.as_ref().and_then(|args| args.get(FORMAT_ARGUMENT)),
+        Some(ArgumentValue::String(format)) if format == SD_JWT_FORMAT
+    )
+}
+
+/// Computes the age in full years of the holder from the first valid birth date of the claims, so
+/// that every age argument (e.g. both bounds of an age range) is checked against the same date.
+fn age_from_claims(claims: &[Claim], birth_date_claim: &str, now_ns: u64) -> Option<i64> {
+    let birth_date = claims
+        .iter()
+        .find_map(|c| match c.claims.get(birth_date_claim)? {
+            ClaimValue::Date(date) | ClaimValue::Text(date) => parse_date(date),
+            _ => None,
+        })?;
+    let today = civil_from_days((now_ns / DAY_NS) as i64);
+    Some(age_on(birth_date, today))
+}
+
+/// Age in full years on the given day. Holders born on February 29 turn a year older on March 1
+/// in common years.
+fn age_on(birth_date: (i64, u32, u32), today: (i64, u32, u32)) -> i64 {
+    let (birth_year, birth_month, birth_day) = birth_date;
+    let (year, month, day) = today;
+    let had_birthday = (month, day) >= (birth_month, birth_day);
+    year - birth_year - if had_birthday { 0 } else { 1 }
+}
+
+/// Parses the date part of an ISO 8601 date ("YYYY-MM-DD", optionally followed by a time).
+fn parse_date(date: &str) -> Option<(i64, u32, u32)> {
+    let date = date.get(..10)?;
+    let mut parts = date.split('-');
+    let year = parts.next().filter(|y| y.len() == 4)?.parse().ok()?;
+    let month = parts.next().filter(|m| m.len() == 2)?.parse().ok()?;
+    let day = parts.next().filter(|d| d.len() == 2)?.parse().ok()?;
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some((year, month, day))
+}
+
+/// Converts days since the Unix epoch into a (year, month, day) date of the proleptic Gregorian
+/// calendar (cf. http://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day)
+}
+
+fn claim_equals(claim: &ClaimValue, value: &ArgumentValue) -> bool {
+    match (claim, value) {
+        (ClaimValue::Text(t) | ClaimValue::Date(t), ArgumentValue::String(s)) => t == s,
+        (ClaimValue::Number(n), ArgumentValue::Int(i)) => *n == i64::from(*i),
+        _ => false,
+    }
+}
+
+/// The credential types the registry is seeded with on first install.
+pub(crate) fn default_credential_types() -> Vec<CredentialTypeDefinition> {
+    vec![
+        CredentialTypeDefinition {
+            name: "VerifiedAdult".to_string(),
+            accepted_types: vec!["VerifiedAdult".to_string()],
+            arguments: vec![ArgumentDefinition {
+                name: "minAge".to_string(),
+                kind: ArgumentKind::Int,
+                rule: ArgumentRule::AnyOf(vec![
+                    ArgumentRule::ClaimAtLeast {
+                        claim: "age".to_string(),
+                        implied_by_type: Some(18),
+                    },
+                    ArgumentRule::AgeAtLeast {
+                        birth_date_claim: BIRTH_DATE_CLAIM.to_string(),
+                    },
+                ]),
+            }],
+            required_any_of: None,
+            disclosable_claims: Some(vec![]),
+            lifetime: Some(LifetimePolicy::default()),
+            selection: Some(SelectionPolicy::default()),
+            consent_templates: HashMap::from([
+                ("en".to_string(), ADULT_VC_DESCRIPTION_EN.to_string()),
+                ("de".to_string(), ADULT_VC_DESCRIPTION_DE.to_string()),
+            ]),
+            display: CredentialDisplay {
+                name: "Verified Adult".to_string(),
+                description: "The holder's age is at least 18 years.".to_string(),
+                icon: None,
+            },
+            claim_display: Some(HashMap::from([(
+                "minAge".to_string(),
+                claim_labels("Minimum age", "Mindestalter"),
+            )])),
+        },
+        CredentialTypeDefinition {
+            name: "AgeRange".to_string(),
+            accepted_types: vec!["VerifiedAge".to_string(), "VerifiedAdult".to_string()],
+            arguments: vec![
+                ArgumentDefinition {
+                    name: "min".to_string(),
+                    kind: ArgumentKind::Int,
+                    rule: ArgumentRule::AgeAtLeast {
+                        birth_date_claim: BIRTH_DATE_CLAIM.to_string(),
+                    },
+                },
+                ArgumentDefinition {
+                    name: "max".to_string(),
+                    kind: ArgumentKind::Int,
+                    rule: ArgumentRule::AgeAtMost {
+                        birth_date_claim: BIRTH_DATE_CLAIM.to_string(),
+                    },
+                },
+            ],
+            required_any_of: Some(vec!["min".to_string(), "max".to_string()]),
+            disclosable_claims: Some(vec![]),
+            lifetime: Some(LifetimePolicy::default()),
+            selection: Some(SelectionPolicy::default()),
+            consent_templates: HashMap::from([
+                ("en".to_string(), AGE_RANGE_VC_DESCRIPTION_EN.to_string()),
+                ("de".to_string(), AGE_RANGE_VC_DESCRIPTION_DE.to_string()),
+            ]),
+            display: CredentialDisplay {
+                name: "Verified Age Range".to_string(),
+                description: "The holder's age is within the requested range.".to_string(),
+                icon: None,
+            },
+            claim_display: Some(HashMap::from([
+                (
+                    "min".to_string(),
+                    claim_labels("Minimum age", "Mindestalter"),
+                ),
+                (
+                    "max".to_string(),
+                    claim_labels("Maximum age", "Höchstalter"),
+                ),
+            ])),
+        },
+    ]
+}
+
+/// Display with English and German labels and without preview
+fn claim_labels(en: &str, de: &str) -> ClaimDisplay {
+    ClaimDisplay {
+        labels: HashMap::from([
+            ("en".to_string(), en.to_string()),
+            ("de".to_string(), de.to_string()),
+        ]),
+        masked_preview: false,
+    }
+}
+
+/// Seed the registry with the default credential types if it is empty.
+pub(crate) fn init_credential_types() {
+    CREDENTIAL_TYPES.with_borrow_mut(|types| {
+        if types.is_empty() {
+            for definition in default_credential_types() {
+                types.insert(definition.name.clone(), definition);
+            }
+        }
+    });
+}
+
+/// Get the registry entry for the given credential type.
+pub(crate) fn get_credential_type(name: &str) -> Option<CredentialTypeDefinition> {
+    CREDENTIAL_TYPES.with_borrow(|types| types.get(&name.to_string()))
+}
+
+/// Adds or replaces a credential type in the registry. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn set_credential_type(mut definition: CredentialTypeDefinition) -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    if definition.name.is_empty() || definition.accepted_types.is_empty() {
+        return Err(IssuerError::UnsupportedCredentialSpec(
+            "A credential type needs a name and at least one accepted type.".to_string(),
+        ));
+    }
+    if !definition.lifetime().is_valid() {
+        return Err(invalid_lifetime_policy());
+    }
+    definition
+        .normalize_consent()
+        .map_err(IssuerError::UnsupportedCredentialSpec)?;
+    CREDENTIAL_TYPES.with_borrow_mut(|types| types.insert(definition.name.clone(), definition));
+    Ok(())
+}
+
+/// Sets the lifetime policy of a credential type. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn set_credential_lifetime_policy(name: String, policy: LifetimePolicy) -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    if !policy.is_valid() {
+        return Err(invalid_lifetime_policy());
+    }
+    let mut definition = get_credential_type(&name).ok_or_else(|| {
+        IssuerError::UnsupportedCredentialSpec(format!("Credential {} is not supported", name))
+    })?;
+    definition.lifetime = Some(policy);
+    CREDENTIAL_TYPES.with_borrow_mut(|types| types.insert(name, definition));
+    Ok(())
+}
+
+/// Sets the policy used to choose between several stored credentials of a credential type. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn set_credential_selection_policy(
+    name: String,
+    policy: SelectionPolicy,
+) -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    let mut definition = get_credential_type(&name).ok_or_else(|| {
+        IssuerError::UnsupportedCredentialSpec(format!("Credential {} is not supported", name))
+    })?;
+    definition.selection = Some(policy);
+    CREDENTIAL_TYPES.with_borrow_mut(|types| types.insert(name, definition));
+    Ok(())
+}
+
+/// Sets the consent template of a credential type for a BCP-47 language tag, or removes it if no
+/// template is given. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn set_consent_template(
+    name: String,
+    language: String,
+    template: Option<String>,
+) -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    let mut definition = get_credential_type(&name).ok_or_else(|| {
+        IssuerError::UnsupportedCredentialSpec(format!("Credential {} is not supported", name))
+    })?;
+    let tag = normalize_language_tag(&language).ok_or_else(|| {
+        IssuerError::UnsupportedCredentialSpec(format!("{} is not a valid language tag", language))
+    })?;
+    match template {
+        Some(template) => {
+            definition
+                .verify_consent_template(&template)
+                .map_err(IssuerError::UnsupportedCredentialSpec)?;
+            definition.consent_templates.insert(tag, template);
+        }
+        None => {
+            definition.consent_templates.remove(&tag);
+        }
+    }
+    CREDENTIAL_TYPES.with_borrow_mut(|types| types.insert(name, definition));
+    Ok(())
+}
+
+fn invalid_lifetime_policy() -> IssuerError {
+    IssuerError::UnsupportedCredentialSpec(
+        "The lifetime policy must satisfy 0 < min <= default <= max.".to_string(),
+    )
+}
+
+/// Removes a credential type from the registry. Can only be called by the admin.
+#[update]
+#[candid_method]
+fn remove_credential_type(name: String) -> Result<(), IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    CREDENTIAL_TYPES.with_borrow_mut(|types| types.remove(&name));
+    Ok(())
+}
+
+/// Retrieves all credential types of the registry.
+#[query]
+#[candid_method(query)]
+fn get_credential_types() -> Vec<CredentialTypeDefinition> {
+    CREDENTIAL_TYPES.with_borrow(|types| types.iter().map(|(_, definition)| definition).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adult_spec(min_age: Option<i32>) -> CredentialSpec {
+        CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: min_age
+                .map(|age| HashMap::from([("minAge".to_string(), ArgumentValue::Int(age))])),
+        }
+    }
+
+    fn claims(claims: HashMap<String, ClaimValue>) -> Vec<Claim> {
+        vec![Claim { claims }]
+    }
+
+    /// Test that the minAge argument is checked against what the stored claims can prove
+    #[test]
+    fn test_min_age_is_satisfied_by_claims() {
+        let adult = default_credential_types().remove(0);
+        let no_age = claims(HashMap::new());
+        assert!(adult.is_satisfied_by(&adult_spec(None), &no_age, 0));
+        assert!(adult.is_satisfied_by(&adult_spec(Some(18)), &no_age, 0));
+        assert!(!adult.is_satisfied_by(&adult_spec(Some(21)), &no_age, 0));
+
+        let age_25 = claims(HashMap::from([("age".to_string(), ClaimValue::Number(25))]));
+        assert!(adult.is_satisfied_by(&adult_spec(Some(21)), &age_25, 0));
+        assert!(!adult.is_satisfied_by(&adult_spec(Some(30)), &age_25, 0));
+    }
+
+    /// Test that age predicates are derived from the stored birth date at the given time
+    #[test]
+    fn test_age_is_derived_from_birth_date() {
+        // 2024-06-15T12:00:00Z
+        let now_ns = 1_718_452_800 * SECOND_NS;
+        let born = |date: &str| {
+            claims(HashMap::from([(
+                BIRTH_DATE_CLAIM.to_string(),
+                ClaimValue::Date(date.to_string()),
+            )]))
+        };
+        let adult = default_credential_types().remove(0);
+        assert!(adult.is_satisfied_by(&adult_spec(Some(21)), &born("2003-06-15"), now_ns));
+        assert!(!adult.is_satisfied_by(&adult_spec(Some(21)), &born("2003-06-16"), now_ns));
+        assert!(!adult.is_satisfied_by(&adult_spec(Some(21)), &born("June 2003"), now_ns));
+
+        let age_range = default_credential_types().remove(1);
+        let range_spec = |min: i32, max: i32| CredentialSpec {
+            credential_type: "AgeRange".to_string(),
+            arguments: Some(HashMap::from([
+                ("min".to_string(), ArgumentValue::Int(min)),
+                ("max".to_string(), ArgumentValue::Int(max)),
+            ])),
+        };
+        let born_1990 = born("1990-12-31T00:00:00Z");
+        assert!(age_range.is_satisfied_by(&range_spec(25, 34), &born_1990, now_ns));
+        assert!(!age_range.is_satisfied_by(&range_spec(34, 40), &born_1990, now_ns));
+        assert!(!age_range.is_satisfied_by(&range_spec(18, 32), &born_1990, now_ns));
+
+        // Both bounds are checked against the same birth date
+        let mut two_birth_dates = born("2004-01-01");
+        two_birth_dates.extend(born("1950-01-01"));
+        assert!(!age_range.is_satisfied_by(&range_spec(60, 70), &two_birth_dates, now_ns));
+        assert!(age_range.is_satisfied_by(&range_spec(18, 25), &two_birth_dates, now_ns));
+    }
+
+    /// Test the conversion of days to dates and the age on birthdays
+    #[test]
+    fn test_age_on() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_783), (2024, 3, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(parse_date("2000-02-29"), Some((2000, 2, 29)));
+        assert_eq!(parse_date("2000-13-01"), None);
+        assert_eq!(age_on((2000, 2, 29), (2018, 2, 28)), 17);
+        assert_eq!(age_on((2000, 2, 29), (2018, 3, 1)), 18);
+        assert_eq!(age_on((2000, 2, 29), (2024, 2, 29)), 24);
+    }
+
+    /// Test that unknown arguments and arguments of the wrong kind are rejected
+    #[test]
+    fn test_verify_arguments() {
+        let adult = default_credential_types().remove(0);
+        assert!(adult.verify_arguments(&adult_spec(Some(18))).is_ok());
+
+        let mut spec = adult_spec(None);
+        spec.arguments = Some(HashMap::from([(
+            "country".to_string(),
+            ArgumentValue::String("CH".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_err());
+
+        spec.arguments = Some(HashMap::from([(
+            "minAge".to_string(),
+            ArgumentValue::String("18".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_err());
+
+        // An age range needs at least one bound
+        let age_range = default_credential_types().remove(1);
+        let mut spec = CredentialSpec {
+            credential_type: "AgeRange".to_string(),
+            arguments: None,
+        };
+        assert!(age_range.verify_arguments(&spec).is_err());
+        spec.arguments = Some(HashMap::from([(
+            LIFETIME_ARGUMENT.to_string(),
+            ArgumentValue::Int(60),
+        )]));
+        assert!(age_range.verify_arguments(&spec).is_err());
+        spec.arguments = Some(HashMap::from([("max".to_string(), ArgumentValue::Int(30))]));
+        assert!(age_range.verify_arguments(&spec).is_ok());
+    }
+
+    /// Test that only disclosable claims can be requested and the names are parsed in order
+    #[test]
+    fn test_disclosed_claims() {
+        let mut adult = default_credential_types().remove(0);
+        adult.disclosable_claims = Some(vec!["name".to_string(), "country".to_string()]);
+        let mut spec = adult_spec(None);
+        assert!(adult.disclosed_claims(&spec).is_empty());
+
+        spec.arguments = Some(HashMap::from([(
+            DISCLOSE_ARGUMENT.to_string(),
+            ArgumentValue::String("country, name,,country".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_ok());
+        assert!(adult.is_satisfied_by(&spec, &[], 0));
+        assert_eq!(adult.disclosed_claims(&spec), vec!["country", "name"]);
+
+        spec.arguments = Some(HashMap::from([(
+            DISCLOSE_ARGUMENT.to_string(),
+            ArgumentValue::String("name,dateOfBirth".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_err());
+
+        // An SD-JWT has a disclosure of every disclosable claim unless specific ones are requested
+        spec.arguments = Some(HashMap::from([(
+            FORMAT_ARGUMENT.to_string(),
+            ArgumentValue::String("sd-jwt".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_ok());
+        assert_eq!(adult.claims_to_disclose(&spec), vec!["name", "country"]);
+        spec.arguments = Some(HashMap::from([(
+            FORMAT_ARGUMENT.to_string(),
+            ArgumentValue::String("mdoc".to_string()),
+        )]));
+        assert!(adult.verify_arguments(&spec).is_err());
+    }
+
+    /// Test that the requested lifetime is clamped to the policy
+    #[test]
+    fn test_vc_lifetime_is_clamped_to_policy() {
+        let policy = default_credential_types().remove(0).lifetime();
+        let lifetime_spec = |seconds: i32| CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(HashMap::from([(
+                LIFETIME_ARGUMENT.to_string(),
+                ArgumentValue::Int(seconds),
+            )])),
+        };
+        assert_eq!(policy.vc_lifetime_ns(&adult_spec(None)), DEFAULT_VC_LIFETIME_NS);
+        assert_eq!(policy.vc_lifetime_ns(&lifetime_spec(120)), 120 * SECOND_NS);
+        assert_eq!(policy.vc_lifetime_ns(&lifetime_spec(1)), MIN_VC_LIFETIME_NS);
+        assert_eq!(policy.vc_lifetime_ns(&lifetime_spec(-5)), MIN_VC_LIFETIME_NS);
+        assert_eq!(policy.vc_lifetime_ns(&lifetime_spec(i32::MAX)), MAX_VC_LIFETIME_NS);
+    }
+
+    /// Test the fallback to shorter language tags and the default language for consent templates
+    #[test]
+    fn test_consent_template_language_fallback() {
+        let mut adult = default_credential_types().remove(0);
+        adult
+            .consent_templates
+            .insert("de-ch".to_string(), "Grüezi".to_string());
+        assert_eq!(adult.consent_template("de").unwrap().0, "de");
+        assert_eq!(adult.consent_template("de-CH").unwrap().0, "de-ch");
+        assert_eq!(adult.consent_template("de_CH-1996").unwrap().0, "de-ch");
+        assert_eq!(adult.consent_template("de-AT").unwrap().0, "de");
+        assert_eq!(adult.consent_template("ja").unwrap().0, DEFAULT_LANGUAGE);
+        for malformed in ["", "d", "*", "x-private", "de--ch", "ü"] {
+            assert_eq!(
+                adult.consent_template(malformed).unwrap().0,
+                DEFAULT_LANGUAGE
+            );
+        }
+    }
+
+    /// Test the shorter forms of language tags
+    #[test]
+    fn test_language_fallbacks() {
+        assert_eq!(language_fallbacks("de-CH"), vec!["de-ch", "de"]);
+        assert_eq!(
+            language_fallbacks("zh-Hant-CN-x-private1"),
+            vec!["zh-hant-cn-x-private1", "zh-hant-cn", "zh-hant", "zh"]
+        );
+        assert!(language_fallbacks("en-").is_empty());
+    }
+
+    /// Test that the language tags of the templates are normalized and their placeholders checked
+    #[test]
+    fn test_normalize_consent() {
+        let mut adult = default_credential_types().remove(0);
+        adult.consent_templates = HashMap::from([(
+            "de-CH".to_string(),
+            "Mindestens {minAge|18} Jahre".to_string(),
+        )]);
+        assert_eq!(adult.normalize_consent(), Ok(()));
+        assert!(adult.consent_templates.contains_key("de-ch"));
+
+        adult.consent_templates = HashMap::from([("en".to_string(), "{country}".to_string())]);
+        assert!(adult.normalize_consent().is_err());
+        adult.consent_templates = HashMap::from([("e".to_string(), "Hello".to_string())]);
+        assert!(adult.normalize_consent().is_err());
+
+        // Only arguments and disclosable claims have a display
+        adult.consent_templates = HashMap::new();
+        adult.claim_display = Some(HashMap::from([(
+            "country".to_string(),
+            claim_labels("Country", "Land"),
+        )]));
+        assert!(adult.normalize_consent().is_err());
+        adult.disclosable_claims = Some(vec!["country".to_string()]);
+        assert_eq!(adult.normalize_consent(), Ok(()));
+        assert_eq!(adult.claim_label("country", "de-CH"), "Land");
+        assert_eq!(adult.claim_label("country", "fr"), "Country");
+        assert_eq!(adult.claim_label("city", "de"), "city");
+    }
+
+    /// Test that an entry stored before the lifetime, selection and disclosable claims were added
+    /// can be decoded and gets the defaults
+    #[test]
+    fn test_decode_entry_without_added_fields() {
+        #[derive(CandidType)]
+        struct EarlierCredentialTypeDefinition {
+            name: String,
+            accepted_types: Vec<String>,
+            arguments: Vec<ArgumentDefinition>,
+            consent_templates: HashMap<String, String>,
+            display: CredentialDisplay,
+            claim_display: Option<HashMap<String, ClaimDisplay>>,
+        }
+
+        let adult = default_credential_types().remove(0);
+        let earlier = EarlierCredentialTypeDefinition {
+            name: adult.name.clone(),
+            accepted_types: adult.accepted_types.clone(),
+            arguments: adult.arguments.clone(),
+            consent_templates: adult.consent_templates.clone(),
+            display: adult.display.clone(),
+            claim_display: adult.claim_display.clone(),
+        };
+        let decoded = CredentialTypeDefinition::from_bytes(Cow::Owned(Encode!(&earlier).unwrap()));
+        assert_eq!(decoded.name, adult.name);
+        assert!(decoded.disclosable_claims().is_empty());
+        assert_eq!(decoded.lifetime(), LifetimePolicy::default());
+        assert_eq!(decoded.selection(), SelectionPolicy::default());
+    }
+
+    /// Test that the manifest lists the arguments with the ones accepted for every type
+    #[test]
+    fn test_manifest() {
+        let manifest = default_credential_types().remove(0).manifest();
+        assert_eq!(manifest.credential_type, "VerifiedAdult");
+        let arguments: Vec<(&str, &ArgumentType)> = manifest
+            .arguments
+            .iter()
+            .map(|arg| (arg.name.as_str(), &arg.value_type))
+            .collect();
+        assert_eq!(
+            arguments,
+            vec![
+                ("minAge", &ArgumentType::Int),
+                (LIFETIME_ARGUMENT, &ArgumentType::Int),
+                (FORMAT_ARGUMENT, &ArgumentType::String),
+            ]
+        );
+        assert_eq!(manifest.lifetime.default_seconds, 15 * 60);
+        assert_eq!(manifest.consent_languages, vec!["de", "en"]);
+    }
+}
@@ -0,0 +1,83 @@
+//! The DID document and the JWKS of the issuer canister, served as certified assets at
+//! `/.well-known/did.json` and `/.well-known/jwks.json`, so that verifiers can get the keys of
+//! the canister signatures without calling the canister.
+//!
+//! The DID document is the `did:web` DID document of the configured derivation origin, which it is
+//! served at. It lists the `did:icp` DID of the canister, which is the `kid` in the JWS header of
+//! the VCs, and the configured `iss` of the VCs as `alsoKnownAs`. Both documents contain the
+//! current and the previous signing keys and are certified again whenever the signing keys or the
+//! configuration change.
+use asset_util::{Asset, CertifiedAssets, ContentEncoding, ContentType};
+use ic_cdk::api::time;
+use serde_json::{json, Value};
+use vc_util::{canister_sig_pk_jwk, did_for_principal};
+
+use crate::config::{
+    configured_derivation_origin, did_web_of_host, static_headers, vc_issuer, ASSETS,
+};
+use crate::credential::update_root_hash;
+use crate::signing_key::valid_signing_seeds;
+
+const DID_DOCUMENT_PATH: &str = "/.well-known/did.json";
+const JWKS_PATH: &str = "/.well-known/jwks.json";
+const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+const JWS_2020_CONTEXT: &str = "https://w3id.org/security/suites/jws-2020/v1";
+
+/// Certifies the DID document and the JWKS. The caller must update the certified data afterwards.
+pub(crate) fn certify_did_document(assets: &mut CertifiedAssets) {
+    let origin = configured_derivation_origin();
+    let did = did_web_of_host(origin.split_once("://").map_or(&origin, |(_, host)| host));
+    // The JWK of each key is identified by its verification method
+    let keys: Vec<(String, Value)> = valid_signing_seeds(time())
+        .iter()
+        .map(|seed| {
+            let kid = format!("{}#key-{}", did, seed.id);
+            let mut jwk = canister_sig_pk_jwk(&seed.public_key().to_der())
+                .expect("internal: failed creating JWK");
+            jwk.set_kid(kid.clone());
+            let jwk = serde_json::to_value(jwk).expect("internal: failed serializing JWK");
+            (kid, jwk)
+        })
+        .collect();
+    let also_known_as = vec![did_for_principal(ic_cdk::id()), vc_issuer()];
+    let verification_methods: Vec<Value> = keys
+        .iter()
+        .map(|(kid, jwk)| {
+            json!({
+                "id": kid,
+                "type": "JsonWebKey2020",
+                "controller": did,
+                "publicKeyJwk": jwk,
+            })
+        })
+        .collect();
+    let key_ids: Vec<&String> = keys.iter().map(|(kid, _)| kid).collect();
+    let did_document = json!({
+        "@context": [DID_CONTEXT, JWS_2020_CONTEXT],
+        "id": did,
+        "alsoKnownAs": also_known_as,
+        "verificationMethod": verification_methods,
+        "assertionMethod": key_ids,
+    });
+    let jwks = json!({
+        "keys": keys.iter().map(|(_, jwk)| jwk).collect::<Vec<&Value>>(),
+    });
+
+    for (url_path, document) in [(DID_DOCUMENT_PATH, did_document), (JWKS_PATH, jwks)] {
+        assets.certify_asset(
+            Asset {
+                url_path: url_path.to_string(),
+                content: document.to_string().into_bytes(),
+                encoding: ContentEncoding::Identity,
+                content_type: ContentType::JSON,
+            },
+            &static_headers(),
+        );
+    }
+}
+
+/// Certifies the DID document and the JWKS again after the signing keys changed.
+pub(crate) fn update_did_document() {
+    ASSETS.with_borrow_mut(certify_did_document);
+    update_root_hash();
+}
@@ -0,0 +1,137 @@
+//! Keeps a log of the VCs that were issued from the credentials of each holder.
+//!
+//! Only the holder can read their own history. The relying party a VC was issued for is
+//! never recorded, so the log does not break the unlinkability provided by Internet Identity.
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_cdk_macros::query;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use vc_util::issuer_api::ArgumentValue;
+
+use crate::config::ISSUANCE_HISTORY;
+
+/// Maximum number of events kept per holder, older events are dropped first.
+const MAX_ISSUANCE_EVENTS_PER_HOLDER: usize = 100;
+
+/// A single VC issuance for a holder.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct IssuanceEvent {
+    pub timestamp_ns: u64,
+    pub credential_type: String,
+    pub credential_id: String,
+    pub arguments: Option<HashMap<String, ArgumentValue>>,
+}
+
+/// Wrapper around the events of a holder so that they can be stored inside Stable Storage
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IssuanceHistory(Vec<IssuanceEvent>);
+
+impl Storable for IssuanceHistory {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(&self.0).expect("Failed to encode IssuanceHistory"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        IssuanceHistory(
+            Decode!(&bytes, Vec<IssuanceEvent>).expect("Failed to decode IssuanceHistory"),
+        )
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl IssuanceHistory {
+    /// Append an event, dropping the oldest ones if the cap is reached.
+    fn push(&mut self, event: IssuanceEvent) {
+        self.0.push(event);
+        if self.0.len() > MAX_ISSUANCE_EVENTS_PER_HOLDER {
+            let excess = self.0.len() - MAX_ISSUANCE_EVENTS_PER_HOLDER;
+            self.0.drain(..excess);
+        }
+    }
+}
+
+/// Record that a VC was issued from one of the holder's credentials.
+pub(crate) fn record_issuance(
+    holder: Principal,
+    credential_type: String,
+    credential_id: String,
+    arguments: Option<HashMap<String, ArgumentValue>>,
+) {
+    ISSUANCE_HISTORY.with_borrow_mut(|history| {
+        let mut events = history.get(&holder).unwrap_or_default();
+        events.push(IssuanceEvent {
+            timestamp_ns: time(),
+            credential_type,
+            credential_id,
+            arguments,
+        });
+        history.insert(holder, events);
+    });
+}
+
+/// Move the issuance history of `from` to `to`, merging it with any events `to` already has.
+/// If credential ids are given, only the events of these credentials are moved.
+pub(crate) fn move_issuance_history(
+    from: &Principal,
+    to: Principal,
+    credential_ids: Option<&[String]>,
+) {
+    ISSUANCE_HISTORY.with_borrow_mut(|history| {
+        if let Some(moved) = history.remove(from) {
+            let (mut events, kept): (Vec<IssuanceEvent>, Vec<IssuanceEvent>) = moved
+                .0
+                .into_iter()
+                .partition(|e| credential_ids.map_or(true, |ids| ids.contains(&e.credential_id)));
+            if !kept.is_empty() {
+                history.insert(*from, IssuanceHistory(kept));
+            }
+            events.extend(history.get(&to).unwrap_or_default().0);
+            events.sort_by_key(|e| e.timestamp_ns);
+
+            let mut merged = IssuanceHistory::default();
+            for event in events {
+                merged.push(event);
+            }
+            history.insert(to, merged);
+        }
+    });
+}
+
+/// Retrieves the issuance history of the caller, oldest event first.
+#[query]
+#[candid_method(query)]
+fn my_issuance_history() -> Vec<IssuanceEvent> {
+    ISSUANCE_HISTORY
+        .with_borrow(|history| history.get(&caller()))
+        .map(|events| events.0)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: usize) -> IssuanceEvent {
+        IssuanceEvent {
+            timestamp_ns: id as u64,
+            credential_type: "VerifiedAdult".to_string(),
+            credential_id: format!("http://example.edu/credentials/{}", id),
+            arguments: None,
+        }
+    }
+
+    /// Test that the oldest events are dropped once the cap is reached
+    #[test]
+    fn test_history_is_capped() {
+        let mut history = IssuanceHistory::default();
+        for i in 0..MAX_ISSUANCE_EVENTS_PER_HOLDER + 5 {
+            history.push(event(i));
+        }
+        assert_eq!(history.0.len(), MAX_ISSUANCE_EVENTS_PER_HOLDER);
+        assert_eq!(history.0[0].timestamp_ns, 5);
+    }
+}
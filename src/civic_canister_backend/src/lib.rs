@@ -1,6 +1,19 @@
 //! Main library entry point for civic_canister_backend crate.
 
+pub mod alternative_origins;
+pub mod assets;
+pub mod call_consent_message;
 pub mod config;
 pub mod credential;
+pub mod credential_request;
+pub mod credential_type;
 pub mod consent_message;
+pub mod did_document;
+pub mod history;
+pub mod manifest;
+pub mod migration;
 
+pub mod prepared_context;
+pub mod sd_jwt;
+pub mod signing_key;
+pub mod status_list;
@@ -0,0 +1,51 @@
+//! Discovery of the canister's capabilities: the standards it supports (ICRC-10) and the manifest
+//! of the credential types it issues VCs for, so that relying parties don't have to read the
+//! source to build a credential spec.
+use candid::candid_method;
+use ic_cdk_macros::query;
+use vc_util::issuer_api::{
+    Icrc10SupportedStandard, ManifestData, ManifestRequest, ManifestResponse,
+};
+
+use crate::config::CREDENTIAL_TYPES;
+
+const SUPPORTED_STANDARDS: [(&str, &str); 3] = [
+    (
+        "ICRC-10",
+        "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md",
+    ),
+    (
+        "ICRC-21",
+        "https://github.com/dfinity/wg-identity-authentication/blob/main/topics/ICRC-21/icrc_21_consent_msg.md",
+    ),
+    (
+        "VC-ISSUER",
+        "https://github.com/dfinity/internet-identity/blob/main/docs/vc-spec.md",
+    ),
+];
+
+/// Lists the standards the canister implements.
+#[query]
+#[candid_method(query)]
+fn icrc10_supported_standards() -> Vec<Icrc10SupportedStandard> {
+    SUPPORTED_STANDARDS
+        .iter()
+        .map(|(name, url)| Icrc10SupportedStandard {
+            name: name.to_string(),
+            url: url.to_string(),
+        })
+        .collect()
+}
+
+/// Describes the credential types of the registry.
+#[query]
+#[candid_method(query)]
+fn vc_manifest(_req: ManifestRequest) -> ManifestResponse {
+    let credential_types = CREDENTIAL_TYPES.with_borrow(|types| {
+        types
+            .iter()
+            .map(|(_, definition)| definition.manifest())
+            .collect()
+    });
+    ManifestResponse::Ok(ManifestData { credential_types })
+}
@@ -0,0 +1,196 @@
+//! Moves the credentials of a holder to a new principal, e.g. after they lost their II anchor.
+//!
+//! There are two ways to migrate:
+//! - The admin or an authorized issuer moves the credentials directly with `migrate_credentials`.
+//!   An issuer can only move the credentials it issued, the admin moves all of them.
+//! - The holder proves control of both principals: the old principal approves the move with
+//!   `approve_credential_migration` and the new principal completes it with `complete_credential_migration`.
+//!   Both calls are signed by the respective principal, so no other proof is needed.
+//!   The approval is kept in stable memory, so it survives upgrades, and expires after 15 minutes.
+//!
+//! Every migration is recorded in an audit log in stable memory.
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::config::{is_admin, MIGRATION_LOG, PENDING_MIGRATIONS};
+use crate::credential::{is_authorized_issuer, move_credentials, CredentialError};
+use crate::history::move_issuance_history;
+use crate::status_list::move_status_entries;
+
+// How long the new principal has to complete a migration approved by the old principal.
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+const MIGRATION_APPROVAL_PERIOD_NS: u64 = 15 * MINUTE_NS;
+
+/// Audit entry for credentials that were moved between principals.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct MigrationRecord {
+    pub timestamp_ns: u64,
+    pub from: Principal,
+    pub to: Principal,
+    /// The principal that triggered the migration (issuer, admin or the new principal of the holder)
+    pub initiated_by: Principal,
+    pub credential_count: u64,
+}
+
+impl Storable for MigrationRecord {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode MigrationRecord"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, MigrationRecord).expect("Failed to decode MigrationRecord")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Migration approved by the old principal of a holder.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct PendingMigration {
+    to: Principal,
+    expires_at_ns: u64,
+}
+
+impl Storable for PendingMigration {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode PendingMigration"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, PendingMigration).expect("Failed to decode PendingMigration")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Moves the credentials of `from` to `to`. The admin moves all credentials, an authorized issuer
+/// only the ones it issued.
+#[update]
+#[candid_method]
+async fn migrate_credentials(from: Principal, to: Principal) -> Result<String, CredentialError> {
+    let caller = caller();
+    let issuer = if is_admin(caller) {
+        None
+    } else if is_authorized_issuer(caller) {
+        Some(caller)
+    } else {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: You do not have permission to migrate credentials.".to_string(),
+        ));
+    };
+    migrate(from, to, caller, issuer)
+}
+
+/// Called by the old principal of a holder to allow `to` to claim its credentials.
+#[update]
+#[candid_method]
+async fn approve_credential_migration(to: Principal) -> Result<String, CredentialError> {
+    let from = caller();
+    if from == Principal::anonymous() || to == Principal::anonymous() {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: Credentials cannot be migrated from or to the anonymous principal."
+                .to_string(),
+        ));
+    }
+    let now = time();
+    PENDING_MIGRATIONS.with_borrow_mut(|pending| {
+        // Drop the approvals that were never completed
+        let expired: Vec<Principal> = pending
+            .iter()
+            .filter(|(_, migration)| migration.expires_at_ns < now)
+            .map(|(from, _)| from)
+            .collect();
+        for from in expired {
+            pending.remove(&from);
+        }
+        pending.insert(
+            from,
+            PendingMigration {
+                to,
+                expires_at_ns: now + MIGRATION_APPROVAL_PERIOD_NS,
+            },
+        )
+    });
+    Ok(format!(
+        "Migration approved, {} can now claim the credentials",
+        to.to_text()
+    ))
+}
+
+/// Called by the new principal of a holder to claim the credentials approved by the old principal `from`.
+#[update]
+#[candid_method]
+async fn complete_credential_migration(from: Principal) -> Result<String, CredentialError> {
+    let to = caller();
+    let approved = PENDING_MIGRATIONS.with_borrow_mut(|pending| match pending.get(&from) {
+        Some(migration) if migration.to == to && migration.expires_at_ns >= time() => {
+            pending.remove(&from);
+            true
+        }
+        _ => false,
+    });
+    if !approved {
+        return Err(CredentialError::UnauthorizedSubject(format!(
+            "Unauthorized: No pending migration from {} to the caller.",
+            from.to_text()
+        )));
+    }
+    migrate(from, to, to, None)
+}
+
+/// Retrieves the audit log of all migrations. Can only be called by the admin.
+#[query]
+#[candid_method(query)]
+fn get_migration_log() -> Result<Vec<MigrationRecord>, CredentialError> {
+    if !is_admin(caller()) {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    Ok(MIGRATION_LOG.with_borrow(|log| log.iter().map(|(_, record)| record).collect()))
+}
+
+/// Moves the credentials, of the given issuer or all, with their issuance history and writes the
+/// audit entry.
+fn migrate(
+    from: Principal,
+    to: Principal,
+    initiated_by: Principal,
+    issuer: Option<Principal>,
+) -> Result<String, CredentialError> {
+    if from == to {
+        return Err(CredentialError::UnauthorizedSubject(
+            "Unauthorized: Credentials cannot be migrated to the same principal.".to_string(),
+        ));
+    }
+    let credential_ids = move_credentials(&from, to, issuer)?;
+    // Only the events and VCs of the moved credentials follow them if not all were moved
+    let moved_ids = issuer.map(|_| credential_ids.as_slice());
+    move_issuance_history(&from, to, moved_ids);
+    move_status_entries(&from, to, moved_ids);
+    let credential_count = credential_ids.len();
+
+    MIGRATION_LOG.with_borrow_mut(|log| {
+        let next_id = log.last_key_value().map_or(0, |(id, _)| id + 1);
+        log.insert(
+            next_id,
+            MigrationRecord {
+                timestamp_ns: time(),
+                from,
+                to,
+                initiated_by,
+                credential_count: credential_count as u64,
+            },
+        );
+    });
+    Ok(format!(
+        "Migrated {} credentials from {} to {}",
+        credential_count,
+        from.to_text(),
+        to.to_text()
+    ))
+}
@@ -0,0 +1,326 @@
+//! The `prepared_context` handed out by `prepare_credential` and passed back to `get_credential`.
+//!
+//! The context is a versioned envelope around the JWT of the VC that also records what the VC was
+//! prepared for: a hash of the credential spec, the alias tuple and an expiry, as well as the id of
+//! the seed the VC was signed with and the disclosures of an SD-JWT. The envelope is
+//! authenticated with an HMAC keyed by a secret of the canister, so `get_credential` can reject
+//! contexts that were tampered with or that are used for a different request.
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use vc_util::issuer_api::{ArgumentValue, CredentialSpec, IssueCredentialError};
+use vc_util::AliasTuple;
+
+use crate::config::CONTEXT_KEY;
+
+const PREPARED_CONTEXT_VERSION: u8 = 2;
+
+const HMAC_BLOCK_SIZE: usize = 64;
+
+/// What a VC was prepared for, authenticated by the envelope.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct PreparedContext {
+    pub(crate) credential_jwt: String,
+    /// Disclosures to return with the JWT if it is the issuer JWT of an SD-JWT
+    pub(crate) disclosures: Vec<String>,
+    /// Id of the signing seed the VC was signed with
+    pub(crate) signing_key_id: u32,
+    spec_hash: ByteBuf,
+    id_alias: Principal,
+    id_dapp: Principal,
+    expires_at_ns: u64,
+}
+
+/// The bytes handed out as `prepared_context`
+#[derive(CandidType, Deserialize)]
+struct PreparedContextEnvelope {
+    version: u8,
+    /// Candid encoded `PreparedContext`
+    payload: ByteBuf,
+    /// HMAC of the version and the payload
+    mac: ByteBuf,
+}
+
+/// Reasons for rejecting a prepared context.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PreparedContextError {
+    Malformed,
+    UnsupportedVersion(u8),
+    InvalidMac,
+    Expired,
+    SpecMismatch,
+    AliasMismatch,
+}
+
+impl From<PreparedContextError> for IssueCredentialError {
+    fn from(err: PreparedContextError) -> Self {
+        match err {
+            PreparedContextError::Malformed => {
+                IssueCredentialError::Internal("Invalid prepared_context".to_string())
+            }
+            PreparedContextError::UnsupportedVersion(version) => IssueCredentialError::Internal(
+                format!("Unsupported prepared_context version {}", version),
+            ),
+            PreparedContextError::InvalidMac => IssueCredentialError::Internal(
+                "The prepared_context could not be authenticated".to_string(),
+            ),
+            PreparedContextError::Expired => IssueCredentialError::SignatureNotFound(
+                "The prepared_context has expired".to_string(),
+            ),
+            PreparedContextError::SpecMismatch => IssueCredentialError::UnsupportedCredentialSpec(
+                "The credential spec does not match the prepared_context".to_string(),
+            ),
+            PreparedContextError::AliasMismatch => IssueCredentialError::InvalidIdAlias(
+                "The id alias does not match the prepared_context".to_string(),
+            ),
+        }
+    }
+}
+
+impl PreparedContext {
+    pub(crate) fn new(
+        credential_jwt: String,
+        disclosures: Vec<String>,
+        signing_key_id: u32,
+        spec: &CredentialSpec,
+        alias_tuple: &AliasTuple,
+        expires_at_ns: u64,
+    ) -> Self {
+        PreparedContext {
+            credential_jwt,
+            disclosures,
+            signing_key_id,
+            spec_hash: ByteBuf::from(spec_hash(spec)),
+            id_alias: alias_tuple.id_alias,
+            id_dapp: alias_tuple.id_dapp,
+            expires_at_ns,
+        }
+    }
+
+    /// Checks that the context was prepared for the given request and has not expired.
+    pub(crate) fn verify(
+        &self,
+        spec: &CredentialSpec,
+        alias_tuple: &AliasTuple,
+        now_ns: u64,
+    ) -> Result<(), PreparedContextError> {
+        if self.expires_at_ns < now_ns {
+            return Err(PreparedContextError::Expired);
+        }
+        if self.id_alias != alias_tuple.id_alias || self.id_dapp != alias_tuple.id_dapp {
+            return Err(PreparedContextError::AliasMismatch);
+        }
+        if self.spec_hash.as_slice() != spec_hash(spec).as_slice() {
+            return Err(PreparedContextError::SpecMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Wraps the context into an authenticated envelope.
+pub(crate) fn encode_prepared_context(key: &[u8], context: &PreparedContext) -> Vec<u8> {
+    let payload = Encode!(context).expect("Failed to encode PreparedContext");
+    let mac = hmac_sha256(key, &mac_input(PREPARED_CONTEXT_VERSION, &payload));
+    Encode!(&PreparedContextEnvelope {
+        version: PREPARED_CONTEXT_VERSION,
+        payload: ByteBuf::from(payload),
+        mac: ByteBuf::from(mac.to_vec()),
+    })
+    .expect("Failed to encode PreparedContextEnvelope")
+}
+
+/// Authenticates the envelope and returns the context inside.
+pub(crate) fn decode_prepared_context(
+    key: &[u8],
+    bytes: &[u8],
+) -> Result<PreparedContext, PreparedContextError> {
+    let envelope = Decode!(bytes, PreparedContextEnvelope)
+        .map_err(|_| PreparedContextError::Malformed)?;
+    if envelope.version != PREPARED_CONTEXT_VERSION {
+        return Err(PreparedContextError::UnsupportedVersion(envelope.version));
+    }
+    let mac = hmac_sha256(key, &mac_input(envelope.version, &envelope.payload));
+    if key.is_empty() || !constant_time_eq(&mac, &envelope.mac) {
+        return Err(PreparedContextError::InvalidMac);
+    }
+    Decode!(&envelope.payload, PreparedContext).map_err(|_| PreparedContextError::Malformed)
+}
+
+/// Get the key used to authenticate prepared contexts, empty if it hasn't been generated yet.
+pub(crate) fn context_key() -> Vec<u8> {
+    CONTEXT_KEY.with_borrow(|key| key.get().clone())
+}
+
+/// Get the key used to authenticate prepared contexts, generating it on first use.
+pub(crate) async fn ensure_context_key() -> Result<Vec<u8>, IssueCredentialError> {
+    let key = context_key();
+    if !key.is_empty() {
+        return Ok(key);
+    }
+    let (random,) = raw_rand().await.map_err(|(_, msg)| {
+        IssueCredentialError::Internal(format!(
+            "Failed to generate the prepared_context key: {}",
+            msg
+        ))
+    })?;
+    // Another call may have stored a key while waiting for the randomness
+    Ok(CONTEXT_KEY.with_borrow_mut(|key| {
+        if key.get().is_empty() {
+            key.set(random).expect("failed to store the prepared_context key");
+        }
+        key.get().clone()
+    }))
+}
+
+/// Hash of the credential spec that doesn't depend on the order of its arguments.
+fn spec_hash(spec: &CredentialSpec) -> [u8; 32] {
+    let mut arguments: Vec<(String, ArgumentValue)> = spec
+        .arguments
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    arguments.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let bytes =
+        Encode!(&spec.credential_type, &arguments).expect("Failed to encode CredentialSpec");
+    Sha256::digest(bytes).into()
+}
+
+fn mac_input(version: u8, payload: &[u8]) -> Vec<u8> {
+    let mut input = vec![version];
+    input.extend_from_slice(payload);
+    input
+}
+
+/// HMAC-SHA256 as specified in RFC 2104.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK_SIZE];
+    if key.len() > HMAC_BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const KEY: &[u8] = &[7; 32];
+
+    fn adult_spec(min_age: Option<i32>) -> CredentialSpec {
+        CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: min_age
+                .map(|age| HashMap::from([("minAge".to_string(), ArgumentValue::Int(age))])),
+        }
+    }
+
+    fn alias_tuple() -> AliasTuple {
+        AliasTuple {
+            id_alias: Principal::from_slice(&[1]),
+            id_dapp: Principal::from_slice(&[2]),
+        }
+    }
+
+    /// Test the HMAC implementation against RFC 4231 test case 2
+    #[test]
+    fn test_hmac_sha256() {
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    /// Test that a context can only be used for the request it was prepared for
+    #[test]
+    fn test_verify_prepared_context() {
+        let context = PreparedContext::new(
+            "jwt".to_string(),
+            vec![],
+            0,
+            &adult_spec(Some(18)),
+            &alias_tuple(),
+            10,
+        );
+        let bytes = encode_prepared_context(KEY, &context);
+        let decoded = decode_prepared_context(KEY, &bytes).unwrap();
+        assert_eq!(decoded, context);
+        assert_eq!(decoded.verify(&adult_spec(Some(18)), &alias_tuple(), 10), Ok(()));
+
+        assert_eq!(
+            decoded.verify(&adult_spec(Some(18)), &alias_tuple(), 11),
+            Err(PreparedContextError::Expired)
+        );
+        assert_eq!(
+            decoded.verify(&adult_spec(None), &alias_tuple(), 10),
+            Err(PreparedContextError::SpecMismatch)
+        );
+        let other_alias = AliasTuple {
+            id_alias: Principal::from_slice(&[3]),
+            ..alias_tuple()
+        };
+        assert_eq!(
+            decoded.verify(&adult_spec(Some(18)), &other_alias, 10),
+            Err(PreparedContextError::AliasMismatch)
+        );
+    }
+
+    /// Test that tampered contexts and contexts of another key are rejected
+    #[test]
+    fn test_reject_tampered_prepared_context() {
+        let context = PreparedContext::new(
+            "jwt".to_string(),
+            vec![],
+            0,
+            &adult_spec(None),
+            &alias_tuple(),
+            10,
+        );
+        let bytes = encode_prepared_context(KEY, &context);
+        assert_eq!(
+            decode_prepared_context(&[8; 32], &bytes),
+            Err(PreparedContextError::InvalidMac)
+        );
+        assert_eq!(
+            decode_prepared_context(&[], &bytes),
+            Err(PreparedContextError::InvalidMac)
+        );
+
+        let forged = PreparedContext {
+            credential_jwt: "forged".to_string(),
+            ..context
+        };
+        let mut envelope = Decode!(&bytes, PreparedContextEnvelope).unwrap();
+        envelope.payload = ByteBuf::from(Encode!(&forged).unwrap());
+        let tampered = Encode!(&envelope).unwrap();
+        assert_eq!(
+            decode_prepared_context(KEY, &tampered),
+            Err(PreparedContextError::InvalidMac)
+        );
+
+        envelope.version = 1;
+        let unsupported = Encode!(&envelope).unwrap();
+        assert_eq!(
+            decode_prepared_context(KEY, &unsupported),
+            Err(PreparedContextError::UnsupportedVersion(1))
+        );
+        assert_eq!(
+            decode_prepared_context(KEY, b"jwt"),
+            Err(PreparedContextError::Malformed)
+        );
+    }
+}
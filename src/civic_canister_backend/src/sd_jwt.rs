@@ -0,0 +1,133 @@
+//! Issuance of VCs as SD-JWT (cf. `vc_util::sd_jwt`), requested with the `format` spec argument.
+//!
+//! The issuer JWT follows the SD-JWT VC data model: the credential type is given as `vct` and the
+//! spec arguments are embedded as `<credential type>` claim like in the JWT VCs. Every stored
+//! claim to disclose becomes a salted disclosure, so the holder can choose which of them to
+//! present. The JWT is signed with the canister signature like the JWT VCs, with `vc+sd-jwt` as
+//! `typ` of the JWS header. The disclosures are only handed back with the signed JWT by
+//! `get_sd_jwt_credential`, `get_credential` returns the signed JWT alone.
+use ic_cdk::api::management_canister::main::raw_rand;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use vc_util::issuer_api::{CredentialSpec, IssueCredentialError};
+use vc_util::sd_jwt::{add_disclosure_digests, create_disclosure};
+
+use crate::credential::{credential_spec_args_to_json, Claim};
+
+// Each salt is 128 bits, as recommended by the SD-JWT specification.
+const SALT_LENGTH: usize = 16;
+
+/// Parameters of an SD-JWT to build.
+pub(crate) struct SdJwtParams<'a> {
+    pub(crate) spec: &'a CredentialSpec,
+    /// Unique id of the VC (`jti`)
+    pub(crate) id: String,
+    pub(crate) subject_id: String,
+    pub(crate) issuer: String,
+    pub(crate) claims: &'a [Claim],
+    /// Names of the stored claims to put into disclosures
+    pub(crate) disclosed: &'a [String],
+    pub(crate) issued_at_s: u32,
+    pub(crate) expiration_timestamp_s: u32,
+    /// `credentialStatus` entries, which are always disclosed
+    pub(crate) status: Vec<Value>,
+}
+
+/// Get a random seed the salts of the disclosures of a VC are derived from.
+pub(crate) async fn random_salt_seed() -> Result<Vec<u8>, IssueCredentialError> {
+    raw_rand().await.map(|(random,)| random).map_err(|(_, msg)| {
+        IssueCredentialError::Internal(format!("Failed to generate the SD-JWT salts: {}", msg))
+    })
+}
+
+/// Builds the claims of the issuer JWT and the disclosures of the stored claims to disclose.
+/// Stored claims that the credential doesn't have are skipped.
+pub(crate) fn build_sd_jwt(params: SdJwtParams, salt_seed: &[u8]) -> (String, Vec<String>) {
+    let mut disclosures = Vec::new();
+    for name in params.disclosed {
+        if let Some(value) = params
+            .claims
+            .iter()
+            .find_map(|claim| claim.claims.get(name))
+        {
+            let value: Value = value.clone().into();
+            disclosures.push(create_disclosure(&salt(salt_seed, name), name, &value));
+        }
+    }
+    let mut claims = Map::new();
+    claims.insert("iss".to_string(), json!(params.issuer));
+    claims.insert("sub".to_string(), json!(params.subject_id));
+    claims.insert("jti".to_string(), json!(params.id));
+    claims.insert("iat".to_string(), json!(params.issued_at_s));
+    claims.insert("nbf".to_string(), json!(params.issued_at_s));
+    claims.insert("exp".to_string(), json!(params.expiration_timestamp_s));
+    claims.insert("vct".to_string(), json!(params.spec.credential_type));
+    claims.insert(
+        params.spec.credential_type.clone(),
+        credential_spec_args_to_json(params.spec),
+    );
+    claims.insert("credentialStatus".to_string(), Value::Array(params.status));
+    add_disclosure_digests(&mut claims, &disclosures);
+    (Value::Object(claims).to_string(), disclosures)
+}
+
+/// Derives the salt of a claim from the random seed of the VC.
+fn salt(salt_seed: &[u8], name: &str) -> [u8; SALT_LENGTH] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt_seed);
+    hasher.update(name.as_bytes());
+    let mut salt = [0u8; SALT_LENGTH];
+    salt.copy_from_slice(&hasher.finalize()[..SALT_LENGTH]);
+    salt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::ClaimValue;
+    use std::collections::HashMap;
+    use vc_util::issuer_api::ArgumentValue;
+    use vc_util::sd_jwt::process_disclosures;
+
+    /// Test that only the digests of the disclosed claims end up in the issuer JWT
+    #[test]
+    fn test_build_sd_jwt() {
+        let spec = CredentialSpec {
+            credential_type: "VerifiedResident".to_string(),
+            arguments: Some(HashMap::from([(
+                "format".to_string(),
+                ArgumentValue::String("sd-jwt".to_string()),
+            )])),
+        };
+        let claims = vec![Claim {
+            claims: HashMap::from([
+                ("country".to_string(), ClaimValue::Text("CH".to_string())),
+                ("street".to_string(), ClaimValue::Text("Bahnhofstrasse".to_string())),
+            ]),
+        }];
+        let disclosed = vec!["country".to_string(), "city".to_string()];
+        let (jwt, disclosures) = build_sd_jwt(
+            SdJwtParams {
+                spec: &spec,
+                id: "urn:uuid:00000000-0000-8000-8000-000000000000".to_string(),
+                subject_id: "did:icp:aaaaa-aa".to_string(),
+                issuer: "did:icp:v0:issuer".to_string(),
+                claims: &claims,
+                disclosed: &disclosed,
+                issued_at_s: 1,
+                expiration_timestamp_s: 2,
+                status: vec![],
+            },
+            &[0; 32],
+        );
+        assert_eq!(disclosures.len(), 1);
+        assert!(!jwt.contains("country") && !jwt.contains("street"));
+
+        let jwt_claims: Map<String, Value> = serde_json::from_str(&jwt).unwrap();
+        let disclosures: Vec<&str> = disclosures.iter().map(String::as_str).collect();
+        let processed = process_disclosures(jwt_claims, &disclosures).unwrap();
+        assert_eq!(processed.get("country"), Some(&json!("CH")));
+        assert_eq!(processed.get("vct"), Some(&json!("VerifiedResident")));
+        assert!(!processed.contains_key("street"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Seeds of the canister signatures used to sign the issued VCs.
+//!
+//! The seeds are random and kept in stable memory. As `init` cannot call the management canister,
+//! it schedules a timer that generates the first seed from `raw_rand` right afterwards. Should that
+//! fail, the seed is generated when it is first needed (i.e. by the first `prepare_credential` or
+//! `rotate_signing_key` call). The admin can rotate the seed; previous seeds are kept for a grace
+//! period so that VCs prepared before the rotation can still be retrieved and verifiers have time to
+//! pick up the new public key from `get_signing_public_keys`.
+//!
+//! The message hashes of the signatures are persisted with their expiry, so that only the unexpired
+//! signatures are restored after an upgrade.
+//!
+//! The public keys of the valid seeds are also published in the DID document and the JWKS of the
+//! canister (cf. `did_document`).
+use candid::{candid_method, CandidType, Decode, Deserialize, Encode};
+use canister_sig_util::signature_map::SIGNATURE_EXPIRATION_PERIOD_NS;
+use canister_sig_util::CanisterSigPublicKey;
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_cdk_macros::{query, update};
+use ic_certification::Hash;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+use std::time::Duration;
+use vc_util::issuer_api::IssuerError;
+
+use crate::config::{is_admin, MSG_HASHES, SIGNATURES, SIGNING_KEYS};
+use crate::did_document::update_did_document;
+
+// How long a previous seed can still be used after a rotation.
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const SIGNING_KEY_GRACE_PERIOD_NS: u64 = 7 * DAY_NS;
+
+// At most as many expired message hashes are pruned per signature as by the SignatureMap.
+const MAX_MSG_HASHES_TO_PRUNE: usize = 50;
+
+/// A seed of the canister signatures
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SigningSeed {
+    pub(crate) id: u32,
+    pub(crate) seed: ByteBuf,
+    created_at_ns: u64,
+    retired_at_ns: Option<u64>,
+}
+
+impl SigningSeed {
+    pub(crate) fn public_key(&self) -> CanisterSigPublicKey {
+        CanisterSigPublicKey::new(ic_cdk::id(), self.seed.to_vec())
+    }
+
+    fn is_valid(&self, now_ns: u64) -> bool {
+        match self.retired_at_ns {
+            Some(retired_at) => retired_at + SIGNING_KEY_GRACE_PERIOD_NS > now_ns,
+            None => true,
+        }
+    }
+}
+
+/// The current seed (last) and the previous seeds that are still in their grace period
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SigningKeys(Vec<SigningSeed>);
+
+impl Storable for SigningKeys {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(&self.0).expect("Failed to encode SigningKeys"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        SigningKeys(Decode!(&bytes, Vec<SigningSeed>).expect("Failed to decode SigningKeys"))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Public key of a signing seed, as used in the header of the issued VCs
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SigningPublicKey {
+    pub id: u32,
+    /// DER encoding of the `CanisterSigPublicKey`
+    pub public_key_der: ByteBuf,
+    pub created_at_ns: u64,
+    /// Set for previous keys, which can no longer be used after `valid_until_ns`
+    pub retired_at_ns: Option<u64>,
+    pub valid_until_ns: Option<u64>,
+}
+
+/// Get the current signing seed, if one has been generated.
+pub(crate) fn current_signing_seed() -> Option<SigningSeed> {
+    SIGNING_KEYS.with_borrow(|keys| keys.get().0.last().cloned())
+}
+
+/// Get the seed with the given id if it is current or still in its grace period.
+pub(crate) fn signing_seed(id: u32, now_ns: u64) -> Option<SigningSeed> {
+    SIGNING_KEYS.with_borrow(|keys| {
+        keys.get()
+            .0
+            .iter()
+            .find(|seed| seed.id == id && seed.is_valid(now_ns))
+            .cloned()
+    })
+}
+
+/// Get the current seed and the previous seeds that are still in their grace period.
+pub(crate) fn valid_signing_seeds(now_ns: u64) -> Vec<SigningSeed> {
+    SIGNING_KEYS.with_borrow(|keys| {
+        keys.get()
+            .0
+            .iter()
+            .filter(|seed| seed.is_valid(now_ns))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Get the current signing seed, generating the first one if needed.
+pub(crate) async fn ensure_signing_seed() -> Result<SigningSeed, String> {
+    if let Some(seed) = current_signing_seed() {
+        return Ok(seed);
+    }
+    let random = random_seed().await?;
+    // Another call may have stored a seed while waiting for the randomness
+    if let Some(seed) = current_signing_seed() {
+        return Ok(seed);
+    }
+    Ok(add_signing_seed(random))
+}
+
+/// Schedules the generation of the first seed if there is none yet. Called by `init` and thereby
+/// `post_upgrade`.
+pub(crate) fn schedule_signing_seed() {
+    if current_signing_seed().is_none() {
+        ic_cdk_timers::set_timer(Duration::ZERO, || {
+            ic_cdk::spawn(async {
+                // On failure the seed is generated by the first call that needs it
+                let _ = ensure_signing_seed().await;
+            })
+        });
+    }
+}
+
+async fn random_seed() -> Result<Vec<u8>, String> {
+    raw_rand()
+        .await
+        .map(|(random,)| random)
+        .map_err(|(_, msg)| format!("Failed to generate a signing seed: {}", msg))
+}
+
+/// Retires the current seed, drops the seeds past their grace period and makes the new seed current.
+/// The DID document and the JWKS are certified again with the new key.
+fn add_signing_seed(seed: Vec<u8>) -> SigningSeed {
+    let now = time();
+    let new_seed = SIGNING_KEYS.with_borrow_mut(|keys| {
+        let mut seeds = keys.get().0.clone();
+        let id = seeds.last().map_or(0, |current| current.id + 1);
+        if let Some(current) = seeds.last_mut() {
+            current.retired_at_ns = Some(now);
+        }
+        seeds.retain(|seed| seed.is_valid(now));
+        let new_seed = SigningSeed {
+            id,
+            seed: ByteBuf::from(seed),
+            created_at_ns: now,
+            retired_at_ns: None,
+        };
+        seeds.push(new_seed.clone());
+        keys.set(SigningKeys(seeds)).expect("failed to store the signing keys");
+        new_seed
+    });
+    update_did_document();
+    new_seed
+}
+
+/// Replaces the signing seed by a new random one. Can only be called by the admin.
+/// VCs prepared with the previous seed can be retrieved during its grace period.
+#[update]
+#[candid_method]
+async fn rotate_signing_key() -> Result<SigningPublicKey, IssuerError> {
+    if !is_admin(caller()) {
+        return Err(IssuerError::UnauthorizedSubject(
+            "Caller is not authorized as admin.".to_string(),
+        ));
+    }
+    let random = random_seed().await.map_err(IssuerError::Internal)?;
+    Ok(public_key_info(&add_signing_seed(random)))
+}
+
+/// Retrieves the public keys of the current and previous signing seeds, so verifiers can pin them.
+#[query]
+#[candid_method(query)]
+fn get_signing_public_keys() -> Vec<SigningPublicKey> {
+    valid_signing_seeds(time()).iter().map(public_key_info).collect()
+}
+
+fn public_key_info(seed: &SigningSeed) -> SigningPublicKey {
+    SigningPublicKey {
+        id: seed.id,
+        public_key_der: ByteBuf::from(seed.public_key().to_der()),
+        created_at_ns: seed.created_at_ns,
+        retired_at_ns: seed.retired_at_ns,
+        valid_until_ns: seed
+            .retired_at_ns
+            .map(|retired_at| retired_at + SIGNING_KEY_GRACE_PERIOD_NS),
+    }
+}
+
+/// Adds the signature of the message hash with the given seed and persists the hash with its expiry.
+/// The signature map prunes up to 50 of its expired signatures itself when a signature is added.
+/// The persisted hashes are pruned separately here, also up to 50 per added signature, and all
+/// expired ones are dropped by `restore_signatures` after an upgrade. Otherwise expired entries
+/// stay in both until the next signature is added.
+/// The caller must update the certified data afterwards.
+pub(crate) fn add_signature(seed: &SigningSeed, msg_hash: Hash) {
+    let now = time();
+    SIGNATURES.with_borrow_mut(|sigs| sigs.add_signature(&seed.seed, msg_hash));
+    MSG_HASHES.with_borrow_mut(|hashes| {
+        for _ in 0..MAX_MSG_HASHES_TO_PRUNE {
+            match hashes.first_key_value() {
+                Some((key, _)) if key.0 <= now => {
+                    hashes.remove(&key);
+                }
+                _ => break,
+            }
+        }
+        hashes.insert((now + SIGNATURE_EXPIRATION_PERIOD_NS, msg_hash), seed.id);
+    });
+}
+
+/// Restores the unexpired signatures from the persisted message hashes, e.g. after an upgrade.
+/// The caller must update the certified data afterwards.
+pub(crate) fn restore_signatures() {
+    let now = time();
+    MSG_HASHES.with_borrow_mut(|hashes| {
+        // Drop all expired hashes, not just one batch
+        let expired: Vec<(u64, Hash)> = hashes
+            .range(..(now + 1, [0; 32]))
+            .map(|(key, _)| key)
+            .collect();
+        for key in expired {
+            hashes.remove(&key);
+        }
+        SIGNATURES.with_borrow_mut(|sigs| {
+            for ((expires_at, msg_hash), seed_id) in hashes.iter() {
+                if let Some(seed) = signing_seed(seed_id, now) {
+                    sigs.restore_signature(&seed.seed, msg_hash, expires_at);
+                }
+            }
+        });
+    });
+}
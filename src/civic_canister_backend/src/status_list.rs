@@ -0,0 +1,287 @@
+//! Status lists of the issued VCs (cf. `vc_util::status_list`).
+//!
+//! Each issuer has a revocation and a suspension list. Every VC gets its own index in the lists
+//! of the issuer of the stored credential it was built from; the index is not shared with other
+//! VCs of the same credential, so that the relying parties cannot link VCs through their status.
+//! An index is handed out again once the VC it was handed out for has expired.
+//!
+//! Removing a stored credential revokes the VCs issued from it and suspending the credential
+//! suspends them. Whenever a list changes, its status list credential is certified again and
+//! served at `/status/<issuer>/<purpose>` below the derivation origin. The status list
+//! credentials have the same issuer as the VCs.
+use asset_util::{Asset, CertifiedAssets, ContentEncoding, ContentType};
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use identity_core::common::Timestamp;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+use vc_util::issuer_api::IssueCredentialError;
+use vc_util::status_list::{
+    build_status_list_credential, set_status_bit, status_bit, StatusListEntry,
+    MIN_STATUS_LIST_LENGTH, REVOCATION_PURPOSE, SUSPENSION_PURPOSE,
+};
+
+use crate::config::{
+    configured_derivation_origin, static_headers, vc_issuer, ASSETS, STATUS_ENTRIES, STATUS_LISTS,
+};
+use crate::credential::update_root_hash;
+
+const STATUS_LIST_LENGTH: u32 = MIN_STATUS_LIST_LENGTH as u32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StatusPurpose {
+    Revocation,
+    Suspension,
+}
+
+const PURPOSES: [StatusPurpose; 2] = [StatusPurpose::Revocation, StatusPurpose::Suspension];
+
+impl StatusPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StatusPurpose::Revocation => REVOCATION_PURPOSE,
+            StatusPurpose::Suspension => SUSPENSION_PURPOSE,
+        }
+    }
+}
+
+/// The status lists of an issuer
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct StatusList {
+    /// Index to hand out to the next VC
+    next_index: u32,
+    revocation: ByteBuf,
+    suspension: ByteBuf,
+    updated_at_ns: u64,
+}
+
+impl StatusList {
+    fn new(now_ns: u64) -> Self {
+        let empty = ByteBuf::from(vec![0; STATUS_LIST_LENGTH as usize / 8]);
+        StatusList {
+            next_index: 0,
+            revocation: empty.clone(),
+            suspension: empty,
+            updated_at_ns: now_ns,
+        }
+    }
+
+    fn bits(&self, purpose: StatusPurpose) -> &[u8] {
+        match purpose {
+            StatusPurpose::Revocation => &self.revocation,
+            StatusPurpose::Suspension => &self.suspension,
+        }
+    }
+
+    fn bits_mut(&mut self, purpose: StatusPurpose) -> &mut [u8] {
+        match purpose {
+            StatusPurpose::Revocation => &mut self.revocation,
+            StatusPurpose::Suspension => &mut self.suspension,
+        }
+    }
+}
+
+impl Storable for StatusList {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode StatusList"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, StatusList).expect("Failed to decode StatusList")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The VC an index of the status lists was handed out for
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct StatusEntry {
+    holder: Principal,
+    /// Id of the stored credential the VC was built from
+    credential_id: String,
+    expires_at_ns: u64,
+}
+
+impl Storable for StatusEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode StatusEntry"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, StatusEntry).expect("Failed to decode StatusEntry")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Hands out the next index of the issuer's status lists to a VC built from the holder's
+/// credential and returns the `credentialStatus` entries of the VC.
+pub(crate) fn allocate_status_entries(
+    issuer: Principal,
+    holder: Principal,
+    credential_id: &str,
+    expires_at_ns: u64,
+) -> Result<Vec<StatusListEntry>, IssueCredentialError> {
+    let now = time();
+    let (index, changed) = STATUS_LISTS.with_borrow_mut(|lists| {
+        let (mut list, mut changed) = match lists.get(&issuer) {
+            Some(list) => (list, false),
+            None => (StatusList::new(now), true),
+        };
+        let index = list.next_index;
+        let previous = STATUS_ENTRIES.with_borrow(|entries| entries.get(&(issuer, index)));
+        if previous.is_some_and(|entry| entry.expires_at_ns > now) {
+            return Err(IssueCredentialError::Internal(format!(
+                "The status list of issuer {} is full",
+                issuer.to_text()
+            )));
+        }
+        // Reset the status of the expired VC the index was handed out for before
+        for purpose in PURPOSES {
+            if status_bit(list.bits(purpose), index as usize) == Some(true) {
+                set_status_bit(list.bits_mut(purpose), index as usize, false);
+                list.updated_at_ns = now;
+                changed = true;
+            }
+        }
+        list.next_index = (index + 1) % STATUS_LIST_LENGTH;
+        lists.insert(issuer, list);
+        Ok((index, changed))
+    })?;
+    STATUS_ENTRIES.with_borrow_mut(|entries| {
+        entries.insert(
+            (issuer, index),
+            StatusEntry {
+                holder,
+                credential_id: credential_id.to_string(),
+                expires_at_ns,
+            },
+        );
+    });
+    if changed {
+        certify_status_lists(&issuer);
+    }
+    Ok(PURPOSES
+        .iter()
+        .map(|purpose| StatusListEntry {
+            status_purpose: purpose.as_str().to_string(),
+            status_list_index: index as usize,
+            status_list_credential: status_list_url(&issuer, *purpose),
+        })
+        .collect())
+}
+
+/// Sets the status of the unexpired VCs that were built from the holder's credential.
+pub(crate) fn set_credential_status(
+    issuer: Principal,
+    holder: Principal,
+    credential_id: &str,
+    purpose: StatusPurpose,
+    value: bool,
+) {
+    let now = time();
+    let indexes: Vec<u32> = STATUS_ENTRIES.with_borrow(|entries| {
+        entries
+            .range((issuer, 0)..=(issuer, u32::MAX))
+            .filter(|(_, entry)| {
+                entry.holder == holder
+                    && entry.credential_id == credential_id
+                    && entry.expires_at_ns > now
+            })
+            .map(|((_, index), _)| index)
+            .collect()
+    });
+    if indexes.is_empty() {
+        return;
+    }
+    STATUS_LISTS.with_borrow_mut(|lists| {
+        if let Some(mut list) = lists.get(&issuer) {
+            for index in indexes {
+                set_status_bit(list.bits_mut(purpose), index as usize, value);
+            }
+            list.updated_at_ns = now;
+            lists.insert(issuer, list);
+        }
+    });
+    certify_status_lists(&issuer);
+}
+
+/// Lets the VCs issued to `from` follow its credentials to `to`, so that they can still be
+/// revoked or suspended. If credential ids are given, only the VCs of these credentials follow.
+pub(crate) fn move_status_entries(
+    from: &Principal,
+    to: Principal,
+    credential_ids: Option<&[String]>,
+) {
+    STATUS_ENTRIES.with_borrow_mut(|entries| {
+        let moved: Vec<((Principal, u32), StatusEntry)> = entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry.holder == *from
+                    && credential_ids.map_or(true, |ids| ids.contains(&entry.credential_id))
+            })
+            .collect();
+        for (key, entry) in moved {
+            entries.insert(key, StatusEntry { holder: to, ..entry });
+        }
+    });
+}
+
+/// Certifies the status list credentials of all issuers, e.g. after the assets were rebuilt.
+/// The caller must update the certified data afterwards.
+pub(crate) fn certify_all_status_lists(assets: &mut CertifiedAssets) {
+    STATUS_LISTS.with_borrow(|lists| {
+        for (issuer, list) in lists.iter() {
+            certify_status_list_credentials(assets, &issuer, &list);
+        }
+    });
+}
+
+fn certify_status_lists(issuer: &Principal) {
+    if let Some(list) = STATUS_LISTS.with_borrow(|lists| lists.get(issuer)) {
+        ASSETS.with_borrow_mut(|assets| certify_status_list_credentials(assets, issuer, &list));
+        update_root_hash();
+    }
+}
+
+fn certify_status_list_credentials(
+    assets: &mut CertifiedAssets,
+    issuer: &Principal,
+    list: &StatusList,
+) {
+    let valid_from = Timestamp::from_unix((list.updated_at_ns / 1_000_000_000) as i64)
+        .expect("internal: failed computing status list timestamp")
+        .to_rfc3339();
+    for purpose in PURPOSES {
+        let credential = build_status_list_credential(
+            &status_list_url(issuer, purpose),
+            &vc_issuer(),
+            purpose.as_str(),
+            &valid_from,
+            list.bits(purpose),
+        );
+        assets.certify_asset(
+            Asset {
+                url_path: status_list_path(issuer, purpose),
+                content: credential.to_string().into_bytes(),
+                encoding: ContentEncoding::Identity,
+                content_type: ContentType::JSON,
+            },
+            &static_headers(),
+        );
+    }
+}
+
+fn status_list_path(issuer: &Principal, purpose: StatusPurpose) -> String {
+    format!("/status/{}/{}", issuer.to_text(), purpose.as_str())
+}
+
+fn status_list_url(issuer: &Principal, purpose: StatusPurpose) -> String {
+    format!(
+        "{}{}",
+        configured_derivation_origin(),
+        status_list_path(issuer, purpose)
+    )
+}
@@ -4,6 +4,7 @@ extern crate civic_canister_backend;
 use assert_matches::assert_matches;
 use candid::Principal;
 use canister_sig_util::{extract_raw_root_pk_from_der, CanisterSigPublicKey};
+use canister_tests::api::http_request;
 use canister_tests::api::internet_identity::vc_mvp as ii_api;
 use canister_tests::flows;
 use canister_tests::framework::{
@@ -13,24 +14,45 @@ use civic_canister_backend::config::IssuerInit;
 use civic_canister_backend::credential::{
     Claim, ClaimValue, Credential, CredentialError, FullCredential,
 };
+use civic_canister_backend::credential_type::{
+    ClaimDisplay, CredentialDisplay, CredentialTypeDefinition, LifetimePolicy, SelectionPolicy,
+};
+use civic_canister_backend::credential_request::{CredentialRequest, CredentialRequestStatus};
+use civic_canister_backend::history::IssuanceEvent;
+use civic_canister_backend::migration::MigrationRecord;
+use civic_canister_backend::signing_key::SigningPublicKey;
 use ic_cdk::api::management_canister::provisional::CanisterId;
 use ic_test_state_machine_client::{call_candid, call_candid_as};
 use ic_test_state_machine_client::{query_candid_as, CallError, StateMachine};
 use internet_identity_interface::internet_identity::types::vc_mvp::{
     GetIdAliasRequest, PrepareIdAliasRequest,
 };
+use internet_identity_interface::http_gateway::HttpRequest;
 use internet_identity_interface::internet_identity::types::FrontendHostname;
 use lazy_static::lazy_static;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::UNIX_EPOCH;
 use vc_util::issuer_api::{
     ArgumentValue, CredentialSpec, DerivationOriginData, DerivationOriginError,
-    DerivationOriginRequest, GetCredentialRequest, Icrc21ConsentInfo, Icrc21ConsentPreferences,
-    Icrc21Error, Icrc21VcConsentMessageRequest, IssueCredentialError, IssuedCredentialData,
-    PrepareCredentialRequest, PreparedCredentialData, SignedIdAlias as SignedIssuerIdAlias,
+    DerivationOriginRequest, GetCredentialRequest, Icrc10SupportedStandard, Icrc21ConsentInfo,
+    Icrc21ConsentMessage, Icrc21ConsentMessageInfo, Icrc21ConsentMessageMetadata,
+    Icrc21ConsentMessageRequest, Icrc21ConsentMessageSpec, Icrc21ConsentPreferences,
+    Icrc21DeviceSpec, Icrc21Error, Icrc21Value, Icrc21VcConsentMessageRequest,
+    Icrc21VcStructuredConsentMessageRequest, IssueCredentialError, IssuedCredentialData,
+    IssuerError, ManifestRequest, ManifestResponse, PrepareCredentialRequest,
+    PreparedCredentialData, SignedIdAlias as SignedIssuerIdAlias,
+};
+use vc_util::sd_jwt::{select_disclosures, split_sd_jwt, verify_sd_jwt_with_canister_id};
+use vc_util::status_list::{check_credential_status, status_list_entries, StatusListResponse};
+use vc_util::test_utils::create_verifiable_presentation_jwt_for_test;
+use vc_util::{
+    canister_sig_pk_jwk, get_verified_id_alias_from_jws, validate_claims_match_spec,
+    validate_ii_presentation_and_claims, verify_credential_jws_with_canister_id, VcFlowSigners,
+    II_ISSUER_URL,
 };
-use vc_util::{get_verified_id_alias_from_jws, verify_credential_jws_with_canister_id};
 
 const DUMMY_ROOT_KEY: &str ="308182301d060d2b0601040182dc7c0503010201060c2b0601040182dc7c05030201036100adf65638a53056b2222c91bb2457b0274bca95198a5acbdadfe7fd72178f069bdea8d99e9479d8087a2686fc81bf3c4b11fe275570d481f1698f79d468afe0e57acc1e298f8b69798da7a891bbec197093ec5f475909923d48bfed6843dbed1f";
 const DUMMY_II_CANISTER_ID: &str = "rwlgt-iiaaa-aaaaa-aaaaa-cai";
@@ -66,6 +88,7 @@ lazy_static! {
         idp_canister_ids: vec![Principal::from_text(DUMMY_II_CANISTER_ID).unwrap()],
         derivation_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
         frontend_hostname: DUMMY_FRONTEND_HOSTNAME.to_string(),
+        vc_issuer: None,
         admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
         authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
     };
@@ -88,6 +111,18 @@ pub fn install_issuer(env: &StateMachine, init: &IssuerInit) -> CanisterId {
     canister_id
 }
 
+/// Lets the timer scheduled by `init` generate the first signing seed.
+fn tick_until_signing_seed(env: &StateMachine, canister_id: CanisterId) {
+    for _ in 0..10 {
+        let keys = api::get_signing_public_keys(env, canister_id).expect("API call failed");
+        if !keys.is_empty() {
+            return;
+        }
+        env.tick();
+    }
+    panic!("no signing seed was generated");
+}
+
 mod api {
     use super::*;
 
@@ -115,6 +150,168 @@ mod api {
         .map(|(x,)| x)
     }
 
+    pub fn icrc21_vc_consent_message(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        consent_message_request: &Icrc21VcStructuredConsentMessageRequest,
+    ) -> Result<Result<Icrc21ConsentMessageInfo, Icrc21Error>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "icrc21_vc_consent_message",
+            (consent_message_request,),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn icrc21_canister_call_consent_message(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        consent_message_request: &Icrc21ConsentMessageRequest,
+    ) -> Result<Result<Icrc21ConsentMessageInfo, Icrc21Error>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "icrc21_canister_call_consent_message",
+            (consent_message_request,),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn set_derivation_origin(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        frontend_hostname: &str,
+        derivation_origin: Option<&str>,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "set_derivation_origin",
+            (frontend_hostname, derivation_origin),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn get_derivation_origins(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<Vec<(String, String)>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "get_derivation_origins",
+            (),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn add_alternative_origin(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        origin: &str,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "add_alternative_origin",
+            (origin,),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn remove_alternative_origin(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        origin: &str,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "remove_alternative_origin",
+            (origin,),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn get_alternative_origins(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<Vec<String>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "get_alternative_origins",
+            (),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn create_asset_batch(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        path: &str,
+    ) -> Result<Result<u64, IssuerError>, CallError> {
+        call_candid_as(env, canister_id, sender, "create_asset_batch", (path,)).map(|(x,)| x)
+    }
+
+    pub fn upload_asset_chunk(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        batch_id: u64,
+        index: u32,
+        content: &[u8],
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "upload_asset_chunk",
+            (batch_id, index, ByteBuf::from(content)),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn commit_asset_batch(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        batch_id: u64,
+        sha256: &[u8],
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "commit_asset_batch",
+            (batch_id, ByteBuf::from(sha256)),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn delete_asset(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        path: &str,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(env, canister_id, sender, "delete_asset", (path,)).map(|(x,)| x)
+    }
+
     pub fn derivation_origin(
         env: &StateMachine,
         canister_id: CanisterId,
@@ -185,6 +382,41 @@ mod api {
         .map(|(x,)| x)
     }
 
+    pub fn set_credential_blocked(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        credential_id: String,
+        blocked: bool,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "set_credential_blocked",
+            (credential_id, blocked),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn set_credential_suspended(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        user: Principal,
+        credential_id: String,
+        suspended: bool,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "set_credential_suspended",
+            (user, credential_id, suspended),
+        )
+        .map(|(x,)| x)
+    }
+
     pub fn get_all_credentials(
         env: &StateMachine,
         canister_id: CanisterId,
@@ -225,6 +457,22 @@ mod api {
         .map(|(x,)| x)
     }
 
+    pub fn get_sd_jwt_credential(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        get_credential_request: &GetCredentialRequest,
+    ) -> Result<Result<IssuedCredentialData, IssueCredentialError>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            sender,
+            "get_sd_jwt_credential",
+            (get_credential_request,),
+        )
+        .map(|(x,)| x)
+    }
+
     pub fn remove_credential(
         env: &StateMachine,
         sender: Principal,
@@ -242,76 +490,301 @@ mod api {
         .map(|(x,)| x)
     }
 
-    pub fn add_issuer(
+    pub fn my_issuance_history(
         env: &StateMachine,
         canister_id: CanisterId,
-        authorized_principal: Principal,
-        new_issuer: Principal,
-    ) -> Result<Result<(), CredentialError>, CallError> {
+        sender: Principal,
+    ) -> Result<Vec<IssuanceEvent>, CallError> {
+        query_candid_as(env, canister_id, sender, "my_issuance_history", ()).map(|(x,)| x)
+    }
+
+    pub fn migrate_credentials(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        from: Principal,
+        to: Principal,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(env, canister_id, sender, "migrate_credentials", (from, to))
+            .map(|(x,)| x)
+    }
+
+    pub fn approve_credential_migration(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        to: Principal,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(env, canister_id, sender, "approve_credential_migration", (to,))
+            .map(|(x,)| x)
+    }
+
+    pub fn complete_credential_migration(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        from: Principal,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(env, canister_id, sender, "complete_credential_migration", (from,))
+            .map(|(x,)| x)
+    }
+
+    pub fn get_migration_log(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+    ) -> Result<Result<Vec<MigrationRecord>, CredentialError>, CallError> {
+        query_candid_as(env, canister_id, sender, "get_migration_log", ()).map(|(x,)| x)
+    }
+
+    pub fn request_credential(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        credential_type: String,
+        evidence_refs: Vec<String>,
+    ) -> Result<Result<u64, CredentialError>, CallError> {
         call_candid_as(
             env,
             canister_id,
-            authorized_principal,
-            "add_issuer",
-            (new_issuer,),
+            sender,
+            "request_credential",
+            (credential_type, evidence_refs),
         )
         .map(|(x,)| x)
     }
 
-    pub fn remove_issuer(
+    pub fn list_pending_credential_requests(
         env: &StateMachine,
         canister_id: CanisterId,
-        authorized_principal: Principal,
-        issuer: Principal,
-    ) -> Result<Result<(), CredentialError>, CallError> {
+        sender: Principal,
+    ) -> Result<Result<Vec<CredentialRequest>, CredentialError>, CallError> {
+        query_candid_as(env, canister_id, sender, "list_pending_credential_requests", ())
+            .map(|(x,)| x)
+    }
+
+    pub fn approve_credential_request(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        request_id: u64,
+        credential: Credential,
+    ) -> Result<Result<String, CredentialError>, CallError> {
         call_candid_as(
             env,
             canister_id,
-            authorized_principal,
-            "remove_issuer",
-            (issuer,),
+            sender,
+            "approve_credential_request",
+            (request_id, credential),
         )
         .map(|(x,)| x)
     }
-}
 
-fn adult_credential_spec() -> CredentialSpec {
-    CredentialSpec {
-        credential_type: "VerifiedAdult".to_string(),
-        arguments: None,
+    pub fn reject_credential_request(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        request_id: u64,
+        reason: String,
+    ) -> Result<Result<String, CredentialError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "reject_credential_request",
+            (request_id, reason),
+        )
+        .map(|(x,)| x)
     }
-}
 
-fn construct_adult_credential() -> Credential {
-    let mut claim_map = HashMap::<String, ClaimValue>::new();
-    claim_map.insert("Is over 18".to_string(), ClaimValue::Boolean(true));
-    Credential {
-        id: "http://example.edu/credentials/3732".to_string(),
-        type_: vec![
-            "VerifiableCredential".to_string(),
-            "VerifiedAdult".to_string(),
-        ],
-        context: vec![
-            "https://www.w3.org/2018/credentials/v1".to_string(),
-            "https://www.w3.org/2018/credentials/examples/v1".to_string(),
-        ],
-        claim: vec![Claim { claims: claim_map }],
+    pub fn my_credential_requests(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+    ) -> Result<Vec<CredentialRequest>, CallError> {
+        query_candid_as(env, canister_id, sender, "my_credential_requests", ()).map(|(x,)| x)
     }
-}
 
-/// Test: Configure canister
-#[test]
-fn should_configure() {
-    let env = env();
-    let issuer_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
-    api::configure(&env, issuer_id, &DUMMY_ISSUER_INIT).expect("API call failed");
-}
+    pub fn set_credential_type(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        definition: &CredentialTypeDefinition,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(env, canister_id, sender, "set_credential_type", (definition,))
+            .map(|(x,)| x)
+    }
 
-/// Test: Add duplicate credentials
-#[test]
-fn should_fail_to_add_duplicate_credentials() {
-    let env = env();
-    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    pub fn set_credential_selection_policy(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        name: &str,
+        policy: &SelectionPolicy,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "set_credential_selection_policy",
+            (name, policy),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn set_consent_template(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+        name: &str,
+        language: &str,
+        template: Option<&str>,
+    ) -> Result<Result<(), IssuerError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            sender,
+            "set_consent_template",
+            (name, language, template),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn rotate_signing_key(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        sender: Principal,
+    ) -> Result<Result<SigningPublicKey, IssuerError>, CallError> {
+        call_candid_as(env, canister_id, sender, "rotate_signing_key", ()).map(|(x,)| x)
+    }
+
+    pub fn get_signing_public_keys(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<Vec<SigningPublicKey>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "get_signing_public_keys",
+            (),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn icrc10_supported_standards(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<Vec<Icrc10SupportedStandard>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "icrc10_supported_standards",
+            (),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn vc_manifest(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<ManifestResponse, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "vc_manifest",
+            (ManifestRequest {},),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn get_credential_types(
+        env: &StateMachine,
+        canister_id: CanisterId,
+    ) -> Result<Vec<CredentialTypeDefinition>, CallError> {
+        query_candid_as(
+            env,
+            canister_id,
+            Principal::anonymous(),
+            "get_credential_types",
+            (),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn add_issuer(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        authorized_principal: Principal,
+        new_issuer: Principal,
+    ) -> Result<Result<(), CredentialError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            authorized_principal,
+            "add_issuer",
+            (new_issuer,),
+        )
+        .map(|(x,)| x)
+    }
+
+    pub fn remove_issuer(
+        env: &StateMachine,
+        canister_id: CanisterId,
+        authorized_principal: Principal,
+        issuer: Principal,
+    ) -> Result<Result<(), CredentialError>, CallError> {
+        call_candid_as(
+            env,
+            canister_id,
+            authorized_principal,
+            "remove_issuer",
+            (issuer,),
+        )
+        .map(|(x,)| x)
+    }
+}
+
+fn adult_credential_spec() -> CredentialSpec {
+    CredentialSpec {
+        credential_type: "VerifiedAdult".to_string(),
+        arguments: None,
+    }
+}
+
+fn construct_adult_credential() -> Credential {
+    let mut claim_map = HashMap::<String, ClaimValue>::new();
+    claim_map.insert("Is over 18".to_string(), ClaimValue::Boolean(true));
+    Credential {
+        id: "http://example.edu/credentials/3732".to_string(),
+        type_: vec![
+            "VerifiableCredential".to_string(),
+            "VerifiedAdult".to_string(),
+        ],
+        context: vec![
+            "https://www.w3.org/2018/credentials/v1".to_string(),
+            "https://www.w3.org/2018/credentials/examples/v1".to_string(),
+        ],
+        claim: vec![Claim { claims: claim_map }],
+        expires_at_ns: None,
+    }
+}
+
+/// Test: Configure canister
+#[test]
+fn should_configure() {
+    let env = env();
+    let issuer_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
+    api::configure(&env, issuer_id, &DUMMY_ISSUER_INIT).expect("API call failed");
+}
+
+/// Test: Add duplicate credentials
+#[test]
+fn should_fail_to_add_duplicate_credentials() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
     let principal = principal_1();
     let credential = construct_adult_credential();
 
@@ -763,6 +1236,7 @@ fn should_return_vc_consent_message_for_adult_vc() {
             preferences: Icrc21ConsentPreferences {
                 language: requested_language.to_string(),
             },
+            signed_id_alias: None,
         };
 
         let response =
@@ -776,6 +1250,161 @@ fn should_return_vc_consent_message_for_adult_vc() {
     }
 }
 
+/// Test: VC consent message for the line display and the fields display of a wallet
+#[test]
+fn should_return_structured_vc_consent_message() {
+    let env = env();
+    let canister_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
+    let request = |device_spec: Icrc21DeviceSpec| Icrc21VcStructuredConsentMessageRequest {
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(HashMap::from([(
+                "minAge".to_string(),
+                ArgumentValue::Int(18),
+            )])),
+        },
+        user_preferences: Icrc21ConsentMessageSpec {
+            metadata: Icrc21ConsentMessageMetadata {
+                language: "en-US".to_string(),
+                utc_offset_minutes: Some(120),
+            },
+            device_spec: Some(device_spec),
+        },
+        signed_id_alias: None,
+    };
+
+    let line_display = Icrc21DeviceSpec::LineDisplay {
+        characters_per_line: 20,
+        lines_per_page: 3,
+    };
+    let response =
+        api::icrc21_vc_consent_message(&env, canister_id, principal_1(), &request(line_display))
+            .expect("API call failed")
+            .expect("Consent message error");
+    assert_eq!(
+        response.metadata,
+        Icrc21ConsentMessageMetadata {
+            language: "en".to_string(),
+            utc_offset_minutes: Some(120),
+        }
+    );
+    let Icrc21ConsentMessage::LineDisplayMessage { pages } = response.consent_message else {
+        panic!("expected a line display message");
+    };
+    assert!(pages.iter().all(|page| page.lines.len() <= 3));
+    let lines: Vec<String> = pages.into_iter().flat_map(|page| page.lines).collect();
+    assert!(lines.iter().all(|line| line.chars().count() <= 20));
+    assert_eq!(lines.first().map(String::as_str), Some("Verified Adult"));
+    assert_eq!(lines.last().map(String::as_str), Some("Minimum age: 18"));
+
+    let response = api::icrc21_vc_consent_message(
+        &env,
+        canister_id,
+        principal_1(),
+        &request(Icrc21DeviceSpec::FieldsDisplay),
+    )
+    .expect("API call failed")
+    .expect("Consent message error");
+    assert_eq!(
+        response.consent_message,
+        Icrc21ConsentMessage::FieldsDisplayMessage {
+            intent: "Verified Adult".to_string(),
+            fields: vec![(
+                "Minimum age".to_string(),
+                Icrc21Value::Text {
+                    content: "18".to_string()
+                }
+            )],
+        }
+    );
+
+    // The markdown for II stays available as generic display message
+    let response = api::icrc21_vc_consent_message(
+        &env,
+        canister_id,
+        principal_1(),
+        &request(Icrc21DeviceSpec::GenericDisplay),
+    )
+    .expect("API call failed")
+    .expect("Consent message error");
+    assert_matches!(
+        response.consent_message,
+        Icrc21ConsentMessage::GenericDisplayMessage(message) if message.starts_with("# Verified Adult")
+    );
+}
+
+/// Test: Consent messages summarize the calls of the canister's methods
+#[test]
+fn should_return_canister_call_consent_message() {
+    let env = env();
+    let canister_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
+    let request =
+        |method: &str, arg: Vec<u8>, device_spec: Icrc21DeviceSpec| Icrc21ConsentMessageRequest {
+            method: method.to_string(),
+            arg: ByteBuf::from(arg),
+            user_preferences: Icrc21ConsentMessageSpec {
+                metadata: Icrc21ConsentMessageMetadata {
+                    language: "de-CH".to_string(),
+                    utc_offset_minutes: None,
+                },
+                device_spec: Some(device_spec),
+            },
+        };
+
+    let holder = principal_1();
+    let credentials = vec![construct_adult_credential(), construct_adult_credential()];
+    let arg = candid::encode_args((holder, credentials)).unwrap();
+    let response = api::icrc21_canister_call_consent_message(
+        &env,
+        canister_id,
+        principal_2(),
+        &request("add_credentials", arg, Icrc21DeviceSpec::FieldsDisplay),
+    )
+    .expect("API call failed")
+    .expect("Consent message error");
+    // The summaries are only available in English
+    assert_eq!(response.metadata.language, "en");
+    let Icrc21ConsentMessage::FieldsDisplayMessage { intent, fields } = response.consent_message
+    else {
+        panic!("expected a fields display message");
+    };
+    assert_eq!(intent, "Add credentials");
+    assert_eq!(
+        fields[0],
+        (
+            "Holder".to_string(),
+            Icrc21Value::Text {
+                content: holder.to_text()
+            }
+        )
+    );
+
+    let arg = candid::encode_args((principal_2(),)).unwrap();
+    let response = api::icrc21_canister_call_consent_message(
+        &env,
+        canister_id,
+        principal_1(),
+        &request("add_issuer", arg.clone(), Icrc21DeviceSpec::GenericDisplay),
+    )
+    .expect("API call failed")
+    .expect("Consent message error");
+    assert_matches!(
+        response.consent_message,
+        Icrc21ConsentMessage::GenericDisplayMessage(message) if message.starts_with("# Add issuer")
+    );
+
+    for method in ["get_admin", "remove_credential"] {
+        let response = api::icrc21_canister_call_consent_message(
+            &env,
+            canister_id,
+            principal_1(),
+            &request(method, arg.clone(), Icrc21DeviceSpec::GenericDisplay),
+        )
+        .expect("API call failed");
+        assert_matches!(response, Err(Icrc21Error::UnsupportedCanisterCall(_)));
+    }
+}
+
 /// Test: Derivation origin
 #[test]
 fn should_return_derivation_origin() {
@@ -798,6 +1427,7 @@ fn should_return_derivation_origin_with_custom_init() {
         idp_canister_ids: vec![Principal::from_text(DUMMY_II_CANISTER_ID).unwrap()],
         derivation_origin: "https://derivation_origin".to_string(),
         frontend_hostname: "https://frontend.host.name".to_string(),
+        vc_issuer: None,
         admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
         authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
     };
@@ -815,71 +1445,268 @@ fn should_return_derivation_origin_with_custom_init() {
     assert_eq!(response.origin, custom_init.derivation_origin);
 }
 
-/// Test: VC consent message failure if not supported
+/// Test: The admin manages the allowlist of frontend hostnames and their derivation origins
 #[test]
-fn should_fail_vc_consent_message_if_not_supported() {
+fn should_return_derivation_origin_of_allowed_frontends() {
     let env = env();
-    let canister_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
-
-    let consent_message_request = Icrc21VcConsentMessageRequest {
-        credential_spec: CredentialSpec {
-            credential_type: "VerifiedResident".to_string(),
-            arguments: None,
-        },
-        preferences: Icrc21ConsentPreferences {
-            language: "en-US".to_string(),
-        },
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let staging = "https://staging.civic.example.com";
+    let staging_origin = "https://staging-origin.civic.example.com";
+    let request = |frontend_hostname: &str| DerivationOriginRequest {
+        frontend_hostname: frontend_hostname.to_string(),
     };
 
-    let response =
-        api::vc_consent_message(&env, canister_id, principal_1(), &consent_message_request)
-            .expect("API call failed");
-    assert_matches!(response, Err(Icrc21Error::UnsupportedCanisterCall(_)));
-}
-
-/// Test: Prepare credential for wrong sender
-#[test]
-fn should_fail_prepare_credential_for_wrong_sender() {
-    let env = env();
-    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
-    let signed_id_alias = DUMMY_SIGNED_ID_ALIAS.clone();
+    let response = api::derivation_origin(&env, issuer_id, principal_1(), &request(staging))
+        .expect("API call failed");
+    assert_matches!(response, Err(DerivationOriginError::UnsupportedOrigin(_)));
 
-    let response = api::prepare_credential(
+    // Only the admin can change the allowlist
+    let response = api::set_derivation_origin(
         &env,
         issuer_id,
-        principal_1(), // not the same as contained in signed_id_alias
-        &PrepareCredentialRequest {
-            credential_spec: adult_credential_spec(),
-            signed_id_alias,
-        },
+        principal_1(),
+        staging,
+        Some(staging_origin),
     )
     .expect("API call failed");
-    assert_matches!(response,
-        Err(IssueCredentialError::InvalidIdAlias(e)) if e.contains("Id alias could not be verified")
-    );
-}
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+    let response = api::set_derivation_origin(&env, issuer_id, admin, "staging", None)
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnsupportedOrigin(_)));
 
-/// Test: Get credential for wrong sender
-#[test]
-fn should_fail_get_credential_for_wrong_sender() {
-    let env = env();
-    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
-    let signed_id_alias = DUMMY_SIGNED_ID_ALIAS.clone();
-    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
-    let _ = api::add_credentials(
+    api::set_derivation_origin(&env, issuer_id, admin, staging, Some(staging_origin))
+        .expect("API call failed")
+        .expect("set_derivation_origin error");
+    let response = api::derivation_origin(
         &env,
         issuer_id,
-        authorized_principal,
-        vec![construct_adult_credential()],
+        principal_1(),
+        &request("https://Staging.civic.example.com/"),
     )
-    .expect("failed to add employee");
-    let unauthorized_principal = test_principal(2);
-
-    let prepare_credential_response = api::prepare_credential(
+    .expect("API call failed")
+    .expect("derivation_origin error");
+    assert_eq!(response.origin, staging_origin);
+    assert_eq!(
+        api::get_derivation_origins(&env, issuer_id).expect("API call failed"),
+        vec![(staging.to_string(), staging_origin.to_string())]
+    );
+    // The configured frontend hostname keeps its derivation origin
+    let response = api::derivation_origin(
         &env,
         issuer_id,
-        authorized_principal,
-        &PrepareCredentialRequest {
+        principal_1(),
+        &request(DUMMY_FRONTEND_HOSTNAME),
+    )
+    .expect("API call failed")
+    .expect("derivation_origin error");
+    assert_eq!(response.origin, DUMMY_DERIVATION_ORIGIN);
+
+    api::set_derivation_origin(&env, issuer_id, admin, staging, None)
+        .expect("API call failed")
+        .expect("set_derivation_origin error");
+    let response = api::derivation_origin(&env, issuer_id, principal_1(), &request(staging))
+        .expect("API call failed");
+    assert_matches!(response, Err(DerivationOriginError::UnsupportedOrigin(_)));
+}
+
+/// Test: The alternative origins are generated from the admin-managed list and certified
+#[test]
+fn should_serve_alternative_origins() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let frontend = "https://frontend.civic.example.com";
+    let dev_server = "http://frontend.localhost:4943";
+    let get_origins = || -> serde_json::Value {
+        let body = get_certified_asset(&env, issuer_id, "/.well-known/ii-alternative-origins")
+            .expect("failed to get asset");
+        let document: serde_json::Value = serde_json::from_str(&body).expect("asset is not JSON");
+        document["alternativeOrigins"].clone()
+    };
+    assert_eq!(get_origins(), serde_json::json!([]));
+
+    // Only the admin can change the list
+    let response = api::add_alternative_origin(&env, issuer_id, principal_1(), frontend)
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+    let response =
+        api::add_alternative_origin(&env, issuer_id, admin, "frontend").expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnsupportedOrigin(_)));
+
+    for origin in ["https://Frontend.civic.example.com/", dev_server] {
+        api::add_alternative_origin(&env, issuer_id, admin, origin)
+            .expect("API call failed")
+            .expect("add_alternative_origin error");
+    }
+    assert_eq!(get_origins(), serde_json::json!([dev_server, frontend]));
+    assert_eq!(
+        api::get_alternative_origins(&env, issuer_id).expect("API call failed"),
+        vec![dev_server.to_string(), frontend.to_string()]
+    );
+
+    api::remove_alternative_origin(&env, issuer_id, admin, dev_server)
+        .expect("API call failed")
+        .expect("remove_alternative_origin error");
+    assert_eq!(get_origins(), serde_json::json!([frontend]));
+
+    // The list is served again after an upgrade
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    assert_eq!(get_origins(), serde_json::json!([frontend]));
+}
+
+/// Test: Uploaded files are certified, served after an upgrade and removed when deleted
+#[test]
+fn should_serve_uploaded_assets() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let html = r#"<html><script type="module" crossorigin src="/index.js"></script></html>"#;
+    let (first, second) = html.as_bytes().split_at(20);
+    let sha256 = Sha256::digest(html.as_bytes());
+    assert!(get_certified_asset(&env, issuer_id, "/").is_err());
+
+    // Only the admin can upload files, and not to the paths of the generated assets
+    let response = api::create_asset_batch(&env, issuer_id, principal_1(), "index.html")
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+    let response = api::create_asset_batch(&env, issuer_id, admin, ".well-known/did.json")
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::InvalidAsset(_)));
+
+    // The file must have the committed hash
+    let upload = |chunks: &[&[u8]]| {
+        let batch_id = api::create_asset_batch(&env, issuer_id, admin, "index.html")
+            .expect("API call failed")
+            .expect("create_asset_batch error");
+        for (index, chunk) in chunks.iter().enumerate().rev() {
+            api::upload_asset_chunk(&env, issuer_id, admin, batch_id, index as u32, chunk)
+                .expect("API call failed")
+                .expect("upload_asset_chunk error");
+        }
+        api::commit_asset_batch(&env, issuer_id, admin, batch_id, &sha256).expect("API call failed")
+    };
+    assert_matches!(upload(&[first]), Err(IssuerError::InvalidAsset(_)));
+    assert!(get_certified_asset(&env, issuer_id, "/").is_err());
+    upload(&[first, second]).expect("commit_asset_batch error");
+
+    // The HTML is served with the id of the canister
+    let expected = html.replace(
+        "<script type",
+        &format!("<script data-canister-id=\"{}\" type", issuer_id.to_text()),
+    );
+    for path in ["/", "/index.html"] {
+        assert_eq!(
+            get_certified_asset(&env, issuer_id, path),
+            Ok(expected.clone())
+        );
+    }
+
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    assert_eq!(get_certified_asset(&env, issuer_id, "/"), Ok(expected));
+
+    api::delete_asset(&env, issuer_id, admin, "index.html")
+        .expect("API call failed")
+        .expect("delete_asset error");
+    assert!(get_certified_asset(&env, issuer_id, "/").is_err());
+    assert!(get_certified_asset(&env, issuer_id, "/.well-known/did.json").is_ok());
+}
+
+/// Test: Uploads beyond the size limit of a file are refused
+#[test]
+fn should_fail_to_upload_asset_beyond_size_limit() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let chunk = vec![0u8; 1024 * 1024];
+    let batch_id = api::create_asset_batch(&env, issuer_id, admin, "assets/app.js")
+        .expect("API call failed")
+        .expect("create_asset_batch error");
+    for index in 0..20 {
+        api::upload_asset_chunk(&env, issuer_id, admin, batch_id, index, &chunk)
+            .expect("API call failed")
+            .expect("upload_asset_chunk error");
+    }
+    let response = api::upload_asset_chunk(&env, issuer_id, admin, batch_id, 20, &chunk)
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::InvalidAsset(_)));
+
+    // Replacing a chunk doesn't count it twice
+    api::upload_asset_chunk(&env, issuer_id, admin, batch_id, 0, &chunk)
+        .expect("API call failed")
+        .expect("upload_asset_chunk error");
+}
+
+/// Test: VC consent message failure if not supported
+#[test]
+fn should_fail_vc_consent_message_if_not_supported() {
+    let env = env();
+    let canister_id = install_canister(&env, CIVIV_CANISTER_BACKEND_WASM.clone());
+
+    let consent_message_request = Icrc21VcConsentMessageRequest {
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedResident".to_string(),
+            arguments: None,
+        },
+        preferences: Icrc21ConsentPreferences {
+            language: "en-US".to_string(),
+        },
+        signed_id_alias: None,
+    };
+
+    let response =
+        api::vc_consent_message(&env, canister_id, principal_1(), &consent_message_request)
+            .expect("API call failed");
+    assert_matches!(response, Err(Icrc21Error::UnsupportedCanisterCall(_)));
+}
+
+/// Test: Prepare credential for wrong sender
+#[test]
+fn should_fail_prepare_credential_for_wrong_sender() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let signed_id_alias = DUMMY_SIGNED_ID_ALIAS.clone();
+
+    let response = api::prepare_credential(
+        &env,
+        issuer_id,
+        principal_1(), // not the same as contained in signed_id_alias
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias,
+        },
+    )
+    .expect("API call failed");
+    assert_matches!(response,
+        Err(IssueCredentialError::InvalidIdAlias(e)) if e.contains("Id alias could not be verified")
+    );
+}
+
+/// Test: Get credential for wrong sender
+#[test]
+fn should_fail_get_credential_for_wrong_sender() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let signed_id_alias = DUMMY_SIGNED_ID_ALIAS.clone();
+    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let _ = api::add_credentials(
+        &env,
+        issuer_id,
+        authorized_principal,
+        vec![construct_adult_credential()],
+    )
+    .expect("failed to add employee");
+    let unauthorized_principal = test_principal(2);
+
+    let prepare_credential_response = api::prepare_credential(
+        &env,
+        issuer_id,
+        authorized_principal,
+        &PrepareCredentialRequest {
             credential_spec: adult_credential_spec(),
             signed_id_alias: signed_id_alias.clone(),
         },
@@ -956,6 +1783,7 @@ fn should_fail_prepare_credential_for_wrong_root_key() {
             idp_canister_ids: vec![Principal::from_text(DUMMY_II_CANISTER_ID).unwrap()],
             derivation_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
             frontend_hostname: DUMMY_FRONTEND_HOSTNAME.to_string(),
+            vc_issuer: None,
             admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
             authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
         },
@@ -984,6 +1812,7 @@ fn should_fail_prepare_credential_for_wrong_idp_canister_id() {
             idp_canister_ids: vec![Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap()], // does not match the DUMMY_II_CANISTER_ID, which is used in DUMMY_ALIAS_JWS
             derivation_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
             frontend_hostname: DUMMY_FRONTEND_HOSTNAME.to_string(),
+            vc_issuer: None,
             admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
             authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
         },
@@ -1023,113 +1852,1684 @@ fn should_prepare_adult_credential_for_authorized_principal() {
     assert_matches!(response, Ok(_));
 }
 
-/// Test: Issue credential end-to-end
+/// Test: A credential blocked by its holder is not used to prepare a VC
 #[test]
-fn should_issue_credential_e2e() -> Result<(), CallError> {
+fn should_fail_prepare_credential_for_credential_blocked_by_holder() {
     let env = env();
-    let ii_id = install_canister(&env, II_WASM.clone());
-    let issuer_id = install_issuer(
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, authorized_principal, vec![credential.clone()])
+        .expect("API call failed");
+
+    // The holder blocks the credential
+    let _ = api::set_credential_blocked(
         &env,
-        &IssuerInit {
-            ic_root_key_der: env.root_key().to_vec(),
-            idp_canister_ids: vec![ii_id],
-            derivation_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
-            frontend_hostname: DUMMY_FRONTEND_HOSTNAME.to_string(),
-            admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
-            authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
+        issuer_id,
+        authorized_principal,
+        credential.id.clone(),
+        true,
+    )
+    .expect("API call failed")
+    .expect("set_credential_blocked error");
+    let stored_credentials = api::get_all_credentials(&env, issuer_id, authorized_principal)
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert!(stored_credentials[0].blocked);
+
+    let response = api::prepare_credential(
+        &env,
+        issuer_id,
+        authorized_principal,
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
         },
+    )
+    .expect("API call failed");
+    assert_matches!(response,
+        Err(IssueCredentialError::UnauthorizedSubject(e)) if e.contains("blocked from sharing")
     );
-    let identity_number = flows::register_anchor(&env, ii_id);
-    let relying_party = FrontendHostname::from("https://some-dapp.com");
-    let issuer = FrontendHostname::from("https://some-issuer.com");
 
-    let prepare_id_alias_req = PrepareIdAliasRequest {
-        identity_number,
-        relying_party: relying_party.clone(),
-        issuer: issuer.clone(),
+    // II is refused the consent message for the holder's ID alias
+    let consent_message_request = Icrc21VcConsentMessageRequest {
+        credential_spec: adult_credential_spec(),
+        preferences: Icrc21ConsentPreferences {
+            language: "en-US".to_string(),
+        },
+        signed_id_alias: Some(DUMMY_SIGNED_ID_ALIAS.clone()),
     };
+    let response =
+        api::vc_consent_message(&env, issuer_id, principal_1(), &consent_message_request)
+            .expect("API call failed");
+    assert_matches!(response,
+        Err(Icrc21Error::ConsentMessageUnavailable(info))
+            if info.description.contains("blocked from sharing")
+    );
 
-    let prepared_id_alias =
-        ii_api::prepare_id_alias(&env, ii_id, principal_1(), prepare_id_alias_req)?
-            .expect("prepare id_alias failed");
-
-    let canister_sig_pk =
-        CanisterSigPublicKey::try_from(prepared_id_alias.canister_sig_pk_der.as_ref())
-            .expect("failed parsing canister sig pk");
+    // And unblocks it again
+    let _ = api::set_credential_blocked(&env, issuer_id, authorized_principal, credential.id, false)
+        .expect("API call failed")
+        .expect("set_credential_blocked error");
+    let response = api::prepare_credential(
+        &env,
+        issuer_id,
+        authorized_principal,
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed");
+    assert_matches!(response, Ok(_));
+}
 
-    let get_id_alias_req = GetIdAliasRequest {
-        identity_number,
-        relying_party,
-        issuer,
-        rp_id_alias_jwt: prepared_id_alias.rp_id_alias_jwt,
-        issuer_id_alias_jwt: prepared_id_alias.issuer_id_alias_jwt,
-    };
-    let id_alias_credentials = ii_api::get_id_alias(&env, ii_id, principal_1(), get_id_alias_req)?
-        .expect("get id_alias failed");
+/// Test: Only the holder can block their credential
+#[test]
+fn should_fail_to_block_credential_of_another_principal() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, principal_1(), vec![credential.clone()])
+        .expect("API call failed");
 
-    let root_pk_raw =
-        extract_raw_root_pk_from_der(&env.root_key()).expect("Failed decoding IC root key.");
-    let alias_tuple = get_verified_id_alias_from_jws(
-        &id_alias_credentials
-            .issuer_id_alias_credential
-            .credential_jws,
-        &id_alias_credentials.issuer_id_alias_credential.id_dapp,
-        &canister_sig_pk.canister_id,
-        &root_pk_raw,
-        env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
-    )
-    .expect("Invalid ID alias");
+    let response =
+        api::set_credential_blocked(&env, issuer_id, principal_2(), credential.id, true)
+            .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::NoCredentialFound(_)));
+}
 
-    let _ = api::add_credentials(
+/// Test: Preparing a credential is logged in the holder's issuance history
+#[test]
+fn should_record_issuance_in_holder_history() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, authorized_principal, vec![credential.clone()])
+        .expect("API call failed");
+    let _ = api::prepare_credential(
         &env,
         issuer_id,
-        alias_tuple.id_dapp,
-        vec![construct_adult_credential()],
-    )?;
+        authorized_principal,
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed")
+    .expect("failed to prepare credential");
 
-    for credential_spec in [adult_credential_spec()] {
-        let prepared_credential = api::prepare_credential(
-            &env,
-            issuer_id,
-            id_alias_credentials.issuer_id_alias_credential.id_dapp,
-            &PrepareCredentialRequest {
-                credential_spec: credential_spec.clone(),
-                signed_id_alias: SignedIssuerIdAlias {
-                    credential_jws: id_alias_credentials
-                        .issuer_id_alias_credential
-                        .credential_jws
-                        .clone(),
-                },
-            },
-        )?
-        .expect("failed to prepare credential");
+    let history = api::my_issuance_history(&env, issuer_id, authorized_principal)
+        .expect("API call failed");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].credential_type, "VerifiedAdult");
+    assert_eq!(history[0].credential_id, credential.id);
 
-        let get_credential_response = api::get_credential(
-            &env,
-            issuer_id,
-            id_alias_credentials.issuer_id_alias_credential.id_dapp,
-            &GetCredentialRequest {
-                credential_spec: credential_spec.clone(),
-                signed_id_alias: SignedIssuerIdAlias {
-                    credential_jws: id_alias_credentials
-                        .issuer_id_alias_credential
+    // Other principals cannot see the history
+    let history = api::my_issuance_history(&env, issuer_id, principal_1())
+        .expect("API call failed");
+    assert!(history.is_empty());
+}
+
+fn prepare_adult_credential(
+    env: &StateMachine,
+    issuer_id: CanisterId,
+    holder: Principal,
+) -> Result<PreparedCredentialData, IssueCredentialError> {
+    api::prepare_credential(
+        env,
+        issuer_id,
+        holder,
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed")
+}
+
+/// Test: The selection policy decides which of several matching credentials is used
+#[test]
+fn should_select_credential_by_policy() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let older = construct_adult_credential();
+    let newer = Credential {
+        id: "http://example.edu/credentials/3733".to_string(),
+        ..construct_adult_credential()
+    };
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![older.clone()])
+        .expect("API call failed");
+    env.advance_time(std::time::Duration::from_secs(1));
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![newer.clone()])
+        .expect("API call failed");
+
+    // The most recently updated credential is used
+    prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+
+    // Suspended credentials are skipped
+    api::set_credential_suspended(&env, issuer_id, civic_issuer, holder, newer.id.clone(), true)
+        .expect("API call failed")
+        .expect("set_credential_suspended error");
+    prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+
+    let history = api::my_issuance_history(&env, issuer_id, holder).expect("API call failed");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].credential_id, newer.id);
+    assert_eq!(history[1].credential_id, older.id);
+
+    // No credential is left if only credentials of another issuer are accepted
+    api::set_credential_selection_policy(
+        &env,
+        issuer_id,
+        civic_issuer,
+        "VerifiedAdult",
+        &SelectionPolicy {
+            preferred_issuers: vec![principal_1()],
+            preferred_issuers_only: true,
+        },
+    )
+    .expect("API call failed")
+    .expect("set_credential_selection_policy error");
+    assert_matches!(
+        prepare_adult_credential(&env, issuer_id, holder),
+        Err(IssueCredentialError::UnauthorizedSubject(e)) if e.contains("accepted issuer")
+    );
+}
+
+/// Test: Only the original issuer can suspend a credential
+#[test]
+fn should_fail_to_suspend_credential_of_another_issuer() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, principal_1(), vec![credential.clone()])
+        .expect("API call failed");
+
+    let response = api::set_credential_suspended(
+        &env,
+        issuer_id,
+        principal_2(),
+        principal_1(),
+        credential.id,
+        true,
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+}
+
+/// Test: Expired credentials are not used to prepare a VC
+#[test]
+fn should_fail_prepare_credential_for_expired_credential() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let now_ns = env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+    let credential = Credential {
+        expires_at_ns: Some(now_ns + 1_000_000_000),
+        ..construct_adult_credential()
+    };
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![credential])
+        .expect("API call failed");
+    prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+
+    env.advance_time(std::time::Duration::from_secs(2));
+    assert_matches!(
+        prepare_adult_credential(&env, issuer_id, holder),
+        Err(IssueCredentialError::UnauthorizedSubject(e)) if e.contains("expired")
+    );
+}
+
+/// Test: The signing key is generated after the installation and can be rotated by the admin
+#[test]
+fn should_rotate_signing_key() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    tick_until_signing_seed(&env, issuer_id);
+
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![construct_adult_credential()])
+        .expect("API call failed");
+    prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+    let keys = api::get_signing_public_keys(&env, issuer_id).expect("API call failed");
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].retired_at_ns, None);
+
+    let new_key = api::rotate_signing_key(&env, issuer_id, admin)
+        .expect("API call failed")
+        .expect("rotate_signing_key error");
+    let keys = api::get_signing_public_keys(&env, issuer_id).expect("API call failed");
+    assert_eq!(keys.len(), 2);
+    assert!(keys[0].retired_at_ns.is_some() && keys[0].valid_until_ns.is_some());
+    assert_eq!(keys[1].id, new_key.id);
+    assert_ne!(keys[0].public_key_der, new_key.public_key_der);
+
+    let response =
+        api::rotate_signing_key(&env, issuer_id, principal_1()).expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+}
+
+/// Test: Unexpired signatures survive an upgrade, expired ones are not restored
+#[test]
+fn should_restore_unexpired_signatures_after_upgrade() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![construct_adult_credential()])
+        .expect("API call failed");
+    let upgrade = || {
+        let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+        env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+            .expect("failed to upgrade canister");
+    };
+    let get_credential = |prepared: PreparedCredentialData| {
+        api::get_credential(
+            &env,
+            issuer_id,
+            holder,
+            &GetCredentialRequest {
+                credential_spec: adult_credential_spec(),
+                signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+                prepared_context: prepared.prepared_context,
+            },
+        )
+        .expect("API call failed")
+    };
+
+    let prepared =
+        prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+    upgrade();
+    assert_matches!(get_credential(prepared), Ok(_));
+
+    let prepared =
+        prepare_adult_credential(&env, issuer_id, holder).expect("failed to prepare credential");
+    env.advance_time(std::time::Duration::from_secs(120));
+    upgrade();
+    assert_matches!(
+        get_credential(prepared),
+        Err(IssueCredentialError::SignatureNotFound(_))
+    );
+}
+
+/// Test: An authorized issuer moves the credentials of a holder to a new principal
+#[test]
+fn should_migrate_credentials_as_issuer() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, principal_1(), vec![credential.clone()])
+        .expect("API call failed");
+
+    let _ = api::migrate_credentials(&env, issuer_id, civic_issuer, principal_1(), principal_2())
+        .expect("API call failed")
+        .expect("migrate_credentials error");
+
+    let moved = api::get_all_credentials(&env, issuer_id, principal_2())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(moved.len(), 1);
+    assert_eq!(moved[0].id, credential.id);
+    assert_eq!(moved[0].issuer, civic_issuer.to_text());
+    assert_matches!(
+        api::get_all_credentials(&env, issuer_id, principal_1()).expect("API call failed"),
+        Err(CredentialError::NoCredentialFound(_))
+    );
+
+    let log = api::get_migration_log(&env, issuer_id, civic_issuer)
+        .expect("API call failed")
+        .expect("get_migration_log error");
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].from, principal_1());
+    assert_eq!(log[0].to, principal_2());
+    assert_eq!(log[0].initiated_by, civic_issuer);
+    assert_eq!(log[0].credential_count, 1);
+}
+
+/// Test: Only issuers and the admin can move credentials on behalf of a holder
+#[test]
+fn should_fail_to_migrate_credentials_for_unauthorized_principal() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let _ = api::add_credentials(&env, issuer_id, principal_1(), vec![construct_adult_credential()])
+        .expect("API call failed");
+
+    let response =
+        api::migrate_credentials(&env, issuer_id, principal_2(), principal_1(), principal_2())
+            .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+}
+
+/// Test: An issuer that is not the admin only moves the credentials it issued
+#[test]
+fn should_migrate_only_credentials_of_calling_issuer() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let another_issuer = test_principal(3);
+    let _ = api::add_issuer(&env, issuer_id, civic_issuer, another_issuer)
+        .expect("API call failed")
+        .expect("add_issuer error");
+    let civic_credential = construct_adult_credential();
+    let _ = api::add_credentials(
+        &env,
+        issuer_id,
+        principal_1(),
+        vec![civic_credential.clone()],
+    )
+    .expect("API call failed");
+    let other_credential = Credential {
+        id: "http://example.edu/credentials/4711".to_string(),
+        ..construct_adult_credential()
+    };
+    let _ = api::add_credentials_with_sender(
+        &env,
+        issuer_id,
+        another_issuer,
+        principal_1(),
+        vec![other_credential.clone()],
+    )
+    .expect("API call failed");
+
+    let _ = api::migrate_credentials(
+        &env,
+        issuer_id,
+        another_issuer,
+        principal_1(),
+        principal_2(),
+    )
+    .expect("API call failed")
+    .expect("migrate_credentials error");
+
+    let moved = api::get_all_credentials(&env, issuer_id, principal_2())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(moved.len(), 1);
+    assert_eq!(moved[0].id, other_credential.id);
+    let kept = api::get_all_credentials(&env, issuer_id, principal_1())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].id, civic_credential.id);
+
+    // Without credentials of the issuer left there is nothing to migrate
+    let response = api::migrate_credentials(
+        &env,
+        issuer_id,
+        another_issuer,
+        principal_1(),
+        principal_2(),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::NoCredentialFound(_)));
+}
+
+/// Test: Credentials are not moved if the new principal has a credential with the same id
+#[test]
+fn should_fail_to_migrate_credentials_with_existing_id() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let credential = construct_adult_credential();
+    for holder in [principal_1(), principal_2()] {
+        let _ = api::add_credentials(&env, issuer_id, holder, vec![credential.clone()])
+            .expect("API call failed");
+    }
+
+    let response =
+        api::migrate_credentials(&env, issuer_id, civic_issuer, principal_1(), principal_2())
+            .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::InvalidRequest(_)));
+
+    let kept = api::get_all_credentials(&env, issuer_id, principal_1())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(kept.len(), 1);
+}
+
+/// Test: The holder moves their credentials by calling from both principals
+#[test]
+fn should_migrate_credentials_approved_by_holder() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, principal_1(), vec![credential.clone()])
+        .expect("API call failed");
+
+    // Without the approval of the old principal the migration fails
+    let response = api::complete_credential_migration(&env, issuer_id, principal_2(), principal_1())
+        .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+
+    let _ = api::approve_credential_migration(&env, issuer_id, principal_1(), principal_2())
+        .expect("API call failed")
+        .expect("approve_credential_migration error");
+    // The approval is kept across an upgrade
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    let _ = api::complete_credential_migration(&env, issuer_id, principal_2(), principal_1())
+        .expect("API call failed")
+        .expect("complete_credential_migration error");
+
+    let moved = api::get_all_credentials(&env, issuer_id, principal_2())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(moved.len(), 1);
+    assert_eq!(moved[0].id, credential.id);
+}
+
+/// Test: A holder requests a credential and an issuer approves it
+#[test]
+fn should_store_credential_for_approved_request() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let request_id = api::request_credential(
+        &env,
+        issuer_id,
+        principal_1(),
+        "VerifiedAdult".to_string(),
+        vec!["sha256:0123456789abcdef".to_string()],
+    )
+    .expect("API call failed")
+    .expect("request_credential error");
+
+    let pending = api::list_pending_credential_requests(&env, issuer_id, civic_issuer)
+        .expect("API call failed")
+        .expect("list_pending_credential_requests error");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].holder, principal_1());
+
+    let credential = construct_adult_credential();
+    let _ = api::approve_credential_request(
+        &env,
+        issuer_id,
+        civic_issuer,
+        request_id,
+        credential.clone(),
+    )
+    .expect("API call failed")
+    .expect("approve_credential_request error");
+
+    let requests = api::my_credential_requests(&env, issuer_id, principal_1())
+        .expect("API call failed");
+    assert_eq!(
+        requests[0].status,
+        CredentialRequestStatus::Approved {
+            credential_id: credential.id.clone()
+        }
+    );
+    let stored_credentials = api::get_all_credentials(&env, issuer_id, principal_1())
+        .expect("API call failed")
+        .expect("get_all_credentials error");
+    assert_eq!(stored_credentials[0].id, credential.id);
+    assert_eq!(stored_credentials[0].issuer, civic_issuer.to_text());
+}
+
+/// Test: An issuer rejects a request, which cannot be processed again afterwards
+#[test]
+fn should_reject_credential_request() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let request_id = api::request_credential(
+        &env,
+        issuer_id,
+        principal_1(),
+        "VerifiedAdult".to_string(),
+        vec![],
+    )
+    .expect("API call failed")
+    .expect("request_credential error");
+
+    // Holders cannot process requests
+    let response = api::reject_credential_request(
+        &env,
+        issuer_id,
+        principal_1(),
+        request_id,
+        "no".to_string(),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+
+    let _ = api::reject_credential_request(
+        &env,
+        issuer_id,
+        civic_issuer,
+        request_id,
+        "Missing evidence".to_string(),
+    )
+    .expect("API call failed")
+    .expect("reject_credential_request error");
+    let requests = api::my_credential_requests(&env, issuer_id, principal_1())
+        .expect("API call failed");
+    assert_eq!(
+        requests[0].status,
+        CredentialRequestStatus::Rejected {
+            reason: "Missing evidence".to_string()
+        }
+    );
+
+    let response = api::approve_credential_request(
+        &env,
+        issuer_id,
+        civic_issuer,
+        request_id,
+        construct_adult_credential(),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::InvalidRequest(_)));
+}
+
+/// Test: Processed requests are pruned after the retention period, survive upgrades until then,
+/// and their ids are not reused
+#[test]
+fn should_prune_processed_credential_requests() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let request = |holder: Principal| {
+        api::request_credential(&env, issuer_id, holder, "VerifiedAdult".to_string(), vec![])
+            .expect("API call failed")
+            .expect("request_credential error")
+    };
+    let rejected_id = request(principal_1());
+    let pending_id = request(principal_2());
+    let _ = api::reject_credential_request(
+        &env,
+        issuer_id,
+        civic_issuer,
+        rejected_id,
+        "Missing evidence".to_string(),
+    )
+    .expect("API call failed")
+    .expect("reject_credential_request error");
+
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    let requests =
+        api::my_credential_requests(&env, issuer_id, principal_1()).expect("API call failed");
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].processed_at_ns.is_some());
+    let pending = api::list_pending_credential_requests(&env, issuer_id, civic_issuer)
+        .expect("API call failed")
+        .expect("list_pending_credential_requests error");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, pending_id);
+
+    env.advance_time(std::time::Duration::from_secs(31 * 24 * 60 * 60));
+    let new_id = request(principal_1());
+    assert!(new_id > pending_id);
+    let requests =
+        api::my_credential_requests(&env, issuer_id, principal_1()).expect("API call failed");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].id, new_id);
+    // Pending requests are not pruned
+    let requests =
+        api::my_credential_requests(&env, issuer_id, principal_2()).expect("API call failed");
+    assert_eq!(requests.len(), 1);
+}
+
+/// Test: Requests for unknown types, with too much evidence or beyond the pending limit are refused
+#[test]
+fn should_fail_to_request_credential_beyond_limits() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let request = |credential_type: &str, evidence_refs: Vec<String>| {
+        api::request_credential(
+            &env,
+            issuer_id,
+            principal_1(),
+            credential_type.to_string(),
+            evidence_refs,
+        )
+        .expect("API call failed")
+    };
+
+    assert_matches!(
+        request("UnknownType", vec![]),
+        Err(CredentialError::InvalidRequest(_))
+    );
+    assert_matches!(
+        request("VerifiedAdult", vec!["sha256:00".to_string(); 11]),
+        Err(CredentialError::InvalidRequest(_))
+    );
+    assert_matches!(
+        request("VerifiedAdult", vec!["a".repeat(257)]),
+        Err(CredentialError::InvalidRequest(_))
+    );
+
+    for _ in 0..10 {
+        let _ = request("VerifiedAdult", vec![]).expect("request_credential error");
+    }
+    assert_matches!(
+        request("VerifiedAdult", vec![]),
+        Err(CredentialError::InvalidRequest(_))
+    );
+}
+
+/// Test: Only the issuers whose credentials the type accepts can process its requests
+#[test]
+fn should_process_credential_requests_only_as_accepted_issuer() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let civic_issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let another_issuer = test_principal(3);
+    let _ = api::add_issuer(&env, issuer_id, civic_issuer, another_issuer)
+        .expect("API call failed")
+        .expect("add_issuer error");
+    api::set_credential_selection_policy(
+        &env,
+        issuer_id,
+        civic_issuer,
+        "VerifiedAdult",
+        &SelectionPolicy {
+            preferred_issuers: vec![civic_issuer],
+            preferred_issuers_only: true,
+        },
+    )
+    .expect("API call failed")
+    .expect("set_credential_selection_policy error");
+    let request_id = api::request_credential(
+        &env,
+        issuer_id,
+        principal_1(),
+        "VerifiedAdult".to_string(),
+        vec![],
+    )
+    .expect("API call failed")
+    .expect("request_credential error");
+
+    let pending = api::list_pending_credential_requests(&env, issuer_id, another_issuer)
+        .expect("API call failed")
+        .expect("list_pending_credential_requests error");
+    assert!(pending.is_empty());
+    let response = api::approve_credential_request(
+        &env,
+        issuer_id,
+        another_issuer,
+        request_id,
+        construct_adult_credential(),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+    let response = api::reject_credential_request(
+        &env,
+        issuer_id,
+        another_issuer,
+        request_id,
+        "no".to_string(),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(CredentialError::UnauthorizedSubject(_)));
+
+    let pending = api::list_pending_credential_requests(&env, issuer_id, civic_issuer)
+        .expect("API call failed")
+        .expect("list_pending_credential_requests error");
+    assert_eq!(pending.len(), 1);
+}
+
+/// Test: A spec whose arguments the stored credential cannot prove is refused
+#[test]
+fn should_fail_prepare_credential_for_unsatisfiable_arguments() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let _ = api::add_credentials(
+        &env,
+        issuer_id,
+        authorized_principal,
+        vec![construct_adult_credential()],
+    )
+    .expect("API call failed");
+
+    let mut args = HashMap::new();
+    args.insert("minAge".to_string(), ArgumentValue::Int(21));
+    let response = api::prepare_credential(
+        &env,
+        issuer_id,
+        authorized_principal,
+        &PrepareCredentialRequest {
+            credential_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: Some(args),
+            },
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(IssueCredentialError::UnsupportedCredentialSpec(_)));
+}
+
+/// Test: Age predicates are derived from a stored birth date, which is not put into the VC
+#[test]
+fn should_issue_age_predicates_derived_from_birth_date() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let mut credential = construct_adult_credential();
+    // The holder is 30 years old at the start time of the state machine (2021-05-06)
+    credential.claim = vec![Claim {
+        claims: HashMap::from([(
+            "dateOfBirth".to_string(),
+            ClaimValue::Date("1990-06-01".to_string()),
+        )]),
+    }];
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![credential])
+        .expect("API call failed");
+    let root_pk_raw =
+        extract_raw_root_pk_from_der(&env.root_key()).expect("Failed decoding IC root key.");
+
+    let spec = |credential_type: &str, args: &[(&str, i32)]| CredentialSpec {
+        credential_type: credential_type.to_string(),
+        arguments: Some(
+            args.iter()
+                .map(|(name, value)| (name.to_string(), ArgumentValue::Int(*value)))
+                .collect(),
+        ),
+    };
+    for credential_spec in [
+        spec("VerifiedAdult", &[("minAge", 21)]),
+        spec("AgeRange", &[("min", 25), ("max", 30)]),
+    ] {
+        let prepared = api::prepare_credential(
+            &env,
+            issuer_id,
+            holder,
+            &PrepareCredentialRequest {
+                credential_spec: credential_spec.clone(),
+                signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+            },
+        )
+        .expect("API call failed")
+        .expect("failed to prepare credential");
+        let issued = api::get_credential(
+            &env,
+            issuer_id,
+            holder,
+            &GetCredentialRequest {
+                credential_spec: credential_spec.clone(),
+                signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+                prepared_context: prepared.prepared_context,
+            },
+        )
+        .expect("API call failed")
+        .expect("failed to get credential");
+        let claims = verify_credential_jws_with_canister_id(
+            &issued.vc_jws,
+            &issuer_id,
+            &root_pk_raw,
+            env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
+        )
+        .expect("credential verification failed");
+        let vc_claims = claims.vc().expect("missing VC claims");
+        assert!(!format!("{:?}", vc_claims).contains("1990-06-01"));
+        validate_claims_match_spec(vc_claims, &credential_spec)
+            .expect("VC claims do not match the credential spec");
+    }
+
+    for credential_spec in [
+        spec("VerifiedAdult", &[("minAge", 31)]),
+        spec("AgeRange", &[("min", 18), ("max", 29)]),
+    ] {
+        let response = api::prepare_credential(
+            &env,
+            issuer_id,
+            holder,
+            &PrepareCredentialRequest {
+                credential_spec,
+                signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+            },
+        )
+        .expect("API call failed");
+        assert_matches!(response, Err(IssueCredentialError::UnsupportedCredentialSpec(_)));
+    }
+}
+
+fn resident_credential_type() -> CredentialTypeDefinition {
+    CredentialTypeDefinition {
+        name: "VerifiedResident".to_string(),
+        accepted_types: vec!["VerifiedResident".to_string()],
+        arguments: vec![],
+        required_any_of: None,
+        disclosable_claims: Some(vec!["country".to_string(), "city".to_string()]),
+        lifetime: Some(LifetimePolicy {
+            min_ns: 60_000_000_000,
+            max_ns: 3_600_000_000_000,
+            default_ns: 60_000_000_000,
+        }),
+        selection: Some(SelectionPolicy::default()),
+        consent_templates: HashMap::from([(
+            "en".to_string(),
+            "# Verified Resident".to_string(),
+        )]),
+        display: CredentialDisplay {
+            name: "Verified Resident".to_string(),
+            description: "The holder is a verified resident.".to_string(),
+            icon: None,
+        },
+        claim_display: None,
+    }
+}
+
+/// Test: The registry is seeded with the default credential types
+#[test]
+fn should_return_default_credential_types() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let types = api::get_credential_types(&env, issuer_id).expect("API call failed");
+    let names: Vec<&str> = types.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["AgeRange", "VerifiedAdult"]);
+}
+
+/// Test: The supported standards and the manifest of the credential types can be discovered
+#[test]
+fn should_return_supported_standards_and_manifest() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let standards = api::icrc10_supported_standards(&env, issuer_id).expect("API call failed");
+    let names: Vec<&str> = standards.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["ICRC-10", "ICRC-21", "VC-ISSUER"]);
+
+    // Credential types added at runtime are listed as well
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    api::set_credential_type(&env, issuer_id, admin, &resident_credential_type())
+        .expect("API call failed")
+        .expect("set_credential_type error");
+    let ManifestResponse::Ok(manifest) =
+        api::vc_manifest(&env, issuer_id).expect("API call failed")
+    else {
+        panic!("vc_manifest error");
+    };
+    let names: Vec<&str> = manifest
+        .credential_types
+        .iter()
+        .map(|t| t.credential_type.as_str())
+        .collect();
+    assert_eq!(names, vec!["AgeRange", "VerifiedAdult", "VerifiedResident"]);
+    let resident = &manifest.credential_types[2];
+    assert_eq!(resident.disclosable_claims, vec!["country", "city"]);
+    assert_eq!(resident.consent_languages, vec!["en"]);
+    assert_eq!(resident.lifetime.max_seconds, 3_600);
+    assert!(resident.arguments.iter().any(|arg| arg.name == "disclose"));
+}
+
+/// Test: The admin adds a credential type at runtime, which is then used for consent messages
+#[test]
+fn should_add_credential_type_as_admin() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+
+    // Only the admin can change the registry
+    let response = api::set_credential_type(&env, issuer_id, principal_1(), &resident_credential_type())
+        .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+
+    api::set_credential_type(&env, issuer_id, admin, &resident_credential_type())
+        .expect("API call failed")
+        .expect("set_credential_type error");
+
+    let consent_message_request = Icrc21VcConsentMessageRequest {
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedResident".to_string(),
+            arguments: None,
+        },
+        preferences: Icrc21ConsentPreferences {
+            language: "de-DE".to_string(),
+        },
+        signed_id_alias: None,
+    };
+    let response =
+        api::vc_consent_message(&env, issuer_id, principal_1(), &consent_message_request)
+            .expect("API call failed")
+            .expect("Consent message error");
+    // There is no German template, so the default language is used
+    assert_eq!(response.language, "en");
+    assert_eq!(response.consent_message, "# Verified Resident");
+
+    // The consent message lists the claims to disclose, which must be disclosable
+    let mut consent_message_request = consent_message_request;
+    consent_message_request.credential_spec.arguments = Some(HashMap::from([(
+        "disclose".to_string(),
+        ArgumentValue::String("country".to_string()),
+    )]));
+    let response =
+        api::vc_consent_message(&env, issuer_id, principal_1(), &consent_message_request)
+            .expect("API call failed")
+            .expect("Consent message error");
+    assert!(response.consent_message.ends_with("\n- country"));
+
+    consent_message_request.credential_spec.arguments = Some(HashMap::from([(
+        "disclose".to_string(),
+        ArgumentValue::String("country,name".to_string()),
+    )]));
+    let response =
+        api::vc_consent_message(&env, issuer_id, principal_1(), &consent_message_request)
+            .expect("API call failed");
+    assert_matches!(response, Err(Icrc21Error::UnsupportedCanisterCall(_)));
+}
+
+/// Test: The admin edits consent templates per language tag, which are looked up with fallbacks
+#[test]
+fn should_set_consent_template_as_admin() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let consent_message = |language: &str, min_age: Option<i32>| {
+        let request = Icrc21VcConsentMessageRequest {
+            credential_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: min_age
+                    .map(|age| HashMap::from([("minAge".to_string(), ArgumentValue::Int(age))])),
+            },
+            preferences: Icrc21ConsentPreferences {
+                language: language.to_string(),
+            },
+            signed_id_alias: None,
+        };
+        api::vc_consent_message(&env, issuer_id, principal_1(), &request)
+            .expect("API call failed")
+            .expect("Consent message error")
+    };
+
+    let response = api::set_consent_template(
+        &env,
+        issuer_id,
+        principal_1(),
+        "VerifiedAdult",
+        "de-CH",
+        Some("# Volljährig"),
+    )
+    .expect("API call failed");
+    assert_matches!(response, Err(IssuerError::UnauthorizedSubject(_)));
+    for (language, template) in [("de-", "# Volljährig"), ("de-CH", "{unknownArgument}")] {
+        let response = api::set_consent_template(
+            &env,
+            issuer_id,
+            admin,
+            "VerifiedAdult",
+            language,
+            Some(template),
+        )
+        .expect("API call failed");
+        assert_matches!(response, Err(IssuerError::UnsupportedCredentialSpec(_)));
+    }
+    api::set_consent_template(
+        &env,
+        issuer_id,
+        admin,
+        "VerifiedAdult",
+        "de-CH",
+        Some("# Volljährig\n\nMindestens {minAge|18} Jahre alt."),
+    )
+    .expect("API call failed")
+    .expect("set_consent_template error");
+
+    let response = consent_message("de-CH", Some(21));
+    assert_eq!(response.language, "de-ch");
+    assert!(response
+        .consent_message
+        .starts_with("# Volljährig\n\nMindestens 21 Jahre alt.\n\n## Offengelegte Angaben"));
+    let response = consent_message("de-AT", None);
+    assert_eq!(response.language, "de");
+    assert!(response.consent_message.contains("mindestens 18 Jahre"));
+    for language in ["", "d", "fr-CH"] {
+        let response = consent_message(language, Some(21));
+        assert_eq!(response.language, "en");
+        assert!(response.consent_message.contains("at least 21 years"));
+    }
+
+    // Removing the template falls back to the shorter language tag
+    api::set_consent_template(&env, issuer_id, admin, "VerifiedAdult", "de-ch", None)
+        .expect("API call failed")
+        .expect("set_consent_template error");
+    assert_eq!(consent_message("de-CH", None).language, "de");
+}
+
+/// Test: The consent message lists the claims to disclose with labels and masked previews
+#[test]
+fn should_list_disclosed_claims_in_consent_message() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let label = |de: &str, masked_preview: bool| ClaimDisplay {
+        labels: HashMap::from([("de".to_string(), de.to_string())]),
+        masked_preview,
+    };
+    let credential_type = CredentialTypeDefinition {
+        claim_display: Some(HashMap::from([
+            ("city".to_string(), label("Wohnort", true)),
+            ("country".to_string(), label("Land", false)),
+        ])),
+        consent_templates: HashMap::from([("de".to_string(), "# Wohnsitz bestätigt".to_string())]),
+        ..resident_credential_type()
+    };
+    api::set_credential_type(&env, issuer_id, admin, &credential_type)
+        .expect("API call failed")
+        .expect("set_credential_type error");
+    let mut credential = construct_adult_credential();
+    credential.type_ = vec!["VerifiedResident".to_string()];
+    credential.claim = vec![Claim {
+        claims: HashMap::from([
+            ("country".to_string(), ClaimValue::Text("CH".to_string())),
+            ("city".to_string(), ClaimValue::Text("Zurich".to_string())),
+        ]),
+    }];
+    api::add_credentials(&env, issuer_id, holder, vec![credential])
+        .expect("API call failed")
+        .expect("add_credentials error");
+
+    let consent_message_request = Icrc21VcConsentMessageRequest {
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedResident".to_string(),
+            arguments: Some(HashMap::from([(
+                "disclose".to_string(),
+                ArgumentValue::String("city,country".to_string()),
+            )])),
+        },
+        preferences: Icrc21ConsentPreferences {
+            language: "de-CH".to_string(),
+        },
+        signed_id_alias: Some(DUMMY_SIGNED_ID_ALIAS.clone()),
+    };
+    // II requests the message with the ID alias of the holder
+    let response =
+        api::vc_consent_message(&env, issuer_id, principal_1(), &consent_message_request)
+            .expect("API call failed")
+            .expect("Consent message error");
+    assert!(response
+        .consent_message
+        .ends_with("## Offengelegte Angaben\n\n- Wohnort: Zu\\*\\*\\*\\*\n- Land"));
+
+    // The wallet shows the preview in the fields display
+    let fields_request = Icrc21VcStructuredConsentMessageRequest {
+        credential_spec: consent_message_request.credential_spec.clone(),
+        user_preferences: Icrc21ConsentMessageSpec {
+            metadata: Icrc21ConsentMessageMetadata {
+                language: "de-CH".to_string(),
+                utc_offset_minutes: None,
+            },
+            device_spec: Some(Icrc21DeviceSpec::FieldsDisplay),
+        },
+        signed_id_alias: Some(DUMMY_SIGNED_ID_ALIAS.clone()),
+    };
+    let response = api::icrc21_vc_consent_message(&env, issuer_id, principal_1(), &fields_request)
+        .expect("API call failed")
+        .expect("Consent message error");
+    let Icrc21ConsentMessage::FieldsDisplayMessage { fields, .. } = response.consent_message else {
+        panic!("expected a fields display message");
+    };
+    let text = |content: &str| Icrc21Value::Text {
+        content: content.to_string(),
+    };
+    assert_eq!(
+        fields,
+        vec![
+            ("Wohnort".to_string(), text("Zu****")),
+            ("Land".to_string(), text("nicht angezeigt")),
+        ]
+    );
+
+    // Without an ID alias, the claims are listed without preview
+    let consent_message_request = Icrc21VcConsentMessageRequest {
+        signed_id_alias: None,
+        ..consent_message_request
+    };
+    let response = api::vc_consent_message(&env, issuer_id, holder, &consent_message_request)
+        .expect("API call failed")
+        .expect("Consent message error");
+    assert!(response.consent_message.ends_with("\n\n- Wohnort\n- Land"));
+}
+
+/// Test: A VC is issued as SD-JWT, from which the holder presents only some claims
+#[test]
+fn should_issue_sd_jwt_credential() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    api::set_credential_type(&env, issuer_id, admin, &resident_credential_type())
+        .expect("API call failed")
+        .expect("set_credential_type error");
+    let mut credential = construct_adult_credential();
+    credential.type_ = vec!["VerifiedResident".to_string()];
+    credential.claim = vec![Claim {
+        claims: HashMap::from([
+            ("country".to_string(), ClaimValue::Text("CH".to_string())),
+            ("city".to_string(), ClaimValue::Text("Zurich".to_string())),
+            ("street".to_string(), ClaimValue::Text("Bahnhofstrasse".to_string())),
+        ]),
+    }];
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![credential])
+        .expect("API call failed");
+
+    let credential_spec = CredentialSpec {
+        credential_type: "VerifiedResident".to_string(),
+        arguments: Some(HashMap::from([(
+            "format".to_string(),
+            ArgumentValue::String("sd-jwt".to_string()),
+        )])),
+    };
+    let prepared = api::prepare_credential(
+        &env,
+        issuer_id,
+        holder,
+        &PrepareCredentialRequest {
+            credential_spec: credential_spec.clone(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed")
+    .expect("failed to prepare credential");
+    let get_credential_request = GetCredentialRequest {
+        credential_spec,
+        signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        prepared_context: prepared.prepared_context,
+    };
+    let sd_jwt = api::get_sd_jwt_credential(&env, issuer_id, holder, &get_credential_request)
+        .expect("API call failed")
+        .expect("failed to get credential")
+        .vc_jws;
+
+    // The signed JWT only contains the digests of the disclosable claims
+    let (issuer_jws, disclosures) = split_sd_jwt(&sd_jwt).expect("malformed SD-JWT");
+    assert_eq!(disclosures.len(), 2);
+    assert!(!issuer_jws.contains("Zurich"));
+    // The flow of Internet Identity only gets the signed JWT
+    let vc_jws = api::get_credential(&env, issuer_id, holder, &get_credential_request)
+        .expect("API call failed")
+        .expect("failed to get credential")
+        .vc_jws;
+    assert_eq!(vc_jws, issuer_jws);
+    let root_pk_raw =
+        extract_raw_root_pk_from_der(&env.root_key()).expect("Failed decoding IC root key.");
+    let now_ns = env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let claims = verify_sd_jwt_with_canister_id(&sd_jwt, &issuer_id, &root_pk_raw, now_ns)
+        .expect("SD-JWT verification failed");
+    assert_eq!(claims.get("vct"), Some(&serde_json::json!("VerifiedResident")));
+    assert_eq!(claims.get("country"), Some(&serde_json::json!("CH")));
+    assert_eq!(claims.get("city"), Some(&serde_json::json!("Zurich")));
+    assert!(!claims.contains_key("street"));
+
+    let presented = select_disclosures(&sd_jwt, &["city"]).expect("malformed SD-JWT");
+    let claims = verify_sd_jwt_with_canister_id(&presented, &issuer_id, &root_pk_raw, now_ns)
+        .expect("SD-JWT verification failed");
+    assert!(claims.contains_key("city"));
+    assert!(!claims.contains_key("country"));
+
+    // Only VCs prepared as SD-JWT are returned with their disclosures
+    let response = api::get_sd_jwt_credential(
+        &env,
+        issuer_id,
+        holder,
+        &GetCredentialRequest {
+            credential_spec: CredentialSpec {
+                credential_type: "VerifiedResident".to_string(),
+                arguments: None,
+            },
+            ..get_credential_request
+        },
+    )
+    .expect("API call failed");
+    assert_matches!(
+        response,
+        Err(IssueCredentialError::UnsupportedCredentialSpec(_))
+    );
+}
+
+/// Gets the certified asset served by the issuer canister at the given path.
+fn get_certified_asset(
+    env: &StateMachine,
+    issuer_id: CanisterId,
+    path: &str,
+) -> Result<String, String> {
+    let response = http_request(
+        env,
+        issuer_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: path.to_string(),
+            headers: vec![],
+            body: ByteBuf::new(),
+            certificate_version: Some(2),
+        },
+    )
+    .map_err(|e| format!("HTTP request failed: {:?}", e))?;
+    if response.status_code != 200 {
+        return Err(format!("HTTP status {}", response.status_code));
+    }
+    assert!(response
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("IC-Certificate")));
+    String::from_utf8(response.body.into_vec()).map_err(|_| "body is not UTF-8".to_string())
+}
+
+/// Fetches the response of the issuer canister with the status list credential at the given URL.
+fn fetch_status_list(
+    env: &StateMachine,
+    issuer_id: CanisterId,
+    url: &str,
+) -> Result<StatusListResponse, String> {
+    let path = url
+        .strip_prefix(DUMMY_DERIVATION_ORIGIN)
+        .ok_or(format!("{} is not served by the issuer canister", url))?;
+    let response = http_request(
+        env,
+        issuer_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: path.to_string(),
+            headers: vec![],
+            body: ByteBuf::new(),
+            certificate_version: Some(2),
+        },
+    )
+    .map_err(|e| format!("HTTP request failed: {:?}", e))?;
+    Ok(StatusListResponse {
+        status_code: response.status_code,
+        headers: response.headers,
+        body: response.body.into_vec(),
+    })
+}
+
+/// Test: The status lists of issued VCs follow the suspension and removal of the credential
+#[test]
+fn should_publish_status_list_of_issued_vcs() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let issuer = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let holder = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let credential = construct_adult_credential();
+    let _ = api::add_credentials(&env, issuer_id, holder, vec![credential.clone()])
+        .expect("API call failed");
+
+    let prepared = api::prepare_credential(
+        &env,
+        issuer_id,
+        holder,
+        &PrepareCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed")
+    .expect("failed to prepare credential");
+    let vc_jws = api::get_credential(
+        &env,
+        issuer_id,
+        holder,
+        &GetCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+            prepared_context: prepared.prepared_context,
+        },
+    )
+    .expect("API call failed")
+    .expect("failed to get credential")
+    .vc_jws;
+    let root_pk_raw =
+        extract_raw_root_pk_from_der(&env.root_key()).expect("Failed decoding IC root key.");
+    let claims = verify_credential_jws_with_canister_id(
+        &vc_jws,
+        &issuer_id,
+        &root_pk_raw,
+        env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
+    )
+    .expect("credential verification failed");
+    let vc_claims = claims.vc().expect("missing VC claims");
+    let vc_issuer = claims.iss().expect("missing issuer");
+    assert_eq!(vc_issuer, DUMMY_DERIVATION_ORIGIN);
+    let entries = status_list_entries(vc_claims).expect("malformed credentialStatus");
+    let purposes: Vec<&str> = entries.iter().map(|e| e.status_purpose.as_str()).collect();
+    assert_eq!(purposes, vec!["revocation", "suspension"]);
+
+    let check_status = || {
+        check_credential_status(
+            vc_claims,
+            vc_issuer,
+            &issuer_id,
+            &env.root_key(),
+            env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
+            |url| fetch_status_list(&env, issuer_id, url),
+        )
+        .expect("status check failed")
+    };
+    assert!(check_status().is_empty());
+
+    api::set_credential_suspended(&env, issuer_id, issuer, holder, credential.id.clone(), true)
+        .expect("API call failed")
+        .expect("failed to suspend credential");
+    assert_eq!(check_status(), vec!["suspension"]);
+    api::set_credential_suspended(&env, issuer_id, issuer, holder, credential.id.clone(), false)
+        .expect("API call failed")
+        .expect("failed to reinstate credential");
+    assert!(check_status().is_empty());
+
+    api::remove_credential(&env, issuer, issuer_id, holder, credential.id)
+        .expect("API call failed")
+        .expect("failed to remove credential");
+    assert_eq!(check_status(), vec!["revocation"]);
+
+    // The status lists are served again after an upgrade
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    assert_eq!(check_status(), vec!["revocation"]);
+}
+
+/// Test: The DID document and the JWKS list the signing keys and the authorized issuers
+#[test]
+fn should_serve_did_document_and_jwks() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let admin = Principal::from_text(ISSUER_PRINCIPAL).unwrap();
+    let get_json = |path: &str| -> serde_json::Value {
+        let body = get_certified_asset(&env, issuer_id, path).expect("failed to get asset");
+        serde_json::from_str(&body).expect("asset is not JSON")
+    };
+    tick_until_signing_seed(&env, issuer_id);
+    for _ in 0..2 {
+        api::rotate_signing_key(&env, issuer_id, admin)
+            .expect("API call failed")
+            .expect("rotate_signing_key error");
+    }
+    let public_keys = api::get_signing_public_keys(&env, issuer_id).expect("API call failed");
+    assert_eq!(public_keys.len(), 3);
+    let did = format!(
+        "did:web:{}",
+        DUMMY_DERIVATION_ORIGIN.strip_prefix("https://").unwrap()
+    );
+    let check_keys = || {
+        let jwks = get_json("/.well-known/jwks.json");
+        let keys = jwks["keys"].as_array().expect("missing keys");
+        assert_eq!(keys.len(), public_keys.len());
+        for (key, public_key) in keys.iter().zip(&public_keys) {
+            // The JWKs are the ones put into the JWS header of the VCs
+            let expected = serde_json::to_value(
+                canister_sig_pk_jwk(&public_key.public_key_der).expect("invalid public key"),
+            )
+            .unwrap();
+            assert_eq!(key["alg"], expected["alg"]);
+            assert_eq!(key["kty"], expected["kty"]);
+            assert_eq!(key["k"], expected["k"]);
+            assert_eq!(key["kid"], serde_json::json!(format!("{}#key-{}", did, public_key.id)));
+        }
+        let did_document = get_json("/.well-known/did.json");
+        assert_eq!(did_document["id"], serde_json::json!(did));
+        assert_eq!(did_document["verificationMethod"][0]["publicKeyJwk"], keys[0]);
+        assert_eq!(
+            did_document["assertionMethod"].as_array().map(Vec::len),
+            Some(public_keys.len())
+        );
+        did_document
+    };
+    // The DID document is only known as the canister and the issuer of the VCs
+    let also_known_as = serde_json::json!([
+        format!("did:icp:{}", issuer_id.to_text()),
+        DUMMY_DERIVATION_ORIGIN
+    ]);
+    assert_eq!(check_keys()["alsoKnownAs"], also_known_as);
+
+    // Both documents are served again after an upgrade
+    let arg = candid::encode_one(Some(&*DUMMY_ISSUER_INIT)).expect("error encoding init arg");
+    env.upgrade_canister(issuer_id, CIVIV_CANISTER_BACKEND_WASM.clone(), arg, None)
+        .expect("failed to upgrade canister");
+    assert_eq!(check_keys()["alsoKnownAs"], also_known_as);
+}
+
+/// Test: The requested lifetime is clamped to the policy and reported in the prepare response
+#[test]
+fn should_report_clamped_vc_expiration() {
+    let env = env();
+    let issuer_id = install_issuer(&env, &DUMMY_ISSUER_INIT);
+    let authorized_principal = Principal::from_text(DUMMY_ALIAS_ID_DAPP_PRINCIPAL).unwrap();
+    let _ = api::add_credentials(
+        &env,
+        issuer_id,
+        authorized_principal,
+        vec![construct_adult_credential()],
+    )
+    .expect("API call failed");
+
+    let mut args = HashMap::new();
+    // Far above the maximum lifetime of one day
+    args.insert("lifetimeSeconds".to_string(), ArgumentValue::Int(1_000_000));
+    let prepared = api::prepare_credential(
+        &env,
+        issuer_id,
+        authorized_principal,
+        &PrepareCredentialRequest {
+            credential_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: Some(args),
+            },
+            signed_id_alias: DUMMY_SIGNED_ID_ALIAS.clone(),
+        },
+    )
+    .expect("API call failed")
+    .expect("failed to prepare credential");
+
+    let now_s = env.time().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let expiration_s = prepared.expiration_timestamp_s.expect("missing expiration") as u64;
+    assert_eq!(expiration_s - now_s, 24 * 60 * 60);
+}
+
+/// Test: Issue credential end-to-end
+#[test]
+fn should_issue_credential_e2e() -> Result<(), CallError> {
+    let env = env();
+    let ii_id = install_canister(&env, II_WASM.clone());
+    let issuer_id = install_issuer(
+        &env,
+        &IssuerInit {
+            ic_root_key_der: env.root_key().to_vec(),
+            idp_canister_ids: vec![ii_id],
+            derivation_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
+            frontend_hostname: DUMMY_FRONTEND_HOSTNAME.to_string(),
+            vc_issuer: None,
+            admin: Principal::from_text(ISSUER_PRINCIPAL).unwrap(),
+            authorized_issuers: vec![Principal::from_text(ISSUER_PRINCIPAL).unwrap()],
+        },
+    );
+    let identity_number = flows::register_anchor(&env, ii_id);
+    let relying_party = FrontendHostname::from("https://some-dapp.com");
+    let issuer = FrontendHostname::from("https://some-issuer.com");
+
+    let prepare_id_alias_req = PrepareIdAliasRequest {
+        identity_number,
+        relying_party: relying_party.clone(),
+        issuer: issuer.clone(),
+    };
+
+    let prepared_id_alias =
+        ii_api::prepare_id_alias(&env, ii_id, principal_1(), prepare_id_alias_req)?
+            .expect("prepare id_alias failed");
+
+    let canister_sig_pk =
+        CanisterSigPublicKey::try_from(prepared_id_alias.canister_sig_pk_der.as_ref())
+            .expect("failed parsing canister sig pk");
+
+    let get_id_alias_req = GetIdAliasRequest {
+        identity_number,
+        relying_party,
+        issuer,
+        rp_id_alias_jwt: prepared_id_alias.rp_id_alias_jwt,
+        issuer_id_alias_jwt: prepared_id_alias.issuer_id_alias_jwt,
+    };
+    let id_alias_credentials = ii_api::get_id_alias(&env, ii_id, principal_1(), get_id_alias_req)?
+        .expect("get id_alias failed");
+
+    let root_pk_raw =
+        extract_raw_root_pk_from_der(&env.root_key()).expect("Failed decoding IC root key.");
+    let alias_tuple = get_verified_id_alias_from_jws(
+        &id_alias_credentials
+            .issuer_id_alias_credential
+            .credential_jws,
+        &id_alias_credentials.issuer_id_alias_credential.id_dapp,
+        &canister_sig_pk.canister_id,
+        &root_pk_raw,
+        env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
+    )
+    .expect("Invalid ID alias");
+
+    let _ = api::add_credentials(
+        &env,
+        issuer_id,
+        alias_tuple.id_dapp,
+        vec![construct_adult_credential()],
+    )?;
+
+    let mut min_age_args = HashMap::new();
+    min_age_args.insert("minAge".to_string(), ArgumentValue::Int(18));
+    let min_age_spec = CredentialSpec {
+        credential_type: "VerifiedAdult".to_string(),
+        arguments: Some(min_age_args),
+    };
+    for credential_spec in [adult_credential_spec(), min_age_spec.clone()] {
+        let prepared_credential = api::prepare_credential(
+            &env,
+            issuer_id,
+            id_alias_credentials.issuer_id_alias_credential.id_dapp,
+            &PrepareCredentialRequest {
+                credential_spec: credential_spec.clone(),
+                signed_id_alias: SignedIssuerIdAlias {
+                    credential_jws: id_alias_credentials
+                        .issuer_id_alias_credential
+                        .credential_jws
+                        .clone(),
+                },
+            },
+        )?
+        .expect("failed to prepare credential");
+
+        let get_credential_response = api::get_credential(
+            &env,
+            issuer_id,
+            id_alias_credentials.issuer_id_alias_credential.id_dapp,
+            &GetCredentialRequest {
+                credential_spec: credential_spec.clone(),
+                signed_id_alias: SignedIssuerIdAlias {
+                    credential_jws: id_alias_credentials
+                        .issuer_id_alias_credential
                         .credential_jws
                         .clone(),
                 },
                 prepared_context: prepared_credential.prepared_context,
             },
         )?;
-        let claims = verify_credential_jws_with_canister_id(
-            &get_credential_response.unwrap().vc_jws,
-            &issuer_id,
-            &root_pk_raw,
-            env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos(),
-        )
-        .expect("credential verification failed");
+        let vc_jws = get_credential_response
+            .expect("failed to get credential")
+            .vc_jws;
+        let now = env.time().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let claims = verify_credential_jws_with_canister_id(&vc_jws, &issuer_id, &root_pk_raw, now)
+            .expect("credential verification failed");
         let vc_claims = claims.vc().expect("missing VC claims");
         println!("{:?}", vc_claims);
+        // Stored claims are only disclosed on request
+        assert!(!format!("{:?}", vc_claims).contains("Is over 18"));
+        validate_claims_match_spec(vc_claims, &credential_spec)
+            .expect("VC claims do not match the credential spec");
+        assert_eq!(
+            vc_claims["type"],
+            serde_json::json!(["VerifiableCredential", "VerifiedAdult"])
+        );
+        assert!(claims.jti().is_some_and(|jti| jti.starts_with("urn:uuid:")));
+        let now_s = (now / 1_000_000_000) as i64;
+        assert_eq!(claims.nbf(), Some(now_s));
+        assert_eq!(claims.iat(), Some(now_s));
+
+        // The relying party accepts the VC presented with the id alias VC of the relying party
+        let vp_jwt = create_verifiable_presentation_jwt_for_test(
+            id_alias_credentials.rp_id_alias_credential.id_dapp,
+            vec![
+                id_alias_credentials
+                    .rp_id_alias_credential
+                    .credential_jws
+                    .clone(),
+                vc_jws,
+            ],
+        )
+        .expect("failed creating presentation");
+        validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_alias_credentials.rp_id_alias_credential.id_dapp,
+            &VcFlowSigners {
+                ii_canister_id: ii_id,
+                ii_origin: II_ISSUER_URL.to_string(),
+                issuer_canister_id: issuer_id,
+                issuer_origin: DUMMY_DERIVATION_ORIGIN.to_string(),
+            },
+            &credential_spec,
+            &root_pk_raw,
+            now,
+        )
+        .expect("the relying party rejected the presentation");
     }
 
+    // The prepared context cannot be used for another spec or after it was tampered with
+    let signed_id_alias = SignedIssuerIdAlias {
+        credential_jws: id_alias_credentials
+            .issuer_id_alias_credential
+            .credential_jws
+            .clone(),
+    };
+    let prepared_credential = api::prepare_credential(
+        &env,
+        issuer_id,
+        id_alias_credentials.issuer_id_alias_credential.id_dapp,
+        &PrepareCredentialRequest {
+            credential_spec: min_age_spec.clone(),
+            signed_id_alias: signed_id_alias.clone(),
+        },
+    )?
+    .expect("failed to prepare credential");
+    let get_credential_response = api::get_credential(
+        &env,
+        issuer_id,
+        id_alias_credentials.issuer_id_alias_credential.id_dapp,
+        &GetCredentialRequest {
+            credential_spec: adult_credential_spec(),
+            signed_id_alias: signed_id_alias.clone(),
+            prepared_context: prepared_credential.prepared_context.clone(),
+        },
+    )?;
+    assert_matches!(
+        get_credential_response,
+        Err(IssueCredentialError::UnsupportedCredentialSpec(e)) if e.contains("does not match")
+    );
+
+    // Change a character of the JWT inside the context
+    let mut tampered_context = prepared_credential.prepared_context.unwrap().into_vec();
+    let jwt_start = tampered_context
+        .windows(3)
+        .position(|w| w == b"eyJ")
+        .expect("missing JWT in prepared context");
+    tampered_context[jwt_start + 3] ^= 1;
+    let get_credential_response = api::get_credential(
+        &env,
+        issuer_id,
+        id_alias_credentials.issuer_id_alias_credential.id_dapp,
+        &GetCredentialRequest {
+            credential_spec: min_age_spec,
+            signed_id_alias,
+            prepared_context: Some(ByteBuf::from(tampered_context)),
+        },
+    )?;
+    assert_matches!(
+        get_credential_response,
+        Err(IssueCredentialError::Internal(e)) if e.contains("could not be authenticated")
+    );
+
     Ok(())
 }
 